@@ -2,6 +2,8 @@ use serde::Deserialize;
 use std::{env, fmt::Debug};
 use tracing_subscriber::fmt::format::FmtSpan;
 
+pub mod protocol;
+
 pub fn enable_tracing() {
     let subscriber = tracing_subscriber::fmt::Subscriber::builder()
         .with_max_level(tracing::Level::DEBUG)
@@ -36,16 +38,92 @@ pub struct ContentRepositoryConfig {
     pub base_path: String,
     pub file_name_prefix: String,
     pub server_port: u16,
+    /// Active segment is rotated once appending the next record would make it larger than this.
+    pub max_segment_bytes: u64,
+    /// Active segment is rotated once it has been open for longer than this.
+    pub max_segment_age_secs: u64,
+    #[serde(default)]
+    pub durability: DurabilityPolicy,
+    /// Codec new records are compressed with. Existing records keep the codec they were written with.
+    #[serde(default)]
+    pub compression: Compression,
+    /// Records are stored in plaintext unless this is set.
+    pub encryption: Option<EncryptionConfig>,
+}
+
+/// Encryption of stored content with AES-256-GCM.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct EncryptionConfig {
+    /// File with one `{key id} {hex encoded 256 bit key}` line per key. To rotate keys, add a
+    /// new key and make it the active one. Older keys have to stay in the file for as long as
+    /// records encrypted with them are still needed.
+    pub key_file: String,
+    /// Id of the key new records are encrypted with.
+    pub active_key_id: u32,
+}
+
+/// Compression applied to the content of each record before it is stored.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+    Lz4,
+    Gzip,
+}
+
+/// When content written to the content repository is synced to disk,
+/// and therefore when the write is acknowledged to the client.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum DurabilityPolicy {
+    /// Writes are acknowledged as soon as they are handed over to the OS, and are synced
+    /// whenever the OS decides to. Acknowledged writes can be lost on power failure.
+    None,
+    /// Every write is synced before it is acknowledged.
+    #[default]
+    PerRecord,
+    /// Writes are synced in groups, once `max_batch` writes are pending or `interval_ms`
+    /// has passed, and are acknowledged after their group is synced.
+    GroupCommit { interval_ms: u64, max_batch: usize },
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct MainConfig {
+    /// Object threshold of connections which do not set one.
     pub processor_queue_length: usize,
     pub server_port: u16,
+    /// Packets with more data than this are stored in the content repository and passed
+    /// between processors by reference. Packets are always kept in memory when not set.
+    pub content_offload_threshold_bytes: Option<usize>,
+    /// Processors which panic are started again after this many milliseconds, doubling with
+    /// every further panic. Panicked processors stay `Errored` when not set.
+    pub processor_restart_backoff_ms: Option<u64>,
+    /// File which provenance events are appended to. Events are only kept in memory when not
+    /// set.
+    pub provenance_log_path: Option<String>,
+}
+
+/// How other modules connect to the content repository.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ContentRepositoryClientConfig {
+    /// `host:port` the content repository listens on.
+    pub address: String,
+    /// Connections opened at most at the same time. Idle connections are kept for reuse.
+    pub max_connections: usize,
+    pub connect_timeout_ms: u64,
+    /// Time a single attempt of a request may take, from sending it to receiving the response.
+    pub request_timeout_ms: u64,
+    /// Times a request is retried after failing because of its connection.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every further retry.
+    pub retry_backoff_ms: u64,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub content_repository: ContentRepositoryConfig,
+    pub content_repository_client: ContentRepositoryClientConfig,
     pub rusk_main: MainConfig,
 }
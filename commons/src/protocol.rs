@@ -0,0 +1,333 @@
+use std::{fmt, io};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Wire format of a single frame exchanged between the content repository and its clients:
+///
+/// ```text
+/// +---------+--------+---------------------+-----------------+
+/// | version | opcode | payload length (BE) | payload         |
+/// | 1 byte  | 1 byte | 8 bytes             | "length" bytes  |
+/// +---------+--------+---------------------+-----------------+
+/// ```
+///
+/// A connection carries any number of requests, each of which is answered by one or more
+/// response frames before the next request is read. Content of any size is streamed in
+/// `Content` frames up to a `ContentEnd`, both when it is written and when it is read.
+pub const PROTOCOL_VERSION: u8 = 1;
+pub const HEADER_LENGTH: usize = 10;
+/// Largest payload a single frame may carry. Frames announcing more are rejected before their
+/// payload is read, so that a peer cannot make us buffer an unbounded amount of data. Larger
+/// content is split into several `Content` frames.
+pub const MAX_PAYLOAD_LENGTH: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+    /// Request: append the payload to the content repository. With an empty payload, the
+    /// content follows in `Content` frames, terminated by a `ContentEnd`.
+    Write = 0x01,
+    /// Request: payload is an encoded `ContentReference` of the content to read.
+    Read = 0x02,
+    /// Request: payload is an encoded `ContentReference` of the content one more FlowFile uses.
+    Claim = 0x03,
+    /// Request: payload is an encoded `ContentReference` of the content one FlowFile stopped using.
+    Release = 0x04,
+    /// Response: payload is an encoded `ContentReference` of the content which was stored.
+    Reference = 0x81,
+    /// Payload is the next chunk of the content requested by a `Read`, or of the content of
+    /// a `Write`.
+    Content = 0x82,
+    /// Marks the end of the content requested by a `Read`, or of the content of a `Write`.
+    /// Payload is empty.
+    ContentEnd = 0x83,
+    /// Response: payload is the big-endian u64 number of claims left on the content after a
+    /// `Claim` or `Release`.
+    ClaimCount = 0x84,
+    /// Response: payload is a UTF-8 error message.
+    Error = 0xFF,
+}
+
+impl TryFrom<u8> for OpCode {
+    type Error = ProtocolError;
+
+    fn try_from(value: u8) -> Result<Self, ProtocolError> {
+        match value {
+            0x01 => Ok(OpCode::Write),
+            0x02 => Ok(OpCode::Read),
+            0x03 => Ok(OpCode::Claim),
+            0x04 => Ok(OpCode::Release),
+            0x81 => Ok(OpCode::Reference),
+            0x82 => Ok(OpCode::Content),
+            0x83 => Ok(OpCode::ContentEnd),
+            0x84 => Ok(OpCode::ClaimCount),
+            0xFF => Ok(OpCode::Error),
+            other => Err(ProtocolError::UnknownOpCode(other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub opcode: OpCode,
+    pub payload: Bytes,
+}
+
+impl Frame {
+    pub fn new(opcode: OpCode, payload: Bytes) -> Self {
+        Frame { opcode, payload }
+    }
+
+    pub fn reference(reference: &ContentReference) -> Self {
+        Frame::new(OpCode::Reference, reference.encode())
+    }
+
+    pub fn claim_count(claim_count: u64) -> Self {
+        Frame::new(
+            OpCode::ClaimCount,
+            Bytes::copy_from_slice(&claim_count.to_be_bytes()),
+        )
+    }
+
+    pub fn error(message: &str) -> Self {
+        Frame::new(OpCode::Error, Bytes::copy_from_slice(message.as_bytes()))
+    }
+}
+
+/// Location of a single record in the content repository. Used both as the response to a
+/// `Write` and as the payload of requests about existing content, encoded as
+/// `segment name length (u16, BE) | segment name (UTF-8) | offset (u64, BE) | length (u64, BE)`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ContentReference {
+    pub segment: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl ContentReference {
+    pub fn encode(&self) -> Bytes {
+        let mut payload = BytesMut::with_capacity(2 + self.segment.len() + 16);
+        payload.put_u16(self.segment.len() as u16);
+        payload.put_slice(self.segment.as_bytes());
+        payload.put_u64(self.offset);
+        payload.put_u64(self.length);
+        payload.freeze()
+    }
+
+    pub fn decode(opcode: OpCode, mut payload: Bytes) -> Result<Self, ProtocolError> {
+        if payload.remaining() < 2 {
+            return Err(ProtocolError::MalformedPayload(opcode));
+        }
+        let segment_length = payload.get_u16() as usize;
+        if payload.remaining() != segment_length + 16 {
+            return Err(ProtocolError::MalformedPayload(opcode));
+        }
+        let segment = String::from_utf8(payload.split_to(segment_length).to_vec())
+            .map_err(|_| ProtocolError::MalformedPayload(opcode))?;
+        Ok(ContentReference {
+            segment,
+            offset: payload.get_u64(),
+            length: payload.get_u64(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    Io(io::Error),
+    UnsupportedVersion(u8),
+    UnknownOpCode(u8),
+    MalformedPayload(OpCode),
+    /// Payload length announced by a frame which is larger than `MAX_PAYLOAD_LENGTH`.
+    FrameTooLarge(u64),
+    /// Frame which cannot follow the frames before it, e.g. a request in the middle of the
+    /// content of a `Write`.
+    UnexpectedFrame(OpCode),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Io(e) => write!(f, "I/O error: {}", e),
+            ProtocolError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported protocol version: {}", version)
+            }
+            ProtocolError::UnknownOpCode(opcode) => write!(f, "Unknown opcode: {:#04x}", opcode),
+            ProtocolError::MalformedPayload(opcode) => {
+                write!(f, "Malformed payload for opcode {:?}", opcode)
+            }
+            ProtocolError::FrameTooLarge(payload_length) => write!(
+                f,
+                "Frame payload of {} bytes is larger than the maximum of {} bytes",
+                payload_length, MAX_PAYLOAD_LENGTH
+            ),
+            ProtocolError::UnexpectedFrame(opcode) => write!(f, "Unexpected {:?} frame", opcode),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<io::Error> for ProtocolError {
+    fn from(e: io::Error) -> Self {
+        ProtocolError::Io(e)
+    }
+}
+
+/// Reads the next frame from the reader.
+/// Returns `Ok(None)` when the peer closed the connection cleanly between two frames.
+pub async fn read_frame<Reader>(reader: &mut Reader) -> Result<Option<Frame>, ProtocolError>
+where
+    Reader: AsyncRead + Unpin,
+{
+    let version = match reader.read_u8().await {
+        Ok(version) => version,
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if version != PROTOCOL_VERSION {
+        return Err(ProtocolError::UnsupportedVersion(version));
+    }
+    let opcode = OpCode::try_from(reader.read_u8().await?)?;
+    let payload_length = reader.read_u64().await?;
+    if payload_length > MAX_PAYLOAD_LENGTH {
+        return Err(ProtocolError::FrameTooLarge(payload_length));
+    }
+
+    // Payload is read incrementally instead of allocating `payload_length` bytes up front
+    // so that a bogus length cannot make us allocate memory for data which never arrives.
+    let mut payload = BytesMut::new();
+    let mut limited_reader = (&mut *reader).take(payload_length);
+    while (payload.len() as u64) < payload_length {
+        if limited_reader.read_buf(&mut payload).await? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "Connection closed after {} of {} payload bytes",
+                    payload.len(),
+                    payload_length
+                ),
+            )
+            .into());
+        }
+    }
+    Ok(Some(Frame::new(opcode, payload.freeze())))
+}
+
+pub async fn write_frame<Writer>(writer: &mut Writer, frame: &Frame) -> io::Result<()>
+where
+    Writer: AsyncWrite + Unpin,
+{
+    let mut header = [0u8; HEADER_LENGTH];
+    header[0] = PROTOCOL_VERSION;
+    header[1] = frame.opcode as u8;
+    header[2..].copy_from_slice(&(frame.payload.len() as u64).to_be_bytes());
+    writer.write_all(&header).await?;
+    writer.write_all(&frame.payload).await?;
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_and_read_multiple_frames() {
+        let large_payload = Bytes::from(vec![7u8; 1024 * 1024]);
+        let frames = vec![
+            Frame::new(OpCode::Write, Bytes::from_static(b"line 1\nline 2\n")),
+            Frame::new(OpCode::Write, large_payload),
+            Frame::reference(&ContentReference {
+                segment: "wal-000001.seg".to_string(),
+                offset: 42,
+                length: 7,
+            }),
+        ];
+
+        let mut buffer: Vec<u8> = vec![];
+        for frame in &frames {
+            write_frame(&mut buffer, frame).await.unwrap();
+        }
+
+        let mut reader = buffer.as_slice();
+        for expected_frame in frames {
+            let actual_frame = read_frame(&mut reader).await.unwrap().unwrap();
+            assert_eq!(actual_frame, expected_frame);
+        }
+        assert!(read_frame(&mut reader).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_content_reference_encode_and_decode() {
+        let reference = ContentReference {
+            segment: "wal-000001.seg".to_string(),
+            offset: 1024,
+            length: 42,
+        };
+        assert_eq!(
+            ContentReference::decode(OpCode::Read, reference.encode()).unwrap(),
+            reference
+        );
+
+        let truncated_payload = reference.encode().slice(..10);
+        assert!(matches!(
+            ContentReference::decode(OpCode::Read, truncated_payload),
+            Err(ProtocolError::MalformedPayload(OpCode::Read))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_with_unsupported_version() {
+        let mut reader: &[u8] = &[
+            PROTOCOL_VERSION + 1,
+            OpCode::Write as u8,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+        match read_frame(&mut reader).await {
+            Err(ProtocolError::UnsupportedVersion(version)) => {
+                assert_eq!(version, PROTOCOL_VERSION + 1)
+            }
+            other => panic!("Expected UnsupportedVersion error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_with_truncated_payload() {
+        let mut buffer: Vec<u8> = vec![];
+        write_frame(
+            &mut buffer,
+            &Frame::new(OpCode::Write, Bytes::from_static(b"test data")),
+        )
+        .await
+        .unwrap();
+        buffer.truncate(buffer.len() - 3);
+
+        let mut reader = buffer.as_slice();
+        match read_frame(&mut reader).await {
+            Err(ProtocolError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            other => panic!("Expected UnexpectedEof error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_larger_than_maximum() {
+        let mut header = vec![PROTOCOL_VERSION, OpCode::Write as u8];
+        header.extend_from_slice(&(MAX_PAYLOAD_LENGTH + 1).to_be_bytes());
+
+        let mut reader = header.as_slice();
+        match read_frame(&mut reader).await {
+            Err(ProtocolError::FrameTooLarge(payload_length)) => {
+                assert_eq!(payload_length, MAX_PAYLOAD_LENGTH + 1)
+            }
+            other => panic!("Expected FrameTooLarge error, got {:?}", other),
+        }
+    }
+}
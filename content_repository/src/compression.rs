@@ -0,0 +1,122 @@
+use std::{
+    borrow::Cow,
+    io::{self, Read, Write},
+};
+
+use commons::Compression;
+use flate2::{read::GzDecoder, write::GzEncoder};
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Codec the payload of a record was compressed with, as stored in the record header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Codec {
+    None = 0,
+    Zstd = 1,
+    Lz4 = 2,
+    Gzip = 3,
+}
+
+impl TryFrom<u8> for Codec {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, u8> {
+        match value {
+            0 => Ok(Codec::None),
+            1 => Ok(Codec::Zstd),
+            2 => Ok(Codec::Lz4),
+            3 => Ok(Codec::Gzip),
+            other => Err(other),
+        }
+    }
+}
+
+impl From<Compression> for Codec {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::None => Codec::None,
+            Compression::Zstd => Codec::Zstd,
+            Compression::Lz4 => Codec::Lz4,
+            Compression::Gzip => Codec::Gzip,
+        }
+    }
+}
+
+/// Compresses `content` with the given codec, unless that would not make it any smaller.
+/// Returns the codec which was actually used along with the compressed content.
+pub fn compress(codec: Codec, content: &[u8]) -> io::Result<(Codec, Cow<'_, [u8]>)> {
+    let compressed = match codec {
+        Codec::None => return Ok((Codec::None, Cow::Borrowed(content))),
+        Codec::Zstd => zstd::bulk::compress(content, ZSTD_LEVEL)?,
+        Codec::Lz4 => lz4_flex::compress(content),
+        Codec::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(content)?;
+            encoder.finish()?
+        }
+    };
+    if compressed.len() >= content.len() {
+        return Ok((Codec::None, Cow::Borrowed(content)));
+    }
+    Ok((codec, Cow::Owned(compressed)))
+}
+
+/// Decompresses a payload back into the `content_length` bytes it was compressed from.
+pub fn decompress(codec: Codec, payload: &[u8], content_length: usize) -> io::Result<Vec<u8>> {
+    let content = match codec {
+        Codec::None => payload.to_vec(),
+        Codec::Zstd => zstd::bulk::decompress(payload, content_length)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        Codec::Lz4 => lz4_flex::decompress(payload, content_length)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        Codec::Gzip => {
+            let mut content = Vec::with_capacity(content_length);
+            GzDecoder::new(payload)
+                .take(content_length as u64 + 1)
+                .read_to_end(&mut content)?;
+            content
+        }
+    };
+    if content.len() != content_length {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{:?} payload decompressed to {} bytes instead of {}",
+                codec,
+                content.len(),
+                content_length
+            ),
+        ));
+    }
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress_and_decompress() {
+        let content = b"compressible content ".repeat(100);
+        for codec in [Codec::Zstd, Codec::Lz4, Codec::Gzip] {
+            let (actual_codec, payload) = compress(codec, &content).unwrap();
+            assert_eq!(actual_codec, codec);
+            assert!(payload.len() < content.len());
+            assert_eq!(decompress(codec, &payload, content.len()).unwrap(), content);
+
+            let error = decompress(codec, &payload, content.len() - 1).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_incompressible_content_is_stored_as_is() {
+        let content = b"tiny";
+        for codec in [Codec::None, Codec::Zstd, Codec::Lz4, Codec::Gzip] {
+            let (actual_codec, payload) = compress(codec, content).unwrap();
+            assert_eq!(actual_codec, Codec::None);
+            assert_eq!(&payload[..], content);
+        }
+    }
+}
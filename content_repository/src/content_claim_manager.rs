@@ -0,0 +1,351 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+};
+
+use commons::protocol::ContentReference;
+
+const CLAIM_JOURNAL_FILE_NAME: &str = "claims.journal";
+/// Journal is compacted once it has at least this many lines, and more than twice as many
+/// as there are claimed records.
+const COMPACTION_MIN_LINES: usize = 10_000;
+
+/// Keeps track of how many live FlowFiles reference each record of the content repository.
+///
+/// Every change is appended to a journal in the content repository directory as a
+/// `{segment} {offset} {length} {delta}` line, so that claims survive restarts.
+/// The journal is compacted to one line per claimed record whenever it is loaded, and once it
+/// has grown to many more lines than there are claimed records.
+pub struct ContentClaimManager {
+    journal_path: PathBuf,
+    journal: File,
+    /// Lines in the journal, one per claimed record right after it was compacted.
+    journal_lines: usize,
+    record_claims: HashMap<ContentReference, u64>,
+    segment_claims: HashMap<String, u64>,
+}
+
+impl ContentClaimManager {
+    pub async fn init(base_path: &str) -> Self {
+        let journal_path = Path::new(base_path).join(CLAIM_JOURNAL_FILE_NAME);
+        let mut claim_manager = ContentClaimManager {
+            journal: open_journal(&journal_path).await,
+            journal_path: journal_path.clone(),
+            journal_lines: 0,
+            record_claims: HashMap::new(),
+            segment_claims: HashMap::new(),
+        };
+        let journal_contents = std::fs::read_to_string(&journal_path)
+            .unwrap_or_else(|e| panic!("Failed to read claim journal {:?}: {}", journal_path, e));
+        for line in journal_contents.lines() {
+            match parse_journal_line(line) {
+                Some((reference, delta)) => {
+                    claim_manager.apply(reference, delta);
+                }
+                // Only the last line can be partially written, when we crashed while appending it.
+                None => tracing::warn!("Ignoring malformed claim journal line: {:?}", line),
+            }
+        }
+        claim_manager.compact_journal().await.unwrap_or_else(|e| {
+            panic!("Failed to compact claim journal {:?}: {}", journal_path, e)
+        });
+        tracing::info!(
+            "Loaded claims on {} records in {} segments",
+            claim_manager.record_claims.len(),
+            claim_manager.segment_claims.len()
+        );
+        claim_manager
+    }
+
+    /// Registers a newly written record, claimed once by the FlowFile which wrote it.
+    pub async fn add_record(&mut self, reference: &ContentReference) -> io::Result<u64> {
+        self.update(reference, 1).await
+    }
+
+    pub async fn claim(&mut self, reference: &ContentReference) -> io::Result<u64> {
+        if !self.record_claims.contains_key(reference) {
+            return Err(unknown_content_error(reference));
+        }
+        self.update(reference, 1).await
+    }
+
+    /// Releases one claim on the record and returns how many claims are left.
+    /// Once a record has no claims left it can no longer be claimed.
+    pub async fn release(&mut self, reference: &ContentReference) -> io::Result<u64> {
+        if !self.record_claims.contains_key(reference) {
+            return Err(unknown_content_error(reference));
+        }
+        self.update(reference, -1).await
+    }
+
+    /// Drops all claims on records for which `is_known_record` returns false, for example
+    /// because they were truncated after a crash. Returns the number of records dropped.
+    pub async fn retain_records(
+        &mut self,
+        is_known_record: impl Fn(&ContentReference) -> bool,
+    ) -> io::Result<usize> {
+        let unknown_records: Vec<(ContentReference, u64)> = self
+            .record_claims
+            .iter()
+            .filter(|(reference, _)| !is_known_record(reference))
+            .map(|(reference, claims)| (reference.clone(), *claims))
+            .collect();
+        for (reference, claims) in &unknown_records {
+            tracing::warn!(
+                "Dropping {} claims on missing record {:?}",
+                claims,
+                reference
+            );
+            self.update(reference, -(*claims as i64)).await?;
+        }
+        Ok(unknown_records.len())
+    }
+
+    pub async fn sync(&mut self) -> io::Result<()> {
+        self.journal.sync_data().await
+    }
+
+    pub fn is_segment_claimed(&self, segment: &str) -> bool {
+        self.segment_claims.contains_key(segment)
+    }
+
+    async fn update(&mut self, reference: &ContentReference, delta: i64) -> io::Result<u64> {
+        let line = format!(
+            "{} {} {} {:+}\n",
+            reference.segment, reference.offset, reference.length, delta
+        );
+        self.journal.write_all(line.as_bytes()).await?;
+        self.journal.flush().await?;
+        let claim_count = self.apply(reference.clone(), delta);
+        self.journal_lines += 1;
+        if self.journal_lines >= COMPACTION_MIN_LINES
+            && self.journal_lines > 2 * self.record_claims.len()
+        {
+            // Journal is still complete when it cannot be compacted, only longer.
+            if let Err(e) = self.compact_journal().await {
+                tracing::error!("Failed to compact claim journal: {}", e);
+            }
+        }
+        Ok(claim_count)
+    }
+
+    fn apply(&mut self, reference: ContentReference, delta: i64) -> u64 {
+        let segment_claims = self
+            .segment_claims
+            .entry(reference.segment.clone())
+            .or_insert(0);
+        *segment_claims = segment_claims.saturating_add_signed(delta);
+        if *segment_claims == 0 {
+            self.segment_claims.remove(&reference.segment);
+        }
+
+        let record_claims = self.record_claims.entry(reference.clone()).or_insert(0);
+        *record_claims = record_claims.saturating_add_signed(delta);
+        let claim_count = *record_claims;
+        if claim_count == 0 {
+            self.record_claims.remove(&reference);
+        }
+        claim_count
+    }
+
+    /// Replaces the journal with one line per claimed record. The compacted journal is synced
+    /// before it replaces the journal, and the rename is synced after, so that a crash leaves
+    /// either the old or the new journal in place.
+    async fn compact_journal(&mut self) -> io::Result<()> {
+        let compacted_journal_path = self.journal_path.with_extension("journal.tmp");
+        let compacted_journal: String = self
+            .record_claims
+            .iter()
+            .map(|(reference, claims)| {
+                format!(
+                    "{} {} {} {:+}\n",
+                    reference.segment, reference.offset, reference.length, claims
+                )
+            })
+            .collect();
+        let mut compacted_journal_file = std::fs::File::create(&compacted_journal_path)?;
+        compacted_journal_file.write_all(compacted_journal.as_bytes())?;
+        compacted_journal_file.sync_all()?;
+        std::fs::rename(&compacted_journal_path, &self.journal_path)?;
+        if let Some(journal_dir) = self.journal_path.parent() {
+            std::fs::File::open(journal_dir)?.sync_all()?;
+        }
+        tracing::debug!(
+            "Compacted claim journal from {} to {} lines",
+            self.journal_lines,
+            self.record_claims.len()
+        );
+        self.journal = open_journal(&self.journal_path).await;
+        self.journal_lines = self.record_claims.len();
+        Ok(())
+    }
+}
+
+async fn open_journal(journal_path: &Path) -> File {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(journal_path)
+        .await
+        .unwrap_or_else(|e| panic!("Failed to open claim journal {:?}: {}", journal_path, e))
+}
+
+/// Parses a `{segment} {offset} {length} {delta}` journal line. Fields are split from the
+/// right, since the segment name starts with the configurable file name prefix, which may
+/// contain spaces.
+fn parse_journal_line(line: &str) -> Option<(ContentReference, i64)> {
+    let mut fields = line.rsplitn(4, ' ');
+    let delta = fields.next()?.parse().ok()?;
+    let length = fields.next()?.parse().ok()?;
+    let offset = fields.next()?.parse().ok()?;
+    let reference = ContentReference {
+        segment: fields.next()?.to_string(),
+        offset,
+        length,
+    };
+    Some((reference, delta))
+}
+
+fn unknown_content_error(reference: &ContentReference) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("No claimed content at {:?}", reference),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn reference(segment: &str, offset: u64) -> ContentReference {
+        ContentReference {
+            segment: segment.to_string(),
+            offset,
+            length: 10,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_claim_and_release() {
+        let temp_dir = tempdir().unwrap();
+        let mut claim_manager = ContentClaimManager::init(temp_dir.path().to_str().unwrap()).await;
+        let first_record = reference("wal-000001.seg", 0);
+        let second_record = reference("wal-000001.seg", 51);
+
+        assert_eq!(claim_manager.add_record(&first_record).await.unwrap(), 1);
+        assert_eq!(claim_manager.add_record(&second_record).await.unwrap(), 1);
+        assert_eq!(claim_manager.claim(&first_record).await.unwrap(), 2);
+
+        assert_eq!(claim_manager.release(&first_record).await.unwrap(), 1);
+        assert_eq!(claim_manager.release(&second_record).await.unwrap(), 0);
+        assert!(claim_manager.is_segment_claimed("wal-000001.seg"));
+
+        assert_eq!(claim_manager.release(&first_record).await.unwrap(), 0);
+        assert!(!claim_manager.is_segment_claimed("wal-000001.seg"));
+
+        let error = claim_manager.claim(&first_record).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        let error = claim_manager.release(&second_record).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_claims_survive_restart() {
+        let temp_dir = tempdir().unwrap();
+        let base_path = temp_dir.path().to_str().unwrap();
+        let first_record = reference("wal-000001.seg", 0);
+        let second_record = reference("wal-000002.seg", 0);
+
+        let mut claim_manager = ContentClaimManager::init(base_path).await;
+        claim_manager.add_record(&first_record).await.unwrap();
+        claim_manager.claim(&first_record).await.unwrap();
+        claim_manager.add_record(&second_record).await.unwrap();
+        claim_manager.release(&second_record).await.unwrap();
+        drop(claim_manager);
+
+        // Simulate a crash in the middle of appending a journal line.
+        let journal_path = temp_dir.path().join(CLAIM_JOURNAL_FILE_NAME);
+        let mut journal = std::fs::read_to_string(&journal_path).unwrap();
+        journal.push_str("wal-000001.seg 0");
+        std::fs::write(&journal_path, journal).unwrap();
+
+        let mut claim_manager = ContentClaimManager::init(base_path).await;
+        assert!(claim_manager.is_segment_claimed("wal-000001.seg"));
+        assert!(!claim_manager.is_segment_claimed("wal-000002.seg"));
+        assert_eq!(claim_manager.release(&first_record).await.unwrap(), 1);
+
+        let compacted_journal = std::fs::read_to_string(&journal_path).unwrap();
+        assert_eq!(
+            compacted_journal,
+            "wal-000001.seg 0 10 +2\nwal-000001.seg 0 10 -1\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_segment_names_with_spaces_survive_restart() {
+        let temp_dir = tempdir().unwrap();
+        let base_path = temp_dir.path().to_str().unwrap();
+        let record = reference("my wal 000001.seg", 0);
+
+        let mut claim_manager = ContentClaimManager::init(base_path).await;
+        claim_manager.add_record(&record).await.unwrap();
+        claim_manager.claim(&record).await.unwrap();
+        drop(claim_manager);
+
+        let mut claim_manager = ContentClaimManager::init(base_path).await;
+        assert!(claim_manager.is_segment_claimed("my wal 000001.seg"));
+        assert_eq!(claim_manager.release(&record).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retain_records() {
+        let temp_dir = tempdir().unwrap();
+        let mut claim_manager = ContentClaimManager::init(temp_dir.path().to_str().unwrap()).await;
+        let first_record = reference("wal-000001.seg", 0);
+        let second_record = reference("wal-000001.seg", 51);
+        claim_manager.add_record(&first_record).await.unwrap();
+        claim_manager.add_record(&second_record).await.unwrap();
+        claim_manager.claim(&second_record).await.unwrap();
+
+        let dropped_records = claim_manager
+            .retain_records(|reference| reference.offset == 0)
+            .await
+            .unwrap();
+        assert_eq!(dropped_records, 1);
+        assert!(claim_manager.claim(&second_record).await.is_err());
+        assert_eq!(claim_manager.release(&first_record).await.unwrap(), 0);
+        assert!(!claim_manager.is_segment_claimed("wal-000001.seg"));
+    }
+
+    #[tokio::test]
+    async fn test_journal_is_compacted_while_running() {
+        let temp_dir = tempdir().unwrap();
+        let base_path = temp_dir.path().to_str().unwrap();
+        let mut claim_manager = ContentClaimManager::init(base_path).await;
+        let first_record = reference("wal-000001.seg", 0);
+        claim_manager.add_record(&first_record).await.unwrap();
+        for _ in 0..COMPACTION_MIN_LINES {
+            claim_manager.claim(&first_record).await.unwrap();
+            claim_manager.release(&first_record).await.unwrap();
+        }
+
+        let journal_path = temp_dir.path().join(CLAIM_JOURNAL_FILE_NAME);
+        let journal_lines = std::fs::read_to_string(&journal_path)
+            .unwrap()
+            .lines()
+            .count();
+        assert!(journal_lines < COMPACTION_MIN_LINES);
+        drop(claim_manager);
+
+        let mut claim_manager = ContentClaimManager::init(base_path).await;
+        assert_eq!(claim_manager.release(&first_record).await.unwrap(), 0);
+    }
+}
@@ -1,75 +1,939 @@
-use commons::ContentRepositoryConfig;
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytes::{Bytes, BytesMut};
+use commons::{protocol::ContentReference, ContentRepositoryConfig};
+
+use crate::{
+    compression::{self, Codec},
+    content_claim_manager::ContentClaimManager,
+    encryption::{KeyRing, NONCE_LENGTH},
+    record::{RecordHeader, RECORD_HEADER_LENGTH},
+    recovery::{self, RecoveryReport},
+};
 use tokio::{
     fs::{File, OpenOptions},
-    io::AsyncWriteExt,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::mpsc,
 };
 
-pub async fn init(config: ContentRepositoryConfig) -> File {
-    tracing::info!("Initializing content repository");
-    std::fs::create_dir_all(&config.base_path).unwrap_or_else(|_| {
-        panic!(
-            "Failed to create content repository directory: {}",
-            config.base_path
-        )
-    });
-    tracing::info!("Content repository directory created: {}", config.base_path);
-    let file_path = format!("{}/{}.txt", config.base_path, config.file_name_prefix);
-    OpenOptions::new()
+pub const READ_CHUNK_SIZE: usize = 64 * 1024;
+const SEGMENT_FILE_EXTENSION: &str = "seg";
+
+/// Segment files are named `{file_name_prefix}-{sequence}.seg` with a zero padded sequence,
+/// so that sorting segment names also sorts them in the order they were written.
+pub fn segment_name(config: &ContentRepositoryConfig, sequence: u64) -> String {
+    format!(
+        "{}-{:06}.{}",
+        config.file_name_prefix, sequence, SEGMENT_FILE_EXTENSION
+    )
+}
+
+fn parse_segment_sequence(config: &ContentRepositoryConfig, file_name: &str) -> Option<u64> {
+    file_name
+        .strip_prefix(config.file_name_prefix.as_str())?
+        .strip_prefix('-')?
+        .strip_suffix(SEGMENT_FILE_EXTENSION)?
+        .strip_suffix('.')?
+        .parse()
+        .ok()
+}
+
+struct ActiveSegment {
+    sequence: u64,
+    name: String,
+    file_handle: File,
+    length: u64,
+    opened_at: Instant,
+}
+
+/// Content length of every record of a segment, by the offset at which the record starts.
+type SegmentIndex = BTreeMap<u64, u64>;
+
+pub struct ContentRepository {
+    config: ContentRepositoryConfig,
+    /// All segments which can be read from, including the active one.
+    segments: BTreeMap<String, SegmentIndex>,
+    active_segment: ActiveSegment,
+    claim_manager: ContentClaimManager,
+    /// Keys new records are encrypted with and existing ones are decrypted with,
+    /// if encryption is enabled.
+    key_ring: Option<Arc<KeyRing>>,
+    recovery_report: RecoveryReport,
+}
+
+impl ContentRepository {
+    /// Opens the content repository at `config.base_path`.
+    ///
+    /// Every existing segment is validated record by record to rebuild the index of records.
+    /// A record at the end of the most recent segment which was only partially written before
+    /// a crash is truncated, and appends continue in that segment.
+    /// Older segments which are no longer claimed are deleted.
+    pub async fn init(config: ContentRepositoryConfig) -> Self {
+        tracing::info!("Initializing content repository");
+        std::fs::create_dir_all(&config.base_path).unwrap_or_else(|_| {
+            panic!(
+                "Failed to create content repository directory: {}",
+                config.base_path
+            )
+        });
+        tracing::info!("Content repository directory created: {}", config.base_path);
+
+        let key_ring = config.encryption.as_ref().map(|encryption_config| {
+            let key_ring = KeyRing::load(encryption_config).unwrap_or_else(|e| {
+                panic!(
+                    "Failed to load encryption keys from {}: {}",
+                    encryption_config.key_file, e
+                )
+            });
+            tracing::info!(
+                "Encrypting content with key {}",
+                encryption_config.active_key_id
+            );
+            Arc::new(key_ring)
+        });
+
+        let mut existing_segments = BTreeMap::new();
+        for entry in std::fs::read_dir(&config.base_path).unwrap() {
+            let file_name = entry.unwrap().file_name().to_string_lossy().to_string();
+            if let Some(sequence) = parse_segment_sequence(&config, &file_name) {
+                existing_segments.insert(sequence, file_name);
+            }
+        }
+        tracing::info!("Found {} existing segments", existing_segments.len());
+
+        let mut recovery_report = RecoveryReport::default();
+        let mut segments = BTreeMap::new();
+        let last_sequence = existing_segments.keys().last().copied();
+        let mut active_sequence = last_sequence.unwrap_or(1);
+        for (sequence, segment) in existing_segments {
+            let segment_path = Path::new(&config.base_path).join(&segment);
+            let scan = recovery::scan_segment(&segment_path)
+                .unwrap_or_else(|e| panic!("Failed to scan segment {:?}: {}", segment_path, e));
+            recovery_report.segments_scanned += 1;
+            recovery_report.records_recovered += scan.records.len();
+            if let Some(e) = &scan.error {
+                // Only the end of the latest segment can be left behind by an interrupted write.
+                // Records after a corrupted one were acknowledged, so the segment is kept.
+                if scan.torn && Some(sequence) == last_sequence {
+                    tracing::warn!(
+                        "Truncating segment {} from {} to {} bytes: {}",
+                        segment,
+                        scan.file_length,
+                        scan.valid_length,
+                        e
+                    );
+                    truncate_segment(&segment_path, scan.valid_length);
+                    recovery_report.torn_bytes_truncated += scan.file_length - scan.valid_length;
+                } else {
+                    tracing::error!(
+                        "Segment {} is corrupted after offset {}: {}",
+                        segment,
+                        scan.valid_length,
+                        e
+                    );
+                    recovery_report.corrupted_segments.push(segment.clone());
+                    if Some(sequence) == last_sequence {
+                        // Records appended after the corrupted one could not be scanned again.
+                        active_sequence += 1;
+                    }
+                }
+            }
+            segments.insert(segment, scan.records);
+        }
+
+        let active_segment = open_segment(&config, active_sequence).await;
+        segments.entry(active_segment.name.clone()).or_default();
+
+        let mut claim_manager = ContentClaimManager::init(&config.base_path).await;
+        recovery_report.dropped_claims = claim_manager
+            .retain_records(|reference| {
+                segments
+                    .get(&reference.segment)
+                    .and_then(|records| records.get(&reference.offset))
+                    == Some(&reference.length)
+            })
+            .await
+            .unwrap();
+
+        let mut content_repository = ContentRepository {
+            config,
+            segments,
+            active_segment,
+            claim_manager,
+            key_ring,
+            recovery_report,
+        };
+        // Segments might have lost their last claim right before a crash, before they were deleted.
+        let existing_segments: Vec<String> = content_repository.segments.keys().cloned().collect();
+        for segment in existing_segments {
+            content_repository
+                .delete_segment_if_unclaimed(&segment)
+                .await;
+        }
+        content_repository
+    }
+
+    pub fn active_segment_name(&self) -> &str {
+        &self.active_segment.name
+    }
+
+    pub fn recovery_report(&self) -> &RecoveryReport {
+        &self.recovery_report
+    }
+
+    /// Keys which `read_data` needs to decrypt content, if encryption is enabled.
+    pub fn key_ring(&self) -> Option<Arc<KeyRing>> {
+        self.key_ring.clone()
+    }
+
+    /// Returns the path of the segment holding the referenced content,
+    /// provided the reference points at a record of this repository.
+    pub fn locate(&self, reference: &ContentReference) -> Option<PathBuf> {
+        let content_length = self
+            .segments
+            .get(&reference.segment)?
+            .get(&reference.offset)?;
+        (*content_length == reference.length)
+            .then(|| Path::new(&self.config.base_path).join(&reference.segment))
+    }
+
+    /// Appends a single record holding `data`, compressed with the configured codec and
+    /// encrypted with the active key if encryption is enabled, rotating the active segment first
+    /// if the record would not fit in it, and returns where the record was stored.
+    /// Length of the returned reference is the length of `data`.
+    pub async fn append_data(&mut self, data: &[u8]) -> ContentReference {
+        let (codec, compressed_data) =
+            compression::compress(Codec::from(self.config.compression), data)
+                .expect("Failed to compress content");
+        let (key_id, nonce, payload) = match &self.key_ring {
+            Some(key_ring) => {
+                let (key_id, nonce, ciphertext) = key_ring
+                    .encrypt(&compressed_data)
+                    .expect("Failed to encrypt content");
+                (Some(key_id), nonce, Cow::Owned(ciphertext))
+            }
+            None => (None, [0u8; NONCE_LENGTH], compressed_data),
+        };
+        let record_length = (RECORD_HEADER_LENGTH + payload.len()) as u64;
+        if self.active_segment.length > 0
+            && self.active_segment.length + record_length > self.config.max_segment_bytes
+        {
+            self.rotate().await;
+        }
+
+        let segment = &mut self.active_segment;
+        let record_start_offset = segment.length;
+        let header = RecordHeader::new(codec, key_id, nonce, data.len() as u64, &payload);
+        segment
+            .file_handle
+            .write_all(&header.encode())
+            .await
+            .unwrap();
+        segment.file_handle.write_all(&payload).await.unwrap();
+        segment.file_handle.flush().await.unwrap();
+        segment.length += record_length;
+        self.segments
+            .entry(segment.name.clone())
+            .or_default()
+            .insert(record_start_offset, data.len() as u64);
+
+        let reference = ContentReference {
+            segment: segment.name.clone(),
+            offset: record_start_offset,
+            length: data.len() as u64,
+        };
+        self.claim_manager.add_record(&reference).await.unwrap();
+        reference
+    }
+
+    /// Adds a claim on existing content and returns how many claims it has now.
+    pub async fn claim(&mut self, reference: &ContentReference) -> io::Result<u64> {
+        self.claim_manager.claim(reference).await
+    }
+
+    /// Releases a claim on existing content and returns how many claims it has left.
+    /// Segment holding the content is deleted once none of its records are claimed anymore,
+    /// unless it is still being appended to.
+    pub async fn release(&mut self, reference: &ContentReference) -> io::Result<u64> {
+        let claim_count = self.claim_manager.release(reference).await?;
+        if claim_count == 0 {
+            self.delete_segment_if_unclaimed(&reference.segment).await;
+        }
+        Ok(claim_count)
+    }
+
+    /// Rotates the active segment if it holds data and has been open for longer than
+    /// `max_segment_age_secs`, so that idle segments get closed as well.
+    pub async fn rotate_if_expired(&mut self) {
+        let max_segment_age = Duration::from_secs(self.config.max_segment_age_secs);
+        if self.active_segment.length > 0
+            && self.active_segment.opened_at.elapsed() >= max_segment_age
+        {
+            self.rotate().await;
+        }
+    }
+
+    /// Syncs all content and claims written so far to disk.
+    pub async fn sync(&mut self) {
+        self.active_segment
+            .file_handle
+            .sync_data()
+            .await
+            .expect("Failed to sync active segment");
+        self.claim_manager
+            .sync()
+            .await
+            .expect("Failed to sync claim journal");
+    }
+
+    async fn rotate(&mut self) {
+        // Writes to the previous segment which are not synced yet would be lost with its handle.
+        self.active_segment
+            .file_handle
+            .sync_data()
+            .await
+            .expect("Failed to sync active segment");
+        let next_segment = open_segment(&self.config, self.active_segment.sequence + 1).await;
+        tracing::info!(
+            "Rotating segment {} ({} bytes) to {}",
+            self.active_segment.name,
+            self.active_segment.length,
+            next_segment.name
+        );
+        self.segments.entry(next_segment.name.clone()).or_default();
+        let previous_segment = std::mem::replace(&mut self.active_segment, next_segment);
+        self.delete_segment_if_unclaimed(&previous_segment.name)
+            .await;
+    }
+
+    async fn delete_segment_if_unclaimed(&mut self, segment: &str) {
+        if segment == self.active_segment.name || self.claim_manager.is_segment_claimed(segment) {
+            return;
+        }
+        let segment_path = Path::new(&self.config.base_path).join(segment);
+        match tokio::fs::remove_file(&segment_path).await {
+            Ok(_) => {
+                tracing::info!(
+                    "Deleted segment {} as none of its content is claimed",
+                    segment
+                );
+                self.segments.remove(segment);
+            }
+            Err(e) => tracing::error!("Failed to delete segment {:?}: {}", segment_path, e),
+        }
+    }
+}
+
+fn truncate_segment(segment_path: &Path, length: u64) {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(segment_path)
+        .and_then(|file| {
+            file.set_len(length)?;
+            file.sync_all()
+        })
+        .unwrap_or_else(|e| panic!("Failed to truncate segment {:?}: {}", segment_path, e));
+}
+
+async fn open_segment(config: &ContentRepositoryConfig, sequence: u64) -> ActiveSegment {
+    let name = segment_name(config, sequence);
+    let file_path = Path::new(&config.base_path).join(&name);
+    let file_handle = OpenOptions::new()
         .create(true)
         .append(true)
-        .open(file_path)
+        .open(&file_path)
         .await
-        .unwrap()
+        .unwrap_or_else(|e| panic!("Failed to open segment {:?}: {}", file_path, e));
+    let length = file_handle.metadata().await.unwrap().len();
+    ActiveSegment {
+        sequence,
+        name,
+        file_handle,
+        length,
+        opened_at: Instant::now(),
+    }
 }
 
-pub async fn append_data(file_handle: &mut File, data: &[u8]) {
-    file_handle.write_all(data).await.unwrap();
-    file_handle.write_all("\n".as_bytes()).await.unwrap();
-    file_handle.flush().await.unwrap();
+/// Streams the content of the record of `length` bytes starting at `offset` of the segment file
+/// to `tx`, in chunks of at most `READ_CHUNK_SIZE` bytes.
+/// Requests which do not point at a record of the given length are rejected before anything
+/// is sent. Plain content is streamed as it is read and its checksum is verified along
+/// the way, so a corrupted record ends with an error after all of its chunks were sent.
+/// Compressed or encrypted content is read and verified as a whole, and decrypted with a key
+/// from `key_ring` and decompressed before it is sent.
+pub async fn read_data(
+    segment_path: PathBuf,
+    offset: u64,
+    length: u64,
+    key_ring: Option<Arc<KeyRing>>,
+    tx: mpsc::Sender<io::Result<Bytes>>,
+) {
+    if let Err(e) = stream_data(segment_path, offset, length, key_ring, &tx).await {
+        let _ = tx.send(Err(e)).await;
+    }
+}
+
+async fn stream_data(
+    segment_path: PathBuf,
+    offset: u64,
+    length: u64,
+    key_ring: Option<Arc<KeyRing>>,
+    tx: &mpsc::Sender<io::Result<Bytes>>,
+) -> io::Result<()> {
+    let mut file_handle = File::open(&segment_path).await?;
+    let segment_length = file_handle.metadata().await?.len();
+    let out_of_segment_error = |record_length: u64| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Record of {} bytes at offset {} is outside of segment {:?} which is {} bytes long",
+                record_length, offset, segment_path, segment_length
+            ),
+        )
+    };
+    let header_end = offset
+        .checked_add(RECORD_HEADER_LENGTH as u64)
+        .filter(|header_end| *header_end <= segment_length)
+        .ok_or_else(|| out_of_segment_error(RECORD_HEADER_LENGTH as u64))?;
+    file_handle.seek(SeekFrom::Start(offset)).await?;
+
+    let mut header_bytes = [0u8; RECORD_HEADER_LENGTH];
+    file_handle.read_exact(&mut header_bytes).await?;
+    let header = RecordHeader::decode(&header_bytes)?;
+    if header.content_length != length {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Record at offset {} of segment {:?} holds {} bytes of content, not {}",
+                offset, segment_path, header.content_length, length
+            ),
+        ));
+    }
+    if header_end
+        .checked_add(header.payload_length)
+        .is_none_or(|record_end| record_end > segment_length)
+    {
+        return Err(out_of_segment_error(
+            RECORD_HEADER_LENGTH as u64 + header.payload_length,
+        ));
+    }
+
+    let mut hasher = header.checksum_hasher();
+    if header.codec != Codec::None || header.key_id.is_some() {
+        let mut payload = vec![0u8; header.payload_length as usize];
+        file_handle.read_exact(&mut payload).await?;
+        hasher.update(&payload);
+        header.verify_checksum(hasher)?;
+        let content = tokio::task::spawn_blocking(move || {
+            let payload = match header.key_id {
+                Some(key_id) => key_ring
+                    .ok_or_else(|| {
+                        io::Error::other("Content is encrypted but no keys are configured")
+                    })?
+                    .decrypt(key_id, &header.nonce, &payload)?,
+                None => payload,
+            };
+            compression::decompress(header.codec, &payload, header.content_length as usize)
+        })
+        .await??;
+        let content = Bytes::from(content);
+        for start in (0..content.len()).step_by(READ_CHUNK_SIZE) {
+            let end = content.len().min(start + READ_CHUNK_SIZE);
+            if tx.send(Ok(content.slice(start..end))).await.is_err() {
+                tracing::info!("Reader went away, stopping read of {:?}", segment_path);
+                return Ok(());
+            }
+        }
+        return Ok(());
+    }
+
+    let mut remaining = length;
+    while remaining > 0 {
+        let chunk_size = remaining.min(READ_CHUNK_SIZE as u64) as usize;
+        let mut chunk = BytesMut::zeroed(chunk_size);
+        file_handle.read_exact(&mut chunk).await?;
+        hasher.update(&chunk);
+        remaining -= chunk_size as u64;
+        if tx.send(Ok(chunk.freeze())).await.is_err() {
+            tracing::info!("Reader went away, stopping read of {:?}", segment_path);
+            return Ok(());
+        }
+    }
+    header.verify_checksum(hasher)?;
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use commons::ContentRepositoryConfig;
-    use tempfile::tempdir;
+    use commons::{Compression, ContentRepositoryConfig, DurabilityPolicy, EncryptionConfig};
+    use tempfile::{tempdir, TempDir};
 
     use super::*;
-    use std::{fs::File as StdFile, io::Read};
+
+    fn test_config(temp_dir: &TempDir) -> ContentRepositoryConfig {
+        ContentRepositoryConfig {
+            base_path: temp_dir.path().to_str().unwrap().to_string(),
+            file_name_prefix: String::from("test_wal"),
+            server_port: 8080,
+            max_segment_bytes: 1024 * 1024,
+            max_segment_age_secs: 3600,
+            durability: DurabilityPolicy::PerRecord,
+            compression: Compression::None,
+            encryption: None,
+        }
+    }
 
     #[tokio::test]
     async fn test_init() {
-        let test_config = ContentRepositoryConfig {
-            base_path: String::from("/tmp"),
-            file_name_prefix: String::from("test_wal_init"),
-            server_port: 8080,
-        };
-        let file_handle = init(test_config).await;
-        let file = StdFile::open("/tmp/test_wal_init.txt").unwrap();
+        let temp_dir = tempdir().unwrap();
+        let content_repository = ContentRepository::init(test_config(&temp_dir)).await;
         assert_eq!(
-            file.metadata().unwrap().len(),
-            file_handle.metadata().await.unwrap().len()
+            content_repository.active_segment_name(),
+            "test_wal-000001.seg"
         );
+        assert!(temp_dir.path().join("test_wal-000001.seg").exists());
     }
 
     #[tokio::test]
     async fn test_append_data() {
         let temp_dir = tempdir().unwrap();
-        let test_config = ContentRepositoryConfig {
-            base_path: temp_dir.path().to_str().unwrap().to_string(),
-            file_name_prefix: String::from("test_wal_append"),
-            server_port: 8080,
-        };
-        let mut file_handle = init(test_config).await;
+        let mut content_repository = ContentRepository::init(test_config(&temp_dir)).await;
+
+        let records: [&[u8]; 3] = [b"test line 1\n", b"\x00\xffbinary\ncontent", b"test line 2"];
+        let mut references = vec![];
+        for record in records {
+            references.push(content_repository.append_data(record).await);
+        }
+
+        let actual_contents = std::fs::read(temp_dir.path().join("test_wal-000001.seg")).unwrap();
+        let mut position = 0;
+        for (record, reference) in records.iter().zip(references) {
+            assert_eq!(reference.segment, "test_wal-000001.seg");
+            assert_eq!(reference.offset, position as u64);
+            assert_eq!(reference.length, record.len() as u64);
+            let header_bytes = actual_contents[position..position + RECORD_HEADER_LENGTH]
+                .try_into()
+                .unwrap();
+            let header = RecordHeader::decode(header_bytes).unwrap();
+            position += RECORD_HEADER_LENGTH;
+            let payload = &actual_contents[position..position + header.payload_length as usize];
+            assert_eq!(payload, *record);
+
+            let mut hasher = header.checksum_hasher();
+            hasher.update(payload);
+            assert!(header.verify_checksum(hasher).is_ok());
+            position += payload.len();
+        }
+        assert_eq!(position, actual_contents.len());
+    }
+
+    #[tokio::test]
+    async fn test_size_based_rotation() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = test_config(&temp_dir);
+        config.max_segment_bytes = 2 * (RECORD_HEADER_LENGTH as u64 + 10);
+        let mut content_repository = ContentRepository::init(config.clone()).await;
 
-        append_data(&mut file_handle, "test line 1".as_bytes()).await;
-        append_data(&mut file_handle, "test line 2".as_bytes()).await;
+        let mut segments = vec![];
+        for _ in 0..5 {
+            segments.push(content_repository.append_data(&[1u8; 10]).await.segment);
+        }
+        // Records larger than a segment still get stored, alone in their segment.
+        let large_record = vec![2u8; config.max_segment_bytes as usize * 2];
+        let reference = content_repository.append_data(&large_record).await;
+        segments.push(reference.segment.clone());
+        assert_eq!(reference.offset, 0);
 
-        let test_file_path = temp_dir.path().join("test_wal_append.txt");
-        let mut file = StdFile::open(test_file_path.to_str().unwrap()).unwrap();
-        let mut actual_contents = String::new();
-        file.read_to_string(&mut actual_contents).unwrap();
+        let expected_segments = [1, 1, 2, 2, 3, 4].map(|sequence| segment_name(&config, sequence));
+        assert_eq!(segments, expected_segments);
+        for segment in expected_segments {
+            assert!(temp_dir.path().join(segment).exists());
+        }
+        assert!(!temp_dir.path().join("test_wal-000005.seg").exists());
 
-        let expected_contents = "test line 1\ntest line 2\n";
-        assert_eq!(actual_contents, expected_contents);
+        // Reopening the repository continues appending to the last segment.
+        drop(content_repository);
+        let mut content_repository = ContentRepository::init(config.clone()).await;
+        assert_eq!(
+            content_repository.active_segment_name(),
+            segment_name(&config, 4)
+        );
+        let reference = content_repository.append_data(&[1u8; 10]).await;
+        assert_eq!(reference.segment, segment_name(&config, 5));
+        assert!(temp_dir.path().join(segment_name(&config, 1)).exists());
+    }
+
+    #[tokio::test]
+    async fn test_unclaimed_segments_are_deleted() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = test_config(&temp_dir);
+        config.max_segment_bytes = 2 * (RECORD_HEADER_LENGTH as u64 + 10);
+        let mut content_repository = ContentRepository::init(config.clone()).await;
+
+        let first_record = content_repository.append_data(&[1u8; 10]).await;
+        let second_record = content_repository.append_data(&[2u8; 10]).await;
+        let third_record = content_repository.append_data(&[3u8; 10]).await;
+        assert_eq!(first_record.segment, segment_name(&config, 1));
+        assert_eq!(third_record.segment, segment_name(&config, 2));
+
+        assert_eq!(content_repository.claim(&first_record).await.unwrap(), 2);
+        assert_eq!(content_repository.release(&first_record).await.unwrap(), 1);
+        assert_eq!(content_repository.release(&first_record).await.unwrap(), 0);
+        assert!(content_repository.locate(&second_record).is_some());
+
+        assert_eq!(content_repository.release(&second_record).await.unwrap(), 0);
+        assert!(content_repository.locate(&second_record).is_none());
+        assert!(!temp_dir.path().join(&first_record.segment).exists());
+
+        // Active segment is only deleted once it has been rotated.
+        assert_eq!(content_repository.release(&third_record).await.unwrap(), 0);
+        assert!(temp_dir.path().join(&third_record.segment).exists());
+        let fourth_record = content_repository.append_data(&[4u8; 30]).await;
+        assert!(!temp_dir.path().join(&third_record.segment).exists());
+
+        // Claims are kept across restarts.
+        drop(content_repository);
+        let mut content_repository = ContentRepository::init(config.clone()).await;
+        assert!(content_repository.locate(&fourth_record).is_some());
+        assert_eq!(content_repository.claim(&fourth_record).await.unwrap(), 2);
+        assert!(content_repository.release(&first_record).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_recovery_after_crash() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = test_config(&temp_dir);
+        config.max_segment_bytes = 2 * (RECORD_HEADER_LENGTH as u64 + 10);
+        let mut content_repository = ContentRepository::init(config.clone()).await;
+        let first_record = content_repository.append_data(&[1u8; 10]).await;
+        let second_record = content_repository.append_data(&[2u8; 10]).await;
+        let third_record = content_repository.append_data(&[3u8; 10]).await;
+        let fourth_record = content_repository.append_data(&[4u8; 10]).await;
+        drop(content_repository);
+
+        // Corrupt a record in the older segment, and tear the last record of the latest one.
+        let first_segment_path = temp_dir.path().join(&first_record.segment);
+        let mut contents = std::fs::read(&first_segment_path).unwrap();
+        *contents.last_mut().unwrap() = 0;
+        std::fs::write(&first_segment_path, contents).unwrap();
+        let last_segment_path = temp_dir.path().join(&fourth_record.segment);
+        let torn_length = std::fs::metadata(&last_segment_path).unwrap().len() - 5;
+        truncate_segment(&last_segment_path, torn_length);
+
+        let mut content_repository = ContentRepository::init(config.clone()).await;
+        assert_eq!(
+            content_repository.recovery_report(),
+            &RecoveryReport {
+                segments_scanned: 2,
+                records_recovered: 2,
+                torn_bytes_truncated: RECORD_HEADER_LENGTH as u64 + 5,
+                corrupted_segments: vec![first_record.segment.clone()],
+                dropped_claims: 2,
+            }
+        );
+        assert_eq!(
+            std::fs::metadata(&last_segment_path).unwrap().len(),
+            RECORD_HEADER_LENGTH as u64 + 10
+        );
+        assert!(content_repository.locate(&first_record).is_some());
+        assert!(content_repository.locate(&second_record).is_none());
+        assert!(content_repository.locate(&third_record).is_some());
+        assert!(content_repository.locate(&fourth_record).is_none());
+        assert!(content_repository.claim(&fourth_record).await.is_err());
+
+        // Appends continue right after the last valid record.
+        let fifth_record = content_repository.append_data(&[5u8; 10]).await;
+        assert_eq!(fifth_record.segment, fourth_record.segment);
+        assert_eq!(fifth_record.offset, fourth_record.offset);
+    }
+
+    #[tokio::test]
+    async fn test_recovery_keeps_corrupted_latest_segment() {
+        let temp_dir = tempdir().unwrap();
+        let config = test_config(&temp_dir);
+        let mut content_repository = ContentRepository::init(config.clone()).await;
+        let first_record = content_repository.append_data(&[1u8; 10]).await;
+        let second_record = content_repository.append_data(&[2u8; 10]).await;
+        let third_record = content_repository.append_data(&[3u8; 10]).await;
+        drop(content_repository);
+
+        // Corrupt the payload of the record in the middle of the latest segment.
+        let segment_path = temp_dir.path().join(&second_record.segment);
+        let mut contents = std::fs::read(&segment_path).unwrap();
+        let segment_length = contents.len() as u64;
+        contents[second_record.offset as usize + RECORD_HEADER_LENGTH] = 0;
+        std::fs::write(&segment_path, contents).unwrap();
+
+        let mut content_repository = ContentRepository::init(config.clone()).await;
+        assert_eq!(
+            content_repository.recovery_report(),
+            &RecoveryReport {
+                segments_scanned: 1,
+                records_recovered: 1,
+                torn_bytes_truncated: 0,
+                corrupted_segments: vec![second_record.segment.clone()],
+                dropped_claims: 2,
+            }
+        );
+        assert_eq!(
+            std::fs::metadata(&segment_path).unwrap().len(),
+            segment_length
+        );
+        assert!(content_repository.locate(&first_record).is_some());
+        assert!(content_repository.locate(&third_record).is_none());
+
+        // Appends continue in a new segment, after the corrupted one.
+        assert_eq!(
+            content_repository.active_segment_name(),
+            segment_name(&config, 2)
+        );
+        let fourth_record = content_repository.append_data(&[4u8; 10]).await;
+        assert_eq!(fourth_record.segment, segment_name(&config, 2));
+        assert_eq!(fourth_record.offset, 0);
+    }
+
+    #[tokio::test]
+    async fn test_time_based_rotation() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = test_config(&temp_dir);
+        config.max_segment_age_secs = 0;
+        let mut content_repository = ContentRepository::init(config.clone()).await;
+
+        // Empty segments are never rotated.
+        content_repository.rotate_if_expired().await;
+        assert_eq!(
+            content_repository.active_segment_name(),
+            segment_name(&config, 1)
+        );
+
+        content_repository.append_data(b"test content").await;
+        content_repository.rotate_if_expired().await;
+        assert_eq!(
+            content_repository.active_segment_name(),
+            segment_name(&config, 2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_data() {
+        let temp_dir = tempdir().unwrap();
+        let mut content_repository = ContentRepository::init(test_config(&temp_dir)).await;
+
+        let large_record = vec![9u8; READ_CHUNK_SIZE * 2 + 10];
+        content_repository.append_data(b"test line 1").await;
+        let reference = content_repository.append_data(&large_record).await;
+        let segment_path = content_repository.locate(&reference).unwrap();
+
+        let (tx, mut rx) = mpsc::channel(10);
+        read_data(
+            segment_path.clone(),
+            reference.offset,
+            reference.length,
+            None,
+            tx,
+        )
+        .await;
+        let mut chunk_sizes = vec![];
+        let mut actual_contents = vec![];
+        while let Some(chunk) = rx.recv().await {
+            let chunk = chunk.unwrap();
+            chunk_sizes.push(chunk.len());
+            actual_contents.extend_from_slice(&chunk);
+        }
+        assert_eq!(chunk_sizes, vec![READ_CHUNK_SIZE, READ_CHUNK_SIZE, 10]);
+        assert_eq!(actual_contents, large_record);
+
+        let (tx, mut rx) = mpsc::channel(10);
+        read_data(
+            segment_path.clone(),
+            reference.offset,
+            reference.length - 1,
+            None,
+            tx,
+        )
+        .await;
+        let error = rx.recv().await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(rx.recv().await.is_none());
+
+        let (tx, mut rx) = mpsc::channel(10);
+        read_data(
+            segment_path,
+            reference.offset - 1,
+            reference.length,
+            None,
+            tx,
+        )
+        .await;
+        let error = rx.recv().await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn test_read_corrupted_data() {
+        let temp_dir = tempdir().unwrap();
+        let mut content_repository = ContentRepository::init(test_config(&temp_dir)).await;
+        let reference = content_repository.append_data(b"test content").await;
+        let segment_path = content_repository.locate(&reference).unwrap();
+
+        let mut contents = std::fs::read(&segment_path).unwrap();
+        *contents.last_mut().unwrap() = b'X';
+        std::fs::write(&segment_path, contents).unwrap();
+
+        let (tx, mut rx) = mpsc::channel(10);
+        read_data(segment_path, reference.offset, reference.length, None, tx).await;
+        assert_eq!(&rx.recv().await.unwrap().unwrap()[..], b"test contenX");
+        let error = rx.recv().await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn test_read_compressed_data() {
+        for compression in [Compression::Zstd, Compression::Lz4, Compression::Gzip] {
+            let temp_dir = tempdir().unwrap();
+            let mut config = test_config(&temp_dir);
+            config.compression = compression;
+            let mut content_repository = ContentRepository::init(config.clone()).await;
+
+            let large_record = b"compressible content\n".repeat(READ_CHUNK_SIZE / 10);
+            let first_reference = content_repository.append_data(b"tiny").await;
+            let reference = content_repository.append_data(&large_record).await;
+            assert_eq!(reference.length, large_record.len() as u64);
+            let segment_path = content_repository.locate(&reference).unwrap();
+            let segment_length = std::fs::metadata(&segment_path).unwrap().len();
+            assert!(segment_length < reference.offset + reference.length);
+
+            // Records keep the codec they were written with when the configuration changes.
+            drop(content_repository);
+            config.compression = Compression::None;
+            let content_repository = ContentRepository::init(config).await;
+            assert_eq!(content_repository.recovery_report().records_recovered, 2);
+            assert!(content_repository.locate(&first_reference).is_some());
+            assert!(content_repository.locate(&reference).is_some());
+
+            let (tx, mut rx) = mpsc::channel(10);
+            read_data(
+                segment_path.clone(),
+                reference.offset,
+                reference.length,
+                None,
+                tx,
+            )
+            .await;
+            let mut chunk_sizes = vec![];
+            let mut actual_contents = vec![];
+            while let Some(chunk) = rx.recv().await {
+                let chunk = chunk.unwrap();
+                chunk_sizes.push(chunk.len());
+                actual_contents.extend_from_slice(&chunk);
+            }
+            assert_eq!(chunk_sizes.len(), 3);
+            assert!(chunk_sizes.iter().all(|size| *size <= READ_CHUNK_SIZE));
+            assert_eq!(actual_contents, large_record);
+
+            // Compressed records are verified before anything is sent.
+            let mut contents = std::fs::read(&segment_path).unwrap();
+            *contents.last_mut().unwrap() ^= 0xFF;
+            std::fs::write(&segment_path, contents).unwrap();
+            let (tx, mut rx) = mpsc::channel(10);
+            read_data(segment_path, reference.offset, reference.length, None, tx).await;
+            let error = rx.recv().await.unwrap().unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            assert!(rx.recv().await.is_none());
+        }
+    }
+
+    async fn read_all(
+        segment_path: PathBuf,
+        reference: &ContentReference,
+        key_ring: Option<Arc<KeyRing>>,
+    ) -> io::Result<Vec<u8>> {
+        let (tx, mut rx) = mpsc::channel(10);
+        read_data(
+            segment_path,
+            reference.offset,
+            reference.length,
+            key_ring,
+            tx,
+        )
+        .await;
+        let mut contents = vec![];
+        while let Some(chunk) = rx.recv().await {
+            contents.extend_from_slice(&chunk?);
+        }
+        Ok(contents)
+    }
+
+    #[tokio::test]
+    async fn test_read_encrypted_data() {
+        let temp_dir = tempdir().unwrap();
+        let key_file = temp_dir.path().join("keys");
+        let first_key = "1 000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f\n";
+        std::fs::write(&key_file, first_key).unwrap();
+        let mut config = test_config(&temp_dir);
+        config.compression = Compression::Lz4;
+        config.encryption = Some(EncryptionConfig {
+            key_file: key_file.to_str().unwrap().to_string(),
+            active_key_id: 1,
+        });
+        let mut content_repository = ContentRepository::init(config.clone()).await;
+
+        let secret = b"customer data ".repeat(100);
+        let first_reference = content_repository.append_data(&secret).await;
+        let segment_path = content_repository.locate(&first_reference).unwrap();
+        let segment_contents = std::fs::read(&segment_path).unwrap();
+        assert!(!segment_contents
+            .windows(b"customer data".len())
+            .any(|window| window == b"customer data"));
+        let contents = read_all(
+            segment_path.clone(),
+            &first_reference,
+            content_repository.key_ring(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(contents, secret);
+        assert!(read_all(segment_path.clone(), &first_reference, None)
+            .await
+            .is_err());
+
+        // Records encrypted with a rotated key can still be read while the key is in the key file.
+        drop(content_repository);
+        let second_key = "2 ff0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f\n";
+        std::fs::write(&key_file, format!("{}{}", first_key, second_key)).unwrap();
+        config.encryption.as_mut().unwrap().active_key_id = 2;
+        let mut content_repository = ContentRepository::init(config.clone()).await;
+        let second_reference = content_repository.append_data(b"more data").await;
+        for (reference, expected_contents) in [
+            (&first_reference, secret.as_slice()),
+            (&second_reference, b"more data".as_slice()),
+        ] {
+            let contents = read_all(
+                segment_path.clone(),
+                reference,
+                content_repository.key_ring(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(contents, expected_contents);
+        }
+
+        drop(content_repository);
+        std::fs::write(&key_file, second_key).unwrap();
+        let content_repository = ContentRepository::init(config).await;
+        assert!(read_all(
+            segment_path,
+            &first_reference,
+            content_repository.key_ring()
+        )
+        .await
+        .is_err());
     }
 }
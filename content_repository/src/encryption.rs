@@ -0,0 +1,197 @@
+use std::{collections::HashMap, io};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm,
+};
+use commons::EncryptionConfig;
+
+pub const NONCE_LENGTH: usize = 12;
+const KEY_LENGTH: usize = 32;
+
+pub type Nonce = [u8; NONCE_LENGTH];
+
+/// All keys from the key file, by key id. New records are encrypted with the active key,
+/// existing records are decrypted with the key they were encrypted with.
+pub struct KeyRing {
+    keys: HashMap<u32, Aes256Gcm>,
+    active_key_id: u32,
+}
+
+impl KeyRing {
+    pub fn load(config: &EncryptionConfig) -> io::Result<Self> {
+        let key_file = std::fs::read_to_string(&config.key_file)?;
+        warn_if_readable_by_others(&config.key_file);
+        let key_ring = KeyRing {
+            keys: parse_key_file(&key_file)?,
+            active_key_id: config.active_key_id,
+        };
+        if !key_ring.keys.contains_key(&config.active_key_id) {
+            return Err(invalid_key_file_error(format!(
+                "Active key {} is not in the key file",
+                config.active_key_id
+            )));
+        }
+        Ok(key_ring)
+    }
+
+    /// Encrypts `content` with the active key under a new random nonce.
+    /// Returns the id of the key and the nonce, which are needed to decrypt it, along with
+    /// the ciphertext followed by its authentication tag.
+    pub fn encrypt(&self, content: &[u8]) -> io::Result<(u32, Nonce, Vec<u8>)> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.keys[&self.active_key_id]
+            .encrypt(&nonce, content)
+            .map_err(|_| io::Error::other("Failed to encrypt content"))?;
+        Ok((self.active_key_id, nonce.into(), ciphertext))
+    }
+
+    /// Decrypts and authenticates content encrypted by `encrypt`.
+    pub fn decrypt(&self, key_id: u32, nonce: &Nonce, ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+        let key = self.keys.get(&key_id).ok_or_else(|| {
+            io::Error::other(format!(
+                "Content is encrypted with key {} which is not in the key file",
+                key_id
+            ))
+        })?;
+        key.decrypt(nonce.into(), ciphertext).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Content encrypted with key {} failed authentication",
+                    key_id
+                ),
+            )
+        })
+    }
+}
+
+fn parse_key_file(key_file: &str) -> io::Result<HashMap<u32, Aes256Gcm>> {
+    let mut keys = HashMap::new();
+    for (line_number, line) in key_file.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid_line_error = |reason: &str| {
+            invalid_key_file_error(format!("Line {} of key file {}", line_number + 1, reason))
+        };
+        let Some((key_id, key)) = line.split_once(' ') else {
+            return Err(invalid_line_error("is not a `{key id} {key}` pair"));
+        };
+        // Key id 0 marks records which are not encrypted.
+        let key_id = match key_id.parse::<u32>() {
+            Ok(key_id) if key_id > 0 => key_id,
+            _ => return Err(invalid_line_error("does not start with a positive key id")),
+        };
+        let key = match hex::decode(key.trim()) {
+            Ok(key) if key.len() == KEY_LENGTH => key,
+            _ => {
+                return Err(invalid_line_error(
+                    "does not hold a hex encoded 256 bit key",
+                ))
+            }
+        };
+        let cipher = Aes256Gcm::new_from_slice(&key).unwrap();
+        if keys.insert(key_id, cipher).is_some() {
+            return Err(invalid_line_error("repeats an earlier key id"));
+        }
+    }
+    Ok(keys)
+}
+
+fn invalid_key_file_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(unix)]
+fn warn_if_readable_by_others(key_file: &str) {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Ok(metadata) = std::fs::metadata(key_file) {
+        if metadata.permissions().mode() & 0o077 != 0 {
+            tracing::warn!(
+                "Key file {} can be accessed by users other than its owner",
+                key_file
+            );
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn warn_if_readable_by_others(_key_file: &str) {}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    const FIRST_KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    const SECOND_KEY: &str = "ff0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    fn write_key_file(temp_dir: &TempDir, contents: &str) -> String {
+        let key_file = temp_dir.path().join("keys");
+        std::fs::write(&key_file, contents).unwrap();
+        key_file.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_encrypt_and_decrypt_with_rotated_keys() {
+        let temp_dir = tempdir().unwrap();
+        let key_file = write_key_file(&temp_dir, &format!("# Rotated keys\n1 {}\n", FIRST_KEY));
+        let first_key_ring = KeyRing::load(&EncryptionConfig {
+            key_file,
+            active_key_id: 1,
+        })
+        .unwrap();
+        let (key_id, nonce, ciphertext) = first_key_ring.encrypt(b"secret content").unwrap();
+        assert_eq!(key_id, 1);
+        assert_ne!(&ciphertext[..], b"secret content");
+
+        let key_file = write_key_file(&temp_dir, &format!("1 {}\n\n2 {}\n", FIRST_KEY, SECOND_KEY));
+        let second_key_ring = KeyRing::load(&EncryptionConfig {
+            key_file,
+            active_key_id: 2,
+        })
+        .unwrap();
+        assert_eq!(
+            second_key_ring.decrypt(1, &nonce, &ciphertext).unwrap(),
+            b"secret content"
+        );
+        let (key_id, nonce, ciphertext) = second_key_ring.encrypt(b"new content").unwrap();
+        assert_eq!(key_id, 2);
+        assert!(first_key_ring.decrypt(2, &nonce, &ciphertext).is_err());
+
+        let mut tampered_ciphertext = ciphertext.clone();
+        tampered_ciphertext[0] ^= 1;
+        let error = second_key_ring
+            .decrypt(2, &nonce, &tampered_ciphertext)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_invalid_key_files_are_rejected() {
+        let temp_dir = tempdir().unwrap();
+        for contents in [
+            format!("0 {}", FIRST_KEY),
+            format!("1 {}", &FIRST_KEY[..32]),
+            format!("1 {}\n1 {}", FIRST_KEY, SECOND_KEY),
+            FIRST_KEY.to_string(),
+            format!("2 {}", FIRST_KEY),
+        ] {
+            let key_file = write_key_file(&temp_dir, &contents);
+            let result = KeyRing::load(&EncryptionConfig {
+                key_file,
+                active_key_id: 1,
+            });
+            assert_eq!(
+                result.err().map(|e| e.kind()),
+                Some(io::ErrorKind::InvalidData),
+                "Key file {:?} was accepted",
+                contents
+            );
+        }
+    }
+}
@@ -1,15 +1,31 @@
-use bytes::Bytes;
+use std::{io, time::Duration};
+
+use bytes::{Bytes, BytesMut};
+use commons::{
+    protocol::{self, ContentReference, Frame, OpCode, ProtocolError},
+    DurabilityPolicy,
+};
+use content_repository_manager::ContentRepository;
 use models::Command;
 use tokio::{
-    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
     net::TcpListener,
     select,
     sync::{mpsc, oneshot},
+    time::Instant,
 };
 use tokio_util::sync::CancellationToken;
 
+mod compression;
+mod content_claim_manager;
 mod content_repository_manager;
+mod encryption;
 mod models;
+mod record;
+mod recovery;
+
+const READ_CHANNEL_SIZE: usize = 4;
+const SEGMENT_ROTATION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 #[tokio::main]
 async fn main() {
@@ -18,26 +34,35 @@ async fn main() {
     let config = commons::get_config().content_repository;
 
     let server_port = config.server_port;
+    let durability = config.durability;
+
+    // Recovery has to finish before any client can connect.
+    let content_repository = ContentRepository::init(config).await;
+    tracing::info!(
+        "Content repository recovery report: {}",
+        content_repository.recovery_report()
+    );
+    tracing::info!(
+        "Appending content to segment {} with durability {:?}",
+        content_repository.active_segment_name(),
+        durability
+    );
+
     let server_address = format!("0.0.0.0:{}", server_port);
     let listener = TcpListener::bind(&server_address).await.unwrap();
     tracing::info!("Rusk content repository listening on {}", server_address);
 
-    let mut file_handle = content_repository_manager::init(config).await;
-
-    let (conten_repo_manager_tx, mut content_repo_manager_rx) = mpsc::channel::<Command>(1000);
+    let (conten_repo_manager_tx, content_repo_manager_rx) = mpsc::channel::<Command>(1000);
 
     let cancellation_token = CancellationToken::new();
     let cancellation_token_for_content_repo_manager = cancellation_token.clone();
 
-    tokio::spawn(async move {
-        while let Some(data) = content_repo_manager_rx.recv().await {
-            if cancellation_token_for_content_repo_manager.is_cancelled() {
-                tracing::info!("Cancellation token received. Stopping content repo manager task.");
-                break;
-            }
-            process_data(data, &mut file_handle).await;
-        }
-    });
+    tokio::spawn(run_content_repo_manager(
+        content_repository,
+        content_repo_manager_rx,
+        durability,
+        cancellation_token_for_content_repo_manager,
+    ));
 
     accept_client_connections(listener, conten_repo_manager_tx, cancellation_token.clone()).await;
 
@@ -52,15 +77,112 @@ async fn main() {
     }
 }
 
-async fn process_data(data: Command, file_handle: &mut tokio::fs::File) {
+/// Writes which were appended to the content repository but not acknowledged yet,
+/// because they are waiting to be synced to disk.
+type PendingWrites = Vec<(oneshot::Sender<ContentReference>, ContentReference)>;
+
+/// Owns the content repository and applies commands to it one at a time.
+/// Writes are acknowledged according to the durability policy.
+async fn run_content_repo_manager(
+    mut content_repository: ContentRepository,
+    mut content_repo_manager_rx: mpsc::Receiver<Command>,
+    durability: DurabilityPolicy,
+    cancellation_token: CancellationToken,
+) {
+    let mut rotation_check_interval = tokio::time::interval(SEGMENT_ROTATION_CHECK_INTERVAL);
+    let (commit_interval, max_batch) = match durability {
+        DurabilityPolicy::GroupCommit {
+            interval_ms,
+            max_batch,
+        } => (Duration::from_millis(interval_ms), max_batch),
+        DurabilityPolicy::None | DurabilityPolicy::PerRecord => (Duration::ZERO, 1),
+    };
+    let commit_timer = tokio::time::sleep(commit_interval);
+    tokio::pin!(commit_timer);
+    let mut pending_writes: PendingWrites = vec![];
+    loop {
+        select! {
+            data = content_repo_manager_rx.recv() => {
+                let Some(data) = data else {
+                    break;
+                };
+                if cancellation_token.is_cancelled() {
+                    tracing::info!("Cancellation token received. Stopping content repo manager task.");
+                    break;
+                }
+                let group_is_empty = pending_writes.is_empty();
+                process_data(data, &mut content_repository, &mut pending_writes).await;
+                if pending_writes.len() >= max_batch {
+                    commit(&mut content_repository, &mut pending_writes, durability).await;
+                } else if group_is_empty && !pending_writes.is_empty() {
+                    // Group is started by its first write.
+                    commit_timer.as_mut().reset(Instant::now() + commit_interval);
+                }
+            }
+            _ = &mut commit_timer, if !pending_writes.is_empty() => {
+                commit(&mut content_repository, &mut pending_writes, durability).await;
+            }
+            _ = rotation_check_interval.tick() => {
+                content_repository.rotate_if_expired().await;
+            }
+        }
+    }
+    // Make sure nothing that was written is left unsynced.
+    commit(&mut content_repository, &mut pending_writes, durability).await;
+}
+
+async fn commit(
+    content_repository: &mut ContentRepository,
+    pending_writes: &mut PendingWrites,
+    durability: DurabilityPolicy,
+) {
+    if pending_writes.is_empty() {
+        return;
+    }
+    if durability != DurabilityPolicy::None {
+        content_repository.sync().await;
+    }
+    for (tx, reference) in pending_writes.drain(..) {
+        let _ = tx.send(reference);
+    }
+}
+
+async fn process_data(
+    data: Command,
+    content_repository: &mut ContentRepository,
+    pending_writes: &mut PendingWrites,
+) {
     match data {
         Command::Data {
             content,
             tx: oneshot_tx,
         } => {
-            let record_start_offset = file_handle.stream_position().await.unwrap();
-            content_repository_manager::append_data(file_handle, &content).await;
-            let _ = oneshot_tx.send(record_start_offset);
+            let reference = content_repository.append_data(&content).await;
+            pending_writes.push((oneshot_tx, reference));
+        }
+        Command::Read { reference, tx } => {
+            let Some(segment_path) = content_repository.locate(&reference) else {
+                let error = io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("No content at {:?}", reference),
+                );
+                let _ = tx.send(Err(error)).await;
+                return;
+            };
+            // Segment data is never modified once written so reads do not need to hold up writes.
+            tokio::spawn(content_repository_manager::read_data(
+                segment_path,
+                reference.offset,
+                reference.length,
+                content_repository.key_ring(),
+                tx,
+            ));
+        }
+        Command::Claim { reference, tx } => {
+            let _ = tx.send(content_repository.claim(&reference).await);
+        }
+        Command::Release { reference, tx } => {
+            let _ = tx.send(content_repository.release(&reference).await);
         }
     }
 }
@@ -82,56 +204,169 @@ async fn accept_client_connections(
 
                 let conten_repo_manager_tx_clone = conten_repo_manager_tx.clone();
                 tokio::spawn(async move {
-                    let (reader, mut writer) = socket.split();
+                    let (reader, writer) = socket.split();
                     // TODO: Do we need to send cancellation token here as well?
-                    match handle_client_request(conten_repo_manager_tx_clone, reader).await {
-                        Some(response) => {
-                            tracing::info!("Response from content repo: {:?}", response);
-                            writer.write_u64(response).await.unwrap();
-                        }
-                        None => {
-                            tracing::info!("Client connection closed.");
-                            writer.write_u64(0).await.unwrap();
-                        }
-                    }
-                    writer.flush().await.unwrap();
-                    writer.shutdown().await.unwrap();
+                    handle_client_requests(conten_repo_manager_tx_clone, reader, writer).await;
                 });
             }
         }
     }
 }
 
-async fn handle_client_request<Reader>(
+/// Serves framed requests from a single client connection until the client disconnects
+/// or sends a frame which cannot be understood.
+async fn handle_client_requests<Reader, Writer>(
     tx_clone: mpsc::Sender<Command>,
     mut reader: Reader,
-) -> Option<u64>
+    mut writer: Writer,
+) where
+    Reader: AsyncRead + Unpin,
+    Writer: AsyncWrite + Unpin,
+{
+    loop {
+        let frame = match protocol::read_frame(&mut reader).await {
+            Ok(Some(frame)) => frame,
+            Ok(None) => {
+                tracing::info!("Client connection closed.");
+                break;
+            }
+            Err(e) => {
+                tracing::error!("Failed to read frame from client: {}", e);
+                let _ = protocol::write_frame(&mut writer, &Frame::error(&e.to_string())).await;
+                break;
+            }
+        };
+
+        let response = match frame.opcode {
+            OpCode::Write => {
+                let content = if frame.payload.is_empty() {
+                    match read_content(&mut reader).await {
+                        Ok(content) => content,
+                        Err(e) => {
+                            tracing::error!("Failed to read content from client: {}", e);
+                            let _ =
+                                protocol::write_frame(&mut writer, &Frame::error(&e.to_string()))
+                                    .await;
+                            break;
+                        }
+                    }
+                } else {
+                    frame.payload
+                };
+                let (one_shot_tx, one_shot_rx) = oneshot::channel::<ContentReference>();
+                let command = Command::Data {
+                    content,
+                    tx: one_shot_tx,
+                };
+                tx_clone.send(command).await.unwrap();
+                let reference = one_shot_rx.await.unwrap();
+                tracing::info!("Response from content repo: {:?}", reference);
+                Frame::reference(&reference)
+            }
+            OpCode::Read => match ContentReference::decode(OpCode::Read, frame.payload) {
+                Ok(reference) => match stream_content(&tx_clone, reference, &mut writer).await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        tracing::error!("Failed to write content to client: {}", e);
+                        break;
+                    }
+                },
+                Err(e) => Frame::error(&e.to_string()),
+            },
+            OpCode::Claim | OpCode::Release => {
+                match ContentReference::decode(frame.opcode, frame.payload) {
+                    Ok(reference) => {
+                        let (one_shot_tx, one_shot_rx) = oneshot::channel::<io::Result<u64>>();
+                        let command = if frame.opcode == OpCode::Claim {
+                            Command::Claim {
+                                reference,
+                                tx: one_shot_tx,
+                            }
+                        } else {
+                            Command::Release {
+                                reference,
+                                tx: one_shot_tx,
+                            }
+                        };
+                        tx_clone.send(command).await.unwrap();
+                        match one_shot_rx.await.unwrap() {
+                            Ok(claim_count) => Frame::claim_count(claim_count),
+                            Err(e) => Frame::error(&e.to_string()),
+                        }
+                    }
+                    Err(e) => Frame::error(&e.to_string()),
+                }
+            }
+            other => {
+                tracing::error!("Received unexpected opcode from client: {:?}", other);
+                Frame::error(&format!("Unexpected opcode: {:?}", other))
+            }
+        };
+
+        if let Err(e) = protocol::write_frame(&mut writer, &response).await {
+            tracing::error!("Failed to write response to client: {}", e);
+            break;
+        }
+    }
+    let _ = writer.shutdown().await;
+}
+
+/// Reads the content of a `Write` which is streamed in `Content` frames up to a `ContentEnd`.
+async fn read_content<Reader>(reader: &mut Reader) -> Result<Bytes, ProtocolError>
 where
     Reader: AsyncRead + Unpin,
 {
-    let mut buffer = vec![0; 1024];
+    let mut content = BytesMut::new();
     loop {
-        if let Ok(bytes_read) = reader.read_buf(&mut buffer).await {
-            if bytes_read == 0 {
-                tracing::info!("0 bytes read, connection closed.");
-                return None;
+        let Some(frame) = protocol::read_frame(reader).await? else {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Connection closed before the end of the content",
+            )
+            .into());
+        };
+        match frame.opcode {
+            OpCode::Content => content.extend_from_slice(&frame.payload),
+            OpCode::ContentEnd => return Ok(content.freeze()),
+            other => return Err(ProtocolError::UnexpectedFrame(other)),
+        }
+    }
+}
+
+/// Forwards content chunks of a read request to the client as they arrive.
+/// Returns the frame which terminates the response.
+async fn stream_content<Writer>(
+    tx_clone: &mpsc::Sender<Command>,
+    reference: ContentReference,
+    writer: &mut Writer,
+) -> io::Result<Frame>
+where
+    Writer: AsyncWrite + Unpin,
+{
+    let (chunk_tx, mut chunk_rx) = mpsc::channel::<io::Result<Bytes>>(READ_CHANNEL_SIZE);
+    let command = Command::Read {
+        reference,
+        tx: chunk_tx,
+    };
+    tx_clone.send(command).await.unwrap();
+
+    while let Some(chunk) = chunk_rx.recv().await {
+        match chunk {
+            Ok(chunk) => protocol::write_frame(writer, &Frame::new(OpCode::Content, chunk)).await?,
+            Err(e) => {
+                tracing::error!("Failed to read content: {}", e);
+                return Ok(Frame::error(&e.to_string()));
             }
-            let (one_shot_tx, one_shot_rx) = oneshot::channel::<u64>();
-            let command = Command::Data {
-                content: Bytes::copy_from_slice(&buffer[..bytes_read]),
-                tx: one_shot_tx,
-            };
-            tx_clone.send(command).await.unwrap();
-            let response = one_shot_rx.await.unwrap();
-            tracing::info!("Response from content repo: {:?}", response);
-            return Some(response);
         }
     }
+    Ok(Frame::new(OpCode::ContentEnd, Bytes::new()))
 }
 
 #[cfg(test)]
 mod tests {
-    use tokio::{io::AsyncWriteExt, net::TcpStream};
+    use commons::{Compression, ContentRepositoryClientConfig, ContentRepositoryConfig};
+    use content_repository_client::{ClientError, ContentRepositoryClient};
+    use tokio::net::TcpStream;
 
     use super::*;
 
@@ -148,7 +383,357 @@ mod tests {
         });
 
         let mut stream = TcpStream::connect(addr).await.unwrap();
-        stream.write_all(b"data_to_write").await.unwrap();
+        let frame = Frame::new(OpCode::Write, Bytes::from_static(b"data_to_write"));
+        protocol::write_frame(&mut stream, &frame).await.unwrap();
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_frame_larger_than_maximum_is_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let cancellation_token = CancellationToken::new();
+        let cancellation_token_clone = cancellation_token.clone();
+        let (tx, _) = mpsc::channel::<Command>(10);
+
+        tokio::spawn(async move {
+            accept_client_connections(listener, tx, cancellation_token_clone).await;
+        });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut header = vec![protocol::PROTOCOL_VERSION, OpCode::Write as u8];
+        header.extend_from_slice(&(protocol::MAX_PAYLOAD_LENGTH + 1).to_be_bytes());
+        stream.write_all(&header).await.unwrap();
+
+        let response = protocol::read_frame(&mut stream).await.unwrap().unwrap();
+        assert_eq!(response.opcode, OpCode::Error);
+        assert!(String::from_utf8_lossy(&response.payload).contains("larger than the maximum"));
+        assert!(protocol::read_frame(&mut stream).await.unwrap().is_none());
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_write_content_in_chunks() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let cancellation_token = CancellationToken::new();
+        let cancellation_token_clone = cancellation_token.clone();
+        let (tx, mut rx) = mpsc::channel::<Command>(10);
+
+        tokio::spawn(async move {
+            accept_client_connections(listener, tx, cancellation_token_clone).await;
+        });
+        let (content_tx, mut content_rx) = mpsc::channel(10);
+        tokio::spawn(async move {
+            while let Some(Command::Data { content, tx }) = rx.recv().await {
+                let reference = ContentReference {
+                    segment: "test_segment".to_string(),
+                    offset: 0,
+                    length: content.len() as u64,
+                };
+                content_tx.send(content).await.unwrap();
+                tx.send(reference).unwrap();
+            }
+        });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        for frame in [
+            Frame::new(OpCode::Write, Bytes::new()),
+            Frame::new(OpCode::Content, Bytes::from_static(b"first chunk, ")),
+            Frame::new(OpCode::Content, Bytes::from_static(b"second chunk")),
+            Frame::new(OpCode::ContentEnd, Bytes::new()),
+        ] {
+            protocol::write_frame(&mut stream, &frame).await.unwrap();
+        }
+        let response = protocol::read_frame(&mut stream).await.unwrap().unwrap();
+        assert_eq!(response.opcode, OpCode::Reference);
+        assert_eq!(
+            content_rx.recv().await.unwrap(),
+            Bytes::from_static(b"first chunk, second chunk")
+        );
+
+        // Content which is interrupted by another request is rejected.
+        for frame in [
+            Frame::new(OpCode::Write, Bytes::new()),
+            Frame::new(OpCode::Content, Bytes::from_static(b"chunk")),
+            Frame::new(OpCode::Read, Bytes::new()),
+        ] {
+            protocol::write_frame(&mut stream, &frame).await.unwrap();
+        }
+        let response = protocol::read_frame(&mut stream).await.unwrap().unwrap();
+        assert_eq!(response.opcode, OpCode::Error);
+        assert!(String::from_utf8_lossy(&response.payload).contains("Unexpected Read frame"));
+        assert!(protocol::read_frame(&mut stream).await.unwrap().is_none());
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_multiple_requests_on_same_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let cancellation_token = CancellationToken::new();
+        let cancellation_token_clone = cancellation_token.clone();
+        let (tx, mut rx) = mpsc::channel::<Command>(10);
+
+        tokio::spawn(async move {
+            accept_client_connections(listener, tx, cancellation_token_clone).await;
+        });
+
+        // Fake content repo manager which replies with the running total of bytes received.
+        tokio::spawn(async move {
+            let mut next_offset = 0;
+            while let Some(Command::Data { content, tx }) = rx.recv().await {
+                let reference = ContentReference {
+                    segment: "test_segment".to_string(),
+                    offset: next_offset,
+                    length: content.len() as u64,
+                };
+                tx.send(reference).unwrap();
+                next_offset += content.len() as u64;
+            }
+        });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let payloads = [
+            Bytes::from_static(b"first record\nwith a newline"),
+            Bytes::from(vec![1u8; 64 * 1024]),
+            Bytes::from_static(b"third record"),
+        ];
+        let mut expected_offset = 0;
+        for payload in payloads {
+            let frame = Frame::new(OpCode::Write, payload.clone());
+            protocol::write_frame(&mut stream, &frame).await.unwrap();
+
+            let response = protocol::read_frame(&mut stream).await.unwrap().unwrap();
+            let expected_reference = ContentReference {
+                segment: "test_segment".to_string(),
+                offset: expected_offset,
+                length: payload.len() as u64,
+            };
+            assert_eq!(response, Frame::reference(&expected_reference));
+            expected_offset += payload.len() as u64;
+        }
+
+        let unexpected_frame = Frame::new(OpCode::ContentEnd, Bytes::new());
+        protocol::write_frame(&mut stream, &unexpected_frame)
+            .await
+            .unwrap();
+        let response = protocol::read_frame(&mut stream).await.unwrap().unwrap();
+        assert_eq!(response.opcode, OpCode::Error);
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_write_and_read_content() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = ContentRepositoryConfig {
+            base_path: temp_dir.path().to_str().unwrap().to_string(),
+            file_name_prefix: String::from("test_wal_read_write"),
+            server_port: 0,
+            max_segment_bytes: 1024,
+            max_segment_age_secs: 3600,
+            durability: DurabilityPolicy::PerRecord,
+            compression: Compression::None,
+            encryption: None,
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let cancellation_token = CancellationToken::new();
+        let cancellation_token_clone = cancellation_token.clone();
+        let (tx, rx) = mpsc::channel::<Command>(10);
+
+        let content_repository = ContentRepository::init(config.clone()).await;
+        tokio::spawn(run_content_repo_manager(
+            content_repository,
+            rx,
+            config.durability,
+            cancellation_token.clone(),
+        ));
+        tokio::spawn(async move {
+            accept_client_connections(listener, tx, cancellation_token_clone).await;
+        });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let payloads = [
+            Bytes::from_static(b"first record"),
+            Bytes::from(vec![3u8; content_repository_manager::READ_CHUNK_SIZE + 1]),
+        ];
+        let mut references = vec![];
+        for payload in &payloads {
+            let frame = Frame::new(OpCode::Write, payload.clone());
+            protocol::write_frame(&mut stream, &frame).await.unwrap();
+            let response = protocol::read_frame(&mut stream).await.unwrap().unwrap();
+            assert_eq!(response.opcode, OpCode::Reference);
+            references.push(ContentReference::decode(OpCode::Reference, response.payload).unwrap());
+        }
+        assert_ne!(
+            references[0].segment, references[1].segment,
+            "Second record does not fit in the first segment"
+        );
+
+        let references_for_claims = references.clone();
+        for (payload, reference) in payloads.iter().zip(references).rev() {
+            let frame = Frame::new(OpCode::Read, reference.encode());
+            protocol::write_frame(&mut stream, &frame).await.unwrap();
+
+            let mut actual_content = vec![];
+            loop {
+                let response = protocol::read_frame(&mut stream).await.unwrap().unwrap();
+                match response.opcode {
+                    OpCode::Content => actual_content.extend_from_slice(&response.payload),
+                    OpCode::ContentEnd => break,
+                    other => panic!("Unexpected opcode: {:?}", other),
+                }
+            }
+            assert_eq!(actual_content, payload.to_vec());
+        }
+
+        for (opcode, expected_claim_count) in [(OpCode::Claim, 2), (OpCode::Release, 1)] {
+            let frame = Frame::new(opcode, references_for_claims[0].encode());
+            protocol::write_frame(&mut stream, &frame).await.unwrap();
+            let response = protocol::read_frame(&mut stream).await.unwrap().unwrap();
+            assert_eq!(response, Frame::claim_count(expected_claim_count));
+        }
+
+        let unknown_reference = ContentReference {
+            segment: "unknown_segment".to_string(),
+            offset: 0,
+            length: 1,
+        };
+        for opcode in [OpCode::Read, OpCode::Release] {
+            let frame = Frame::new(opcode, unknown_reference.encode());
+            protocol::write_frame(&mut stream, &frame).await.unwrap();
+            let response = protocol::read_frame(&mut stream).await.unwrap().unwrap();
+            assert_eq!(response.opcode, OpCode::Error);
+        }
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_group_commit() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let durability = DurabilityPolicy::GroupCommit {
+            interval_ms: 50,
+            max_batch: 3,
+        };
+        let config = ContentRepositoryConfig {
+            base_path: temp_dir.path().to_str().unwrap().to_string(),
+            file_name_prefix: String::from("test_wal_group_commit"),
+            server_port: 0,
+            max_segment_bytes: 1024 * 1024,
+            max_segment_age_secs: 3600,
+            durability,
+            compression: Compression::None,
+            encryption: None,
+        };
+        let cancellation_token = CancellationToken::new();
+        let (tx, rx) = mpsc::channel::<Command>(10);
+        let content_repository = ContentRepository::init(config).await;
+        tokio::spawn(run_content_repo_manager(
+            content_repository,
+            rx,
+            durability,
+            cancellation_token.clone(),
+        ));
+
+        let mut acks = vec![];
+        for content in [&b"first"[..], b"second", b"third"] {
+            let (ack_tx, ack_rx) = oneshot::channel();
+            let command = Command::Data {
+                content: Bytes::copy_from_slice(content),
+                tx: ack_tx,
+            };
+            tx.send(command).await.unwrap();
+            acks.push(ack_rx);
+        }
+        // Group is synced as soon as it is full.
+        let mut offsets = vec![];
+        for ack in acks {
+            offsets.push(ack.await.unwrap().offset);
+        }
+        assert!(offsets.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Groups which do not fill up are synced once the interval passes.
+        let (ack_tx, mut ack_rx) = oneshot::channel();
+        let command = Command::Data {
+            content: Bytes::from_static(b"fourth"),
+            tx: ack_tx,
+        };
+        tx.send(command).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(ack_rx.try_recv().is_err(), "Write acknowledged before sync");
+        let reference = tokio::time::timeout(Duration::from_secs(1), ack_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reference.length, 6);
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_content_repository_client() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = ContentRepositoryConfig {
+            base_path: temp_dir.path().to_str().unwrap().to_string(),
+            file_name_prefix: String::from("test_wal_client"),
+            server_port: 0,
+            max_segment_bytes: 1024 * 1024,
+            max_segment_age_secs: 3600,
+            durability: DurabilityPolicy::PerRecord,
+            compression: Compression::Zstd,
+            encryption: None,
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let cancellation_token = CancellationToken::new();
+        let (tx, rx) = mpsc::channel::<Command>(10);
+        let content_repository = ContentRepository::init(config.clone()).await;
+        tokio::spawn(run_content_repo_manager(
+            content_repository,
+            rx,
+            config.durability,
+            cancellation_token.clone(),
+        ));
+        tokio::spawn(accept_client_connections(
+            listener,
+            tx,
+            cancellation_token.clone(),
+        ));
+
+        let client = ContentRepositoryClient::new(ContentRepositoryClientConfig {
+            address,
+            max_connections: 4,
+            connect_timeout_ms: 1000,
+            request_timeout_ms: 5000,
+            max_retries: 0,
+            retry_backoff_ms: 10,
+        });
+        let writers = (0..8u8).map(|i| {
+            let client = client.clone();
+            tokio::spawn(async move {
+                let content = Bytes::from(vec![i; content_repository_manager::READ_CHUNK_SIZE * 2]);
+                let reference = client.put(content.clone()).await.unwrap();
+                (content, reference)
+            })
+        });
+        for writer in writers.collect::<Vec<_>>() {
+            let (content, reference) = writer.await.unwrap();
+            assert_eq!(reference.length, content.len() as u64);
+            assert_eq!(client.get(&reference).await.unwrap(), content);
+            assert_eq!(client.claim(&reference).await.unwrap(), 2);
+            assert_eq!(client.release(&reference).await.unwrap(), 1);
+            assert_eq!(client.release(&reference).await.unwrap(), 0);
+            assert!(matches!(
+                client.release(&reference).await,
+                Err(ClientError::Server(_))
+            ));
+        }
 
         cancellation_token.cancel();
     }
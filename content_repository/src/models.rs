@@ -1,10 +1,29 @@
+use std::io;
+
 use bytes::Bytes;
-use tokio::sync::oneshot;
+use commons::protocol::ContentReference;
+use tokio::sync::{mpsc, oneshot};
 
 #[derive(Debug)]
 pub enum Command {
     Data {
         content: Bytes,
-        tx: oneshot::Sender<u64>,
+        tx: oneshot::Sender<ContentReference>,
+    },
+    /// Reads the content of the referenced record.
+    /// Content is streamed back in chunks; an error, if any, is always the last item sent.
+    Read {
+        reference: ContentReference,
+        tx: mpsc::Sender<io::Result<Bytes>>,
+    },
+    /// Adds a claim on the referenced content, replies with the number of claims it has now.
+    Claim {
+        reference: ContentReference,
+        tx: oneshot::Sender<io::Result<u64>>,
+    },
+    /// Releases a claim on the referenced content, replies with the number of claims left.
+    Release {
+        reference: ContentReference,
+        tx: oneshot::Sender<io::Result<u64>>,
     },
 }
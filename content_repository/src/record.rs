@@ -0,0 +1,216 @@
+use std::{
+    fmt, io,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use uuid::Uuid;
+
+use crate::{compression::Codec, encryption::Nonce};
+
+/// Every record in a segment file is a fixed size header followed by the payload:
+///
+/// ```text
+/// +---------+---------+--------+---------+----------+------------+-----------+----------------+----------------+---------+---------+
+/// | magic   | version | codec  | key id  | nonce    | content id | timestamp | content length | payload length | CRC32   | payload |
+/// | 4 bytes | 1 byte  | 1 byte | 4 bytes | 12 bytes | 16 bytes   | 8 bytes   | 8 bytes        | 8 bytes        | 4 bytes |         |
+/// +---------+---------+--------+---------+----------+------------+-----------+----------------+----------------+---------+---------+
+/// ```
+///
+/// Integers are big-endian, timestamp is in milliseconds since the UNIX epoch and
+/// the checksum covers every header field before it as well as the payload.
+/// Payload is the content compressed with `codec` and then, unless key id is 0, encrypted
+/// with that key and nonce. Content length is the length of the content before either,
+/// which is what readers get back.
+pub const RECORD_MAGIC: [u8; 4] = *b"RSKR";
+pub const RECORD_VERSION: u8 = 3;
+pub const RECORD_HEADER_LENGTH: usize = 66;
+const CHECKSUM_OFFSET: usize = RECORD_HEADER_LENGTH - 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordHeader {
+    pub codec: Codec,
+    /// Key the payload is encrypted with, if it is encrypted.
+    pub key_id: Option<u32>,
+    pub nonce: Nonce,
+    pub content_id: Uuid,
+    pub timestamp_millis: u64,
+    pub content_length: u64,
+    pub payload_length: u64,
+    pub checksum: u32,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RecordError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownCodec(u8),
+    ChecksumMismatch { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordError::BadMagic => write!(f, "Record does not start with the record magic"),
+            RecordError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported record version: {}", version)
+            }
+            RecordError::UnknownCodec(codec) => write!(f, "Unknown record codec: {}", codec),
+            RecordError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Record checksum mismatch: expected {:#010x}, got {:#010x}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+impl From<RecordError> for io::Error {
+    fn from(e: RecordError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+impl RecordHeader {
+    /// Creates the header of a record holding `content_length` bytes of content,
+    /// stored as `payload` after compressing it with `codec` and encrypting it with the key
+    /// and nonce, if any.
+    pub fn new(
+        codec: Codec,
+        key_id: Option<u32>,
+        nonce: Nonce,
+        content_length: u64,
+        payload: &[u8],
+    ) -> Self {
+        let timestamp_millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let mut header = RecordHeader {
+            codec,
+            key_id,
+            nonce,
+            content_id: Uuid::new_v4(),
+            timestamp_millis,
+            content_length,
+            payload_length: payload.len() as u64,
+            checksum: 0,
+        };
+        let mut hasher = header.checksum_hasher();
+        hasher.update(payload);
+        header.checksum = hasher.finalize();
+        header
+    }
+
+    pub fn encode(&self) -> [u8; RECORD_HEADER_LENGTH] {
+        let mut bytes = [0u8; RECORD_HEADER_LENGTH];
+        bytes[0..4].copy_from_slice(&RECORD_MAGIC);
+        bytes[4] = RECORD_VERSION;
+        bytes[5] = self.codec as u8;
+        bytes[6..10].copy_from_slice(&self.key_id.unwrap_or(0).to_be_bytes());
+        bytes[10..22].copy_from_slice(&self.nonce);
+        bytes[22..38].copy_from_slice(self.content_id.as_bytes());
+        bytes[38..46].copy_from_slice(&self.timestamp_millis.to_be_bytes());
+        bytes[46..54].copy_from_slice(&self.content_length.to_be_bytes());
+        bytes[54..62].copy_from_slice(&self.payload_length.to_be_bytes());
+        bytes[CHECKSUM_OFFSET..].copy_from_slice(&self.checksum.to_be_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8; RECORD_HEADER_LENGTH]) -> Result<Self, RecordError> {
+        if bytes[0..4] != RECORD_MAGIC {
+            return Err(RecordError::BadMagic);
+        }
+        if bytes[4] != RECORD_VERSION {
+            return Err(RecordError::UnsupportedVersion(bytes[4]));
+        }
+        Ok(RecordHeader {
+            codec: Codec::try_from(bytes[5]).map_err(RecordError::UnknownCodec)?,
+            key_id: Some(u32::from_be_bytes(bytes[6..10].try_into().unwrap()))
+                .filter(|key_id| *key_id != 0),
+            nonce: bytes[10..22].try_into().unwrap(),
+            content_id: Uuid::from_slice(&bytes[22..38]).unwrap(),
+            timestamp_millis: u64::from_be_bytes(bytes[38..46].try_into().unwrap()),
+            content_length: u64::from_be_bytes(bytes[46..54].try_into().unwrap()),
+            payload_length: u64::from_be_bytes(bytes[54..62].try_into().unwrap()),
+            checksum: u32::from_be_bytes(bytes[CHECKSUM_OFFSET..].try_into().unwrap()),
+        })
+    }
+
+    /// Returns a hasher which already covers the header fields,
+    /// so that callers only need to feed it the payload, possibly in several chunks.
+    pub fn checksum_hasher(&self) -> crc32fast::Hasher {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&self.encode()[..CHECKSUM_OFFSET]);
+        hasher
+    }
+
+    pub fn verify_checksum(&self, hasher: crc32fast::Hasher) -> Result<(), RecordError> {
+        let actual = hasher.finalize();
+        if actual != self.checksum {
+            return Err(RecordError::ChecksumMismatch {
+                expected: self.checksum,
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encryption::NONCE_LENGTH;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_and_decode_header() {
+        let payload = b"\x00binary\npayload\xff";
+        let header = RecordHeader::new(Codec::Lz4, Some(7), [3u8; NONCE_LENGTH], 42, payload);
+        assert_eq!(header.key_id, Some(7));
+        assert_eq!(header.content_length, 42);
+        assert_eq!(header.payload_length, payload.len() as u64);
+
+        let decoded_header = RecordHeader::decode(&header.encode()).unwrap();
+        assert_eq!(decoded_header, header);
+
+        let mut hasher = decoded_header.checksum_hasher();
+        hasher.update(&payload[..5]);
+        hasher.update(&payload[5..]);
+        assert!(decoded_header.verify_checksum(hasher).is_ok());
+    }
+
+    #[test]
+    fn test_corrupted_record_is_detected() {
+        let payload = b"some content";
+        let header = RecordHeader::new(
+            Codec::None,
+            None,
+            [0u8; NONCE_LENGTH],
+            payload.len() as u64,
+            payload,
+        );
+
+        let mut hasher = header.checksum_hasher();
+        hasher.update(b"some c0ntent");
+        assert!(matches!(
+            header.verify_checksum(hasher),
+            Err(RecordError::ChecksumMismatch { .. })
+        ));
+
+        let mut encoded_header = header.encode();
+        encoded_header[0] = b'X';
+        assert_eq!(
+            RecordHeader::decode(&encoded_header),
+            Err(RecordError::BadMagic)
+        );
+
+        let mut encoded_header = header.encode();
+        encoded_header[5] = 0x7F;
+        assert_eq!(
+            RecordHeader::decode(&encoded_header),
+            Err(RecordError::UnknownCodec(0x7F))
+        );
+    }
+}
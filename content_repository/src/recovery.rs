@@ -0,0 +1,212 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use crate::record::{RecordHeader, RECORD_HEADER_LENGTH};
+
+const SCAN_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Result of validating every record of a segment file.
+pub struct SegmentScan {
+    /// Content length of every valid record, by the offset at which the record starts.
+    pub records: BTreeMap<u64, u64>,
+    /// Length of the segment up to the end of its last valid record.
+    pub valid_length: u64,
+    pub file_length: u64,
+    /// Why scanning stopped before the end of the file, if it did.
+    pub error: Option<io::Error>,
+    /// Whether scanning stopped on a record which runs past the end of the file, as left
+    /// behind by an interrupted write, rather than on a corrupted record.
+    pub torn: bool,
+}
+
+/// Reads the segment from the beginning, validating the header and checksum of each record,
+/// until the end of the file or the first record which is not valid.
+pub fn scan_segment(segment_path: &Path) -> io::Result<SegmentScan> {
+    let file = File::open(segment_path)?;
+    let file_length = file.metadata()?.len();
+    let mut reader = BufReader::with_capacity(SCAN_BUFFER_SIZE, file);
+    let mut scan = SegmentScan {
+        records: BTreeMap::new(),
+        valid_length: 0,
+        file_length,
+        error: None,
+        torn: false,
+    };
+
+    while scan.valid_length < file_length {
+        match read_record(&mut reader, file_length - scan.valid_length) {
+            Ok(header) => {
+                scan.records
+                    .insert(scan.valid_length, header.content_length);
+                scan.valid_length += RECORD_HEADER_LENGTH as u64 + header.payload_length;
+            }
+            Err(e) => {
+                scan.torn = e.kind() == io::ErrorKind::UnexpectedEof;
+                scan.error = Some(e);
+                break;
+            }
+        }
+    }
+    Ok(scan)
+}
+
+/// Reads and validates the next record and returns its header.
+/// Payload is only checked against the checksum, it is not decompressed.
+fn read_record(reader: &mut impl Read, remaining_bytes: u64) -> io::Result<RecordHeader> {
+    if remaining_bytes < RECORD_HEADER_LENGTH as u64 {
+        return Err(torn_record_error(remaining_bytes));
+    }
+    let mut header_bytes = [0u8; RECORD_HEADER_LENGTH];
+    reader.read_exact(&mut header_bytes)?;
+    let header = RecordHeader::decode(&header_bytes)?;
+    if remaining_bytes - (RECORD_HEADER_LENGTH as u64) < header.payload_length {
+        return Err(torn_record_error(remaining_bytes));
+    }
+
+    let mut hasher = header.checksum_hasher();
+    let mut buffer = vec![0u8; SCAN_BUFFER_SIZE];
+    let mut remaining_payload = header.payload_length;
+    while remaining_payload > 0 {
+        let chunk_size = remaining_payload.min(SCAN_BUFFER_SIZE as u64) as usize;
+        reader.read_exact(&mut buffer[..chunk_size])?;
+        hasher.update(&buffer[..chunk_size]);
+        remaining_payload -= chunk_size as u64;
+    }
+    header.verify_checksum(hasher)?;
+    Ok(header)
+}
+
+fn torn_record_error(remaining_bytes: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        format!(
+            "Only {} bytes left, record was not fully written",
+            remaining_bytes
+        ),
+    )
+}
+
+/// Summary of what was found and repaired while opening the content repository.
+#[derive(Debug, Default, PartialEq)]
+pub struct RecoveryReport {
+    pub segments_scanned: usize,
+    pub records_recovered: usize,
+    /// Bytes removed from the end of the most recent segment, left behind by a write which
+    /// was interrupted by a crash.
+    pub torn_bytes_truncated: u64,
+    /// Segments with records which failed validation. These are kept as they are.
+    pub corrupted_segments: Vec<String>,
+    /// Claims on records which no longer exist after truncation.
+    pub dropped_claims: usize,
+}
+
+impl fmt::Display for RecoveryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "scanned {} segments, recovered {} records, truncated {} torn bytes, \
+            found {} corrupted segments {:?}, dropped claims on {} missing records",
+            self.segments_scanned,
+            self.records_recovered,
+            self.torn_bytes_truncated,
+            self.corrupted_segments.len(),
+            self.corrupted_segments,
+            self.dropped_claims
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::tempdir;
+
+    use crate::{
+        compression::{self, Codec},
+        encryption::NONCE_LENGTH,
+    };
+
+    use super::*;
+
+    fn write_record(file: &mut File, content: &[u8]) {
+        let (codec, payload) = compression::compress(Codec::Zstd, content).unwrap();
+        let header = RecordHeader::new(
+            codec,
+            None,
+            [0u8; NONCE_LENGTH],
+            content.len() as u64,
+            &payload,
+        );
+        file.write_all(&header.encode()).unwrap();
+        file.write_all(&payload).unwrap();
+    }
+
+    #[test]
+    fn test_scan_intact_segment() {
+        let temp_dir = tempdir().unwrap();
+        let segment_path = temp_dir.path().join("wal-000001.seg");
+        let mut file = File::create(&segment_path).unwrap();
+        // Large content does not compress, so that its payload takes several scan buffers.
+        let mut state = 1u32;
+        let large_content: Vec<u8> = (0..SCAN_BUFFER_SIZE * 2 + 3)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 24) as u8
+            })
+            .collect();
+        let compressible_content = vec![b'a'; 1000];
+        write_record(&mut file, b"first");
+        write_record(&mut file, &compressible_content);
+        write_record(&mut file, &large_content);
+        write_record(&mut file, b"");
+        let compressed_length = compression::compress(Codec::Zstd, &compressible_content)
+            .unwrap()
+            .1
+            .len();
+
+        let scan = scan_segment(&segment_path).unwrap();
+        assert!(scan.error.is_none());
+        let second_offset = (RECORD_HEADER_LENGTH + 5) as u64;
+        let third_offset = second_offset + (RECORD_HEADER_LENGTH + compressed_length) as u64;
+        let fourth_offset = third_offset + (RECORD_HEADER_LENGTH + large_content.len()) as u64;
+        assert_eq!(
+            scan.records,
+            BTreeMap::from([
+                (0, 5),
+                (second_offset, compressible_content.len() as u64),
+                (third_offset, large_content.len() as u64),
+                (fourth_offset, 0)
+            ])
+        );
+        assert_eq!(scan.valid_length, scan.file_length);
+    }
+
+    #[test]
+    fn test_scan_segment_with_torn_and_corrupted_records() {
+        let temp_dir = tempdir().unwrap();
+        let segment_path = temp_dir.path().join("wal-000001.seg");
+        let mut file = File::create(&segment_path).unwrap();
+        write_record(&mut file, b"first");
+        let header = RecordHeader::new(Codec::None, None, [0u8; NONCE_LENGTH], 11, b"torn record");
+        file.write_all(&header.encode()).unwrap();
+        file.write_all(b"torn").unwrap();
+
+        let scan = scan_segment(&segment_path).unwrap();
+        assert_eq!(scan.records, BTreeMap::from([(0, 5)]));
+        assert_eq!(scan.valid_length, (RECORD_HEADER_LENGTH + 5) as u64);
+        assert!(scan.torn);
+        assert_eq!(scan.error.unwrap().kind(), io::ErrorKind::UnexpectedEof);
+
+        file.write_all(b" recorX").unwrap();
+        let scan = scan_segment(&segment_path).unwrap();
+        assert_eq!(scan.records.len(), 1);
+        assert!(!scan.torn);
+        assert_eq!(scan.error.unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+}
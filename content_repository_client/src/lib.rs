@@ -0,0 +1,547 @@
+use std::{
+    fmt, io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+pub use commons::protocol::ContentReference;
+use commons::{
+    protocol::{self, Frame, OpCode, ProtocolError},
+    ContentRepositoryClientConfig,
+};
+use tokio::{io::BufStream, net::TcpStream, sync::Semaphore};
+
+type Connection = BufStream<TcpStream>;
+
+/// Content larger than this is written in several `Content` frames of at most this size.
+pub const WRITE_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    Protocol(ProtocolError),
+    Timeout(Duration),
+    /// Content repository answered the request with an error message.
+    Server(String),
+    UnexpectedResponse(OpCode),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "I/O error: {}", e),
+            ClientError::Protocol(e) => write!(f, "Protocol error: {}", e),
+            ClientError::Timeout(timeout) => write!(f, "Request timed out after {:?}", timeout),
+            ClientError::Server(message) => write!(f, "Content repository error: {}", message),
+            ClientError::UnexpectedResponse(opcode) => {
+                write!(
+                    f,
+                    "Unexpected response from content repository: {:?}",
+                    opcode
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+impl From<ProtocolError> for ClientError {
+    fn from(e: ProtocolError) -> Self {
+        match e {
+            ProtocolError::Io(e) => ClientError::Io(e),
+            other => ClientError::Protocol(other),
+        }
+    }
+}
+
+/// How far a request got before its attempt failed, which decides whether it is safe to retry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Progress {
+    NotSent,
+    /// Request was written and flushed, so the content repository may have acted on it.
+    Sent,
+}
+
+struct AttemptError {
+    error: ClientError,
+    /// Whether the content repository might have acted on the request.
+    request_processed: bool,
+}
+
+/// Async client of the content repository. Cloning it is cheap, and clones share the same
+/// pool of connections.
+///
+/// Every request is retried with exponential backoff when it fails because of its connection,
+/// as long as it could not be sent. Once a request is sent, the content repository may have
+/// acted on it even if the connection fails before it responds, so only reads, which are safe
+/// to repeat, are retried then. Idle connections which the content repository has closed are
+/// dropped before a request is sent on them.
+#[derive(Clone, Debug)]
+pub struct ContentRepositoryClient {
+    inner: Arc<ClientInner>,
+}
+
+#[derive(Debug)]
+struct ClientInner {
+    config: ContentRepositoryClientConfig,
+    /// Limits the number of connections, idle or in use, to `max_connections`.
+    connection_permits: Semaphore,
+    idle_connections: Mutex<Vec<Connection>>,
+}
+
+impl ContentRepositoryClient {
+    /// Creates a client. Connections are only opened once requests need them.
+    pub fn new(config: ContentRepositoryClientConfig) -> Self {
+        ContentRepositoryClient {
+            inner: Arc::new(ClientInner {
+                connection_permits: Semaphore::new(config.max_connections),
+                idle_connections: Mutex::new(vec![]),
+                config,
+            }),
+        }
+    }
+
+    /// Stores `content` in the content repository and returns where it was stored.
+    /// Stored content starts with one claim, held by the caller.
+    pub async fn put(&self, content: Bytes) -> Result<ContentReference, ClientError> {
+        let request = if !content.is_empty() && content.len() <= WRITE_CHUNK_SIZE {
+            vec![Frame::new(OpCode::Write, content)]
+        } else {
+            let mut request = vec![Frame::new(OpCode::Write, Bytes::new())];
+            for start in (0..content.len()).step_by(WRITE_CHUNK_SIZE) {
+                let end = content.len().min(start + WRITE_CHUNK_SIZE);
+                request.push(Frame::new(OpCode::Content, content.slice(start..end)));
+            }
+            request.push(Frame::new(OpCode::ContentEnd, Bytes::new()));
+            request
+        };
+        let response = self.request(request, false).await?;
+        let frame = expect_single(response, OpCode::Reference)?;
+        Ok(ContentReference::decode(OpCode::Reference, frame.payload)?)
+    }
+
+    /// Reads the whole referenced content.
+    pub async fn get(&self, reference: &ContentReference) -> Result<Bytes, ClientError> {
+        let response = self
+            .request(vec![Frame::new(OpCode::Read, reference.encode())], true)
+            .await?;
+        let mut content = BytesMut::new();
+        for frame in response {
+            match frame.opcode {
+                OpCode::Content => content.extend_from_slice(&frame.payload),
+                OpCode::ContentEnd => return Ok(content.freeze()),
+                other => return Err(unexpected_response(other, frame.payload)),
+            }
+        }
+        unreachable!("Responses always end with a frame other than Content")
+    }
+
+    /// Adds a claim on the referenced content and returns how many claims it has now.
+    pub async fn claim(&self, reference: &ContentReference) -> Result<u64, ClientError> {
+        self.update_claims(Frame::new(OpCode::Claim, reference.encode()))
+            .await
+    }
+
+    /// Releases a claim on the referenced content and returns how many claims it has left.
+    pub async fn release(&self, reference: &ContentReference) -> Result<u64, ClientError> {
+        self.update_claims(Frame::new(OpCode::Release, reference.encode()))
+            .await
+    }
+
+    async fn update_claims(&self, request: Frame) -> Result<u64, ClientError> {
+        let response = self.request(vec![request], false).await?;
+        let frame = expect_single(response, OpCode::ClaimCount)?;
+        let claim_count = frame
+            .payload
+            .as_ref()
+            .try_into()
+            .map_err(|_| ProtocolError::MalformedPayload(OpCode::ClaimCount))?;
+        Ok(u64::from_be_bytes(claim_count))
+    }
+
+    /// Sends the frames of the request and returns every frame of its response, retrying
+    /// failed attempts which are safe to retry.
+    async fn request(
+        &self,
+        request: Vec<Frame>,
+        idempotent: bool,
+    ) -> Result<Vec<Frame>, ClientError> {
+        let config = &self.inner.config;
+        let mut backoff = Duration::from_millis(config.retry_backoff_ms);
+        let mut attempt = 0;
+        loop {
+            let e = match self.attempt(&request).await {
+                Ok(response) => return Ok(response),
+                Err(e) => e,
+            };
+            let retryable = matches!(e.error, ClientError::Io(_) | ClientError::Timeout(_))
+                && (idempotent || !e.request_processed);
+            if !retryable || attempt >= config.max_retries {
+                return Err(e.error);
+            }
+            attempt += 1;
+            tracing::warn!(
+                "{:?} request to content repository failed, retrying in {:?} ({} of {}): {}",
+                request[0].opcode,
+                backoff,
+                attempt,
+                config.max_retries,
+                e.error
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = backoff.saturating_mul(2);
+        }
+    }
+
+    async fn attempt(&self, request: &[Frame]) -> Result<Vec<Frame>, AttemptError> {
+        let _permit = self.inner.connection_permits.acquire().await.unwrap();
+        let idle_connection = loop {
+            let idle_connection = self.inner.idle_connections.lock().unwrap().pop();
+            match idle_connection {
+                Some(connection) if is_closed(&connection) => {
+                    tracing::debug!("Dropping idle connection closed by content repository");
+                }
+                idle_connection => break idle_connection,
+            }
+        };
+        let mut connection = match idle_connection {
+            Some(connection) => connection,
+            None => self.connect().await.map_err(|error| AttemptError {
+                error,
+                request_processed: false,
+            })?,
+        };
+
+        let request_timeout = Duration::from_millis(self.inner.config.request_timeout_ms);
+        let mut progress = Progress::NotSent;
+        let result = match tokio::time::timeout(
+            request_timeout,
+            exchange(&mut connection, request, &mut progress),
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(_) => Err(ClientError::Timeout(request_timeout)),
+        };
+        match result {
+            Ok(response) => {
+                self.inner.idle_connections.lock().unwrap().push(connection);
+                Ok(response)
+            }
+            Err(error) => Err(AttemptError {
+                error,
+                request_processed: progress == Progress::Sent,
+            }),
+        }
+    }
+
+    async fn connect(&self) -> Result<Connection, ClientError> {
+        let connect_timeout = Duration::from_millis(self.inner.config.connect_timeout_ms);
+        let address = &self.inner.config.address;
+        match tokio::time::timeout(connect_timeout, TcpStream::connect(address)).await {
+            Ok(stream) => {
+                let stream = stream?;
+                stream.set_nodelay(true)?;
+                tracing::debug!("Connected to content repository at {}", address);
+                Ok(BufStream::new(stream))
+            }
+            Err(_) => Err(ClientError::Timeout(connect_timeout)),
+        }
+    }
+}
+
+/// Writes the frames of the request and reads response frames up to and including the first
+/// one which is not a chunk of content.
+async fn exchange(
+    connection: &mut Connection,
+    request: &[Frame],
+    progress: &mut Progress,
+) -> Result<Vec<Frame>, ClientError> {
+    for frame in request {
+        protocol::write_frame(connection, frame).await?;
+    }
+    *progress = Progress::Sent;
+    let mut response = vec![];
+    loop {
+        let Some(frame) = protocol::read_frame(connection).await? else {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Content repository closed the connection before responding",
+            )
+            .into());
+        };
+        let is_last_frame = frame.opcode != OpCode::Content;
+        response.push(frame);
+        if is_last_frame {
+            return Ok(response);
+        }
+    }
+}
+
+/// Whether an idle connection can no longer be used. The content repository never sends
+/// anything unrequested, so anything but a read which would block means it was closed.
+fn is_closed(connection: &Connection) -> bool {
+    let mut buf = [0u8; 1];
+    !matches!(
+        connection.get_ref().try_read(&mut buf),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock
+    )
+}
+
+fn expect_single(mut response: Vec<Frame>, opcode: OpCode) -> Result<Frame, ClientError> {
+    let frame = response.pop().unwrap();
+    if frame.opcode != opcode || !response.is_empty() {
+        return Err(unexpected_response(frame.opcode, frame.payload));
+    }
+    Ok(frame)
+}
+
+fn unexpected_response(opcode: OpCode, payload: Bytes) -> ClientError {
+    match opcode {
+        OpCode::Error => ClientError::Server(String::from_utf8_lossy(&payload).to_string()),
+        other => ClientError::UnexpectedResponse(other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn test_config(address: String) -> ContentRepositoryClientConfig {
+        ContentRepositoryClientConfig {
+            address,
+            max_connections: 2,
+            connect_timeout_ms: 1000,
+            request_timeout_ms: 1000,
+            max_retries: 2,
+            retry_backoff_ms: 1,
+        }
+    }
+
+    /// Serves every connection with `handler`, which answers one request at a time and
+    /// returns no frames to close the connection instead. Returns the server address and
+    /// the number of connections accepted so far.
+    async fn spawn_server(
+        handler: impl Fn(Frame) -> Vec<Frame> + Send + Sync + 'static,
+    ) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let accepted_connections = Arc::new(AtomicUsize::new(0));
+        let accepted_connections_clone = accepted_connections.clone();
+        let handler = Arc::new(handler);
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                accepted_connections_clone.fetch_add(1, Ordering::SeqCst);
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    while let Ok(Some(request)) = protocol::read_frame(&mut socket).await {
+                        let response = handler(request);
+                        if response.is_empty() {
+                            break;
+                        }
+                        for frame in response {
+                            protocol::write_frame(&mut socket, &frame).await.unwrap();
+                        }
+                    }
+                });
+            }
+        });
+        (address, accepted_connections)
+    }
+
+    #[tokio::test]
+    async fn test_put_get_claim_and_release() {
+        let store: Arc<Mutex<HashMap<ContentReference, (Bytes, u64)>>> = Arc::default();
+        let (address, accepted_connections) = spawn_server(move |request| {
+            let mut store = store.lock().unwrap();
+            if request.opcode == OpCode::Write {
+                let reference = ContentReference {
+                    segment: "wal-000001.seg".to_string(),
+                    offset: store.len() as u64 * 100,
+                    length: request.payload.len() as u64,
+                };
+                store.insert(reference.clone(), (request.payload, 1));
+                return vec![Frame::reference(&reference)];
+            }
+            let reference = ContentReference::decode(request.opcode, request.payload).unwrap();
+            let Some((content, claims)) = store.get_mut(&reference) else {
+                return vec![Frame::error("No content")];
+            };
+            match request.opcode {
+                OpCode::Read => content
+                    .chunks(4)
+                    .map(|chunk| Frame::new(OpCode::Content, Bytes::copy_from_slice(chunk)))
+                    .chain([Frame::new(OpCode::ContentEnd, Bytes::new())])
+                    .collect(),
+                OpCode::Claim => {
+                    *claims += 1;
+                    vec![Frame::claim_count(*claims)]
+                }
+                _ => {
+                    *claims -= 1;
+                    vec![Frame::claim_count(*claims)]
+                }
+            }
+        })
+        .await;
+        let client = ContentRepositoryClient::new(test_config(address));
+
+        let reference = client
+            .put(Bytes::from_static(b"some content"))
+            .await
+            .unwrap();
+        assert_eq!(reference.length, 12);
+        assert_eq!(&client.get(&reference).await.unwrap()[..], b"some content");
+        assert_eq!(client.claim(&reference).await.unwrap(), 2);
+        assert_eq!(client.release(&reference).await.unwrap(), 1);
+
+        let unknown_reference = ContentReference {
+            segment: "wal-000002.seg".to_string(),
+            offset: 0,
+            length: 1,
+        };
+        match client.get(&unknown_reference).await {
+            Err(ClientError::Server(message)) => assert_eq!(message, "No content"),
+            other => panic!("Expected server error, got {:?}", other),
+        }
+
+        // Requests made one after another share a single connection.
+        assert_eq!(accepted_connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_retried_only_when_safe() {
+        // Server closes every connection as soon as it receives a request.
+        let (address, accepted_connections) = spawn_server(|_| vec![]).await;
+        let client = ContentRepositoryClient::new(test_config(address));
+        let reference = ContentReference {
+            segment: "wal-000001.seg".to_string(),
+            offset: 0,
+            length: 1,
+        };
+
+        assert!(matches!(
+            client.put(Bytes::from_static(b"content")).await,
+            Err(ClientError::Io(_))
+        ));
+        assert_eq!(accepted_connections.load(Ordering::SeqCst), 1);
+        assert!(client.claim(&reference).await.is_err());
+        assert_eq!(accepted_connections.load(Ordering::SeqCst), 2);
+
+        assert!(matches!(
+            client.get(&reference).await,
+            Err(ClientError::Io(_))
+        ));
+        assert_eq!(accepted_connections.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = test_config(listener.local_addr().unwrap().to_string());
+        config.request_timeout_ms = 50;
+        config.max_retries = 1;
+        let client = ContentRepositoryClient::new(config);
+        let reference = ContentReference {
+            segment: "wal-000001.seg".to_string(),
+            offset: 0,
+            length: 1,
+        };
+
+        // Connections are accepted by the OS but nothing ever answers.
+        let started_at = tokio::time::Instant::now();
+        match client.get(&reference).await {
+            Err(ClientError::Timeout(timeout)) => assert_eq!(timeout, Duration::from_millis(50)),
+            other => panic!("Expected timeout, got {:?}", other),
+        }
+        assert!(started_at.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_content_repository() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let client = ContentRepositoryClient::new(test_config(address));
+        assert!(matches!(
+            client.put(Bytes::from_static(b"content")).await,
+            Err(ClientError::Io(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sent_requests_are_not_retried_on_reused_connections() {
+        // Server answers the first request, then crashes after receiving the second one.
+        let requests = Arc::new(AtomicUsize::new(0));
+        let (address, accepted_connections) =
+            spawn_server(move |_| match requests.fetch_add(1, Ordering::SeqCst) {
+                0 => vec![Frame::new(OpCode::ContentEnd, Bytes::new())],
+                _ => vec![],
+            })
+            .await;
+        let client = ContentRepositoryClient::new(test_config(address));
+        let reference = ContentReference {
+            segment: "wal-000001.seg".to_string(),
+            offset: 0,
+            length: 1,
+        };
+
+        client.get(&reference).await.unwrap();
+        assert!(matches!(
+            client.put(Bytes::from_static(b"content")).await,
+            Err(ClientError::Io(_))
+        ));
+        assert_eq!(accepted_connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_closed_idle_connections_are_not_reused() {
+        // Server closes every connection once it has answered a request.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let request = protocol::read_frame(&mut socket).await.unwrap().unwrap();
+                let reference = ContentReference {
+                    segment: "wal-000001.seg".to_string(),
+                    offset: 0,
+                    length: request.payload.len() as u64,
+                };
+                protocol::write_frame(&mut socket, &Frame::reference(&reference))
+                    .await
+                    .unwrap();
+            }
+        });
+        let client = ContentRepositoryClient::new(test_config(address));
+
+        client.put(Bytes::from_static(b"content")).await.unwrap();
+        // Gives the client time to see that the connection was closed.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(
+            client
+                .put(Bytes::from_static(b"more"))
+                .await
+                .unwrap()
+                .length,
+            4
+        );
+    }
+}
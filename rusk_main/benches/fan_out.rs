@@ -0,0 +1,88 @@
+//! Throughput of sending a large packet from one processor to N peers, with packet data held in a
+//! `Vec<u8>` (copied for every peer) compared to `Bytes` (shared between peers).
+//!
+//! Run with `cargo bench --package rusk_main --bench fan_out`.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::{runtime::Runtime, sync::mpsc};
+use uuid::Uuid;
+
+const PAYLOAD_SIZE: usize = 1024 * 1024;
+const PEER_COUNTS: [usize; 4] = [1, 4, 16, 64];
+
+#[derive(Clone)]
+struct Packet<T> {
+    _id: Uuid,
+    data: T,
+}
+
+type Peer<T> = (mpsc::Sender<T>, mpsc::Receiver<T>);
+
+/// Mirrors the fan-out of the processors: the packet is cloned into the channel of every peer.
+async fn fan_out<T: Clone + AsRef<[u8]>>(
+    packet: &Packet<T>,
+    peers: &mut [Peer<Packet<T>>],
+) -> usize {
+    for (tx, _) in peers.iter() {
+        tx.send(packet.clone()).await.unwrap();
+    }
+    let mut received_bytes = 0;
+    for (_, rx) in peers.iter_mut() {
+        received_bytes += rx.recv().await.unwrap().data.as_ref().len();
+    }
+    received_bytes
+}
+
+fn peers<T>(count: usize) -> Vec<Peer<T>> {
+    (0..count).map(|_| mpsc::channel(1)).collect()
+}
+
+async fn timed_fan_out<T: Clone + AsRef<[u8]>>(
+    packet: Packet<T>,
+    peer_count: usize,
+    iterations: u64,
+) -> Duration {
+    let mut peers = peers(peer_count);
+    let start = Instant::now();
+    for _ in 0..iterations {
+        black_box(fan_out(&packet, &mut peers).await);
+    }
+    start.elapsed()
+}
+
+fn bench_fan_out(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let payload = vec![7u8; PAYLOAD_SIZE];
+    let vec_packet = Packet {
+        _id: Uuid::new_v4(),
+        data: payload.clone(),
+    };
+    let bytes_packet = Packet {
+        _id: Uuid::new_v4(),
+        data: Bytes::from(payload),
+    };
+
+    let mut group = c.benchmark_group("fan_out_1MiB");
+    for peer_count in PEER_COUNTS {
+        group.throughput(Throughput::Bytes((PAYLOAD_SIZE * peer_count) as u64));
+        group.bench_function(BenchmarkId::new("vec", peer_count), |b| {
+            b.to_async(&runtime)
+                .iter_custom(|iterations| timed_fan_out(vec_packet.clone(), peer_count, iterations))
+        });
+        group.bench_function(BenchmarkId::new("bytes", peer_count), |b| {
+            b.to_async(&runtime).iter_custom(|iterations| {
+                timed_fan_out(bytes_packet.clone(), peer_count, iterations)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_fan_out);
+criterion_main!(benches);
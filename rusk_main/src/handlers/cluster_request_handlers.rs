@@ -1,11 +1,24 @@
-use axum::{extract::State, Json};
+use axum::{
+    extract::{Query, State},
+    Json,
+};
 use http::StatusCode;
 use tokio::sync::oneshot;
 use uuid::Uuid;
 
-use crate::{processors::models::ProcessorCommand, AppState};
+use crate::{
+    processors::{
+        bulletins::Bulletin,
+        connection::{BackPressure, Connection, ConnectionQueue, DEFAULT_DATA_SIZE_THRESHOLD},
+        models::{relationships, ProcessorCommand},
+        properties::Properties,
+    },
+    AppState,
+};
 
-use super::models::{ClusterInfo, ProcessorConnectionRequest, ProcessorInfo};
+use super::models::{
+    BulletinQuery, ClusterInfo, ConnectionDetails, ProcessorConnectionRequest, ProcessorInfo,
+};
 
 #[tracing::instrument]
 pub async fn is_alive() -> &'static str {
@@ -27,58 +40,101 @@ pub async fn get_cluster_info(
     let cluster_info = ClusterInfo {
         cluster_name: "Rusk Default Cluster".to_string(),
         processors: processors_in_cluster,
+        bulletins: server_state.bulletin_board.bulletins(None, None),
     };
 
     Ok(Json(cluster_info))
 }
 
+/// Latest warnings and errors of the processors, the oldest first.
+#[tracing::instrument]
+pub async fn get_bulletins(
+    State(server_state): State<AppState>,
+    Query(query): Query<BulletinQuery>,
+) -> Json<Vec<Bulletin>> {
+    Json(
+        server_state
+            .bulletin_board
+            .bulletins(query.processor_id.as_deref(), query.since),
+    )
+}
+
 #[tracing::instrument]
 pub async fn connect_processors(
     State(server_state): State<AppState>,
     Json(payload): Json<ProcessorConnectionRequest>,
-) -> Result<Json<ProcessorInfo>, StatusCode> {
+) -> Result<Json<ConnectionDetails>, StatusCode> {
     let source_processor_id = Uuid::parse_str(&payload.source_processor_id).unwrap();
     let destination_processor_id = Uuid::parse_str(&payload.destination_processor_id).unwrap();
 
-    match server_state
+    let Some(inbox) = server_state
         .peers_tx
         .lock()
         .await
         .get(&destination_processor_id)
-    {
-        Some(tx) => {
-            match server_state
-                .parent_processor_tx
-                .lock()
-                .await
-                .get(&source_processor_id)
-            {
-                Some(source_tx) => {
-                    let (oneshot_tx, oneshot_rx) = oneshot::channel();
-                    let command = ProcessorCommand::Connect {
-                        destination_processor_id,
-                        destination_processor_tx: tx.clone(),
-                        resp: oneshot_tx,
-                    };
-
-                    source_tx.send(command).await.unwrap();
-                    let processor_current_status = oneshot_rx.await.unwrap();
-                    let result = Json(ProcessorInfo {
-                        processor_id: source_processor_id.to_string(),
-                        status: processor_current_status,
-                        packets_processed_count: 0,
-                    });
-                    return Ok(result);
-                }
-                None => {
-                    return Err(StatusCode::NOT_FOUND);
-                }
-            }
-        }
-        None => {
-            return Err(StatusCode::NOT_FOUND);
+        .cloned()
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let Some(source_tx) = server_state
+        .parent_processor_tx
+        .lock()
+        .await
+        .get(&source_processor_id)
+        .cloned()
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let back_pressure = BackPressure {
+        object_threshold: payload
+            .object_threshold
+            .unwrap_or(server_state.config.processor_queue_length as u64),
+        data_size_threshold: payload
+            .data_size_threshold
+            .unwrap_or_else(|| DEFAULT_DATA_SIZE_THRESHOLD.to_string()),
+    };
+    let queue = match ConnectionQueue::new(back_pressure, &inbox) {
+        Ok(queue) => queue,
+        Err(e) => {
+            tracing::error!("Invalid backpressure thresholds: {}", e);
+            return Err(StatusCode::BAD_REQUEST);
         }
-    }
+    };
+
+    let connection = Connection {
+        id: Uuid::new_v4(),
+        source_processor_id,
+        relationship: payload
+            .relationship
+            .unwrap_or_else(|| relationships::SUCCESS.to_string()),
+        destination_processor_id,
+        queue,
+    };
+
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+    let command = ProcessorCommand::Connect {
+        relationship: connection.relationship.clone(),
+        destination_processor_id,
+        queue: connection.queue.clone(),
+        resp: oneshot_tx,
+    };
+    source_tx.send(command).await.unwrap();
+    oneshot_rx.await.unwrap();
+
+    let connection_id = connection.id;
+    let mut connections = server_state.connections.lock().await;
+    // The source replaces the queue of an earlier connection between the same processors.
+    connections.retain(|_, other| {
+        !other.links(
+            source_processor_id,
+            Some(&connection.relationship),
+            destination_processor_id,
+        )
+    });
+    connections.insert(connection_id, connection);
+    Ok(Json(ConnectionDetails {
+        connection_id: connection_id.to_string(),
+    }))
 }
 
 #[tracing::instrument]
@@ -98,16 +154,34 @@ pub async fn disconnect_processors(
         Some(source_tx) => {
             let (oneshot_tx, oneshot_rx) = oneshot::channel();
             let command = ProcessorCommand::Disconnect {
+                relationship: payload.relationship.clone(),
                 destination_processor_id,
                 resp: oneshot_tx,
             };
 
             source_tx.send(command).await.unwrap();
             let processor_current_status = oneshot_rx.await.unwrap();
+            server_state
+                .connections
+                .lock()
+                .await
+                .retain(|_, connection| {
+                    !connection.links(
+                        source_processor_id,
+                        payload.relationship.as_deref(),
+                        destination_processor_id,
+                    )
+                });
             let result = Json(ProcessorInfo {
                 processor_id: source_processor_id.to_string(),
                 status: processor_current_status,
                 packets_processed_count: 0,
+                properties: Properties::new(),
+                scheduling: None,
+                last_packet: None,
+                connections: vec![],
+                back_pressured: false,
+                last_error: None,
             });
             return Ok(result);
         }
@@ -122,15 +196,37 @@ mod tests {
 
     use std::sync::Arc;
 
-    use axum::{routing::get, Router};
+    use axum::{
+        routing::{get, patch, post},
+        Router,
+    };
     use axum_test::TestServer;
     use commons::MainConfig;
+    use serde_json::json;
     use std::collections::HashMap;
     use tokio_util::sync::CancellationToken;
+    use uuid::Uuid;
+
+    use crate::processors::properties::Properties;
 
     use tokio::sync::Mutex;
 
-    use crate::{handlers::models::ClusterInfo, processors::models::ProcessorType};
+    use crate::{
+        handlers::{
+            models::{
+                ClusterInfo, ConnectionDetails, ProcessorConnectionRequest, RequestDetails,
+                ResponseDetails,
+            },
+            processor_request_handlers,
+        },
+        processors::{
+            bulletins::{Bulletin, BulletinBoard, Severity},
+            connection::{BackPressure, ConnectionQueue, Inbox},
+            content_offloader::ContentOffloader,
+            models::{relationships, Attributes, InMemoryPacket, Message},
+            provenance::ProvenanceRepository,
+        },
+    };
 
     #[tokio::test]
     async fn test_is_alive() {
@@ -146,19 +242,23 @@ mod tests {
         let config: MainConfig = MainConfig {
             server_port: 8080,
             processor_queue_length: 10,
+            content_offload_threshold_bytes: None,
+            processor_restart_backoff_ms: None,
+            provenance_log_path: None,
         };
 
-        let processor_mappings = HashMap::from([
-            ("adder".to_string(), ProcessorType::SourceProcessor),
-            ("doubler".to_string(), ProcessorType::Other),
-        ]);
         let cancellation_token = CancellationToken::new();
         let state = super::AppState {
             config,
             cancellation_token: cancellation_token.clone(),
             peers_tx: Arc::new(Mutex::new(HashMap::new())),
             parent_processor_tx: Arc::new(Mutex::new(HashMap::new())),
-            processor_types_mappings: Arc::new(Mutex::new(processor_mappings)),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            processor_cancellation_tokens: Arc::new(Mutex::new(HashMap::new())),
+            processor_registry: Arc::new(crate::processor_registry()),
+            content_offloader: ContentOffloader::disabled(),
+            bulletin_board: BulletinBoard::default(),
+            provenance: ProvenanceRepository::default(),
         };
         let app = Router::new()
             .route("/get_cluster_info", get(super::get_cluster_info))
@@ -172,7 +272,120 @@ mod tests {
         let expected_cluster_details = ClusterInfo {
             cluster_name: "Rusk Default Cluster".to_string(),
             processors: vec![],
+            bulletins: vec![],
         };
         assert_eq!(actual_cluster_details, expected_cluster_details);
     }
+
+    #[tokio::test]
+    async fn test_connect_processors_to_relationship() {
+        let config: MainConfig = MainConfig {
+            server_port: 8080,
+            processor_queue_length: 10,
+            content_offload_threshold_bytes: None,
+            processor_restart_backoff_ms: None,
+            provenance_log_path: None,
+        };
+        let cancellation_token = CancellationToken::new();
+        let state = super::AppState {
+            config,
+            cancellation_token: cancellation_token.clone(),
+            peers_tx: Arc::new(Mutex::new(HashMap::new())),
+            parent_processor_tx: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            processor_cancellation_tokens: Arc::new(Mutex::new(HashMap::new())),
+            processor_registry: Arc::new(crate::processor_registry()),
+            content_offloader: ContentOffloader::disabled(),
+            bulletin_board: BulletinBoard::default(),
+            provenance: ProvenanceRepository::default(),
+        };
+        // Stands in for a processor which handles the failures of the doubler.
+        let failure_handler_id = Uuid::new_v4();
+        let failure_handler_inbox = Inbox::default();
+        state
+            .peers_tx
+            .lock()
+            .await
+            .insert(failure_handler_id, failure_handler_inbox.clone());
+        let peers_tx = state.peers_tx.clone();
+        let app = Router::new()
+            .route(
+                "/create_processor",
+                post(processor_request_handlers::create_processor),
+            )
+            .route(
+                "/start_processor",
+                patch(processor_request_handlers::start_processor),
+            )
+            .route("/connect", post(super::connect_processors))
+            .route("/bulletins", get(super::get_bulletins))
+            .with_state(state);
+        let test_server = TestServer::new(app).unwrap();
+
+        let doubler_id = test_server
+            .post("/create_processor")
+            .json(&json!(RequestDetails {
+                processor_name: "doubler".to_string(),
+                processor_id: None,
+                properties: Properties::new(),
+                scheduling: None,
+            }))
+            .await
+            .json::<ResponseDetails>()
+            .processor_id;
+        test_server
+            .patch("/start_processor")
+            .json(&json!(RequestDetails {
+                processor_name: "doubler".to_string(),
+                processor_id: Some(doubler_id.clone()),
+                properties: Properties::new(),
+                scheduling: None,
+            }))
+            .await
+            .assert_status_ok();
+        let connection_details = test_server
+            .post("/connect")
+            .json(&json!(ProcessorConnectionRequest {
+                source_processor_id: doubler_id.clone(),
+                destination_processor_id: failure_handler_id.to_string(),
+                relationship: Some(relationships::FAILURE.to_string()),
+                object_threshold: None,
+                data_size_threshold: None,
+            }))
+            .await
+            .json::<ConnectionDetails>();
+        assert!(Uuid::parse_str(&connection_details.connection_id).is_ok());
+
+        let doubler_inbox = peers_tx.lock().await[&Uuid::parse_str(&doubler_id).unwrap()].clone();
+        let doubler_queue = ConnectionQueue::new(BackPressure::default(), &doubler_inbox).unwrap();
+        for data in [vec![1], vec![200]] {
+            doubler_queue.push(Message::InMemoryMessage(InMemoryPacket {
+                id: Uuid::new_v4(),
+                attributes: Attributes::new(),
+                data: data.into(),
+            }));
+        }
+        match failure_handler_inbox.recv().await {
+            Message::InMemoryMessage(packet) => assert_eq!(packet.data, vec![200]),
+            other => panic!("Expected InMemoryMessage, got {:?}", other),
+        }
+
+        // The doubler warns about the packet it could not double.
+        let bulletins = test_server
+            .get("/bulletins")
+            .add_query_param("processor_id", &doubler_id)
+            .await
+            .json::<Vec<Bulletin>>();
+        assert_eq!(bulletins.len(), 1);
+        assert_eq!(bulletins[0].severity, Severity::Warning);
+        assert!(bulletins[0].message.contains("without overflow"));
+        assert!(test_server
+            .get("/bulletins")
+            .add_query_param("since", bulletins[0].timestamp + 1)
+            .await
+            .json::<Vec<Bulletin>>()
+            .is_empty());
+
+        cancellation_token.cancel();
+    }
 }
@@ -0,0 +1,479 @@
+use std::time::UNIX_EPOCH;
+
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Json,
+};
+use http::{header, StatusCode};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::{
+    processors::{
+        connection::Connection,
+        models::{attributes, ProcessorCommand},
+        provenance::{ProvenanceEvent, ProvenanceEventType},
+    },
+    AppState,
+};
+
+use super::{
+    errors::ApiError,
+    models::{ConnectionInfo, PacketInfo, PurgeDetails, QueuedPacketInfo},
+};
+
+fn connection_info(connection: &Connection) -> ConnectionInfo {
+    ConnectionInfo {
+        connection_id: connection.id.to_string(),
+        source_processor_id: connection.source_processor_id.to_string(),
+        relationship: connection.relationship.clone(),
+        destination_processor_id: connection.destination_processor_id.to_string(),
+        queued_count: connection.queue.len() as u64,
+        queued_bytes: connection.queue.size_bytes(),
+        back_pressure: connection.queue.back_pressure().clone(),
+        is_full: connection.queue.is_full(),
+    }
+}
+
+/// Every connection, ordered by source, relationship and destination.
+#[tracing::instrument]
+pub async fn get_connections(State(server_state): State<AppState>) -> Json<Vec<ConnectionInfo>> {
+    let mut connections: Vec<ConnectionInfo> = server_state
+        .connections
+        .lock()
+        .await
+        .values()
+        .map(connection_info)
+        .collect();
+    connections.sort_by(|a, b| {
+        (
+            &a.source_processor_id,
+            &a.relationship,
+            &a.destination_processor_id,
+        )
+            .cmp(&(
+                &b.source_processor_id,
+                &b.relationship,
+                &b.destination_processor_id,
+            ))
+    });
+    Json(connections)
+}
+
+async fn find_connection(
+    server_state: &AppState,
+    connection_id: String,
+) -> Result<Connection, ApiError> {
+    let connection = match Uuid::parse_str(&connection_id) {
+        Ok(id) => server_state.connections.lock().await.get(&id).cloned(),
+        Err(_) => None,
+    };
+    connection.ok_or(ApiError::ConnectionNotFound(connection_id))
+}
+
+#[tracing::instrument]
+pub async fn get_connection(
+    State(server_state): State<AppState>,
+    Path(connection_id): Path<String>,
+) -> Result<Json<ConnectionInfo>, ApiError> {
+    let connection = find_connection(&server_state, connection_id).await?;
+    Ok(Json(connection_info(&connection)))
+}
+
+/// Packets queued on the connection, in the order the destination takes them.
+#[tracing::instrument]
+pub async fn get_queued_packets(
+    State(server_state): State<AppState>,
+    Path(connection_id): Path<String>,
+) -> Result<Json<Vec<QueuedPacketInfo>>, ApiError> {
+    let connection = find_connection(&server_state, connection_id).await?;
+    let queued_packets = connection
+        .queue
+        .queued()
+        .iter()
+        .map(|queued_message| QueuedPacketInfo {
+            packet: PacketInfo::from(&queued_message.message),
+            enqueued_at: queued_message
+                .enqueued_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+        })
+        .collect();
+    Ok(Json(queued_packets))
+}
+
+/// Data of a queued packet, which stays in the queue. Its content type is the `mime.type`
+/// attribute of the packet.
+#[tracing::instrument]
+pub async fn get_queued_packet_content(
+    State(server_state): State<AppState>,
+    Path((connection_id, packet_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let connection = find_connection(&server_state, connection_id).await?;
+    let message = Uuid::parse_str(&packet_id)
+        .ok()
+        .and_then(|id| connection.queue.find(id))
+        .ok_or(ApiError::PacketNotFound(packet_id))?;
+    let data = server_state
+        .content_offloader
+        .read(&message)
+        .await
+        .map_err(|e| ApiError::ContentUnavailable(e.to_string()))?;
+    let content_type = message
+        .attributes()
+        .get(attributes::MIME_TYPE)
+        .cloned()
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    Ok(([(header::CONTENT_TYPE, content_type)], data))
+}
+
+/// Drops every packet queued on the connection.
+#[tracing::instrument]
+pub async fn purge_queue(
+    State(server_state): State<AppState>,
+    Path(connection_id): Path<String>,
+) -> Result<Json<PurgeDetails>, ApiError> {
+    let connection = find_connection(&server_state, connection_id).await?;
+    let messages = connection.queue.purge();
+    let purged_count = messages.len() as u64;
+    for message in messages {
+        server_state.provenance.record(
+            ProvenanceEvent::of_message(
+                ProvenanceEventType::Drop,
+                connection.destination_processor_id,
+                &message,
+            )
+            .with_relationship(&connection.relationship)
+            .with_details(format!("Purged from connection {}", connection.id)),
+        );
+        server_state.content_offloader.discard(message).await;
+    }
+    tracing::info!(
+        "Purged {} packets from connection {}",
+        purged_count,
+        connection.id
+    );
+    Ok(Json(PurgeDetails {
+        connection_id: connection.id.to_string(),
+        purged_count,
+    }))
+}
+
+/// Removes the connection. Packets which are already queued are still taken by the
+/// destination.
+#[tracing::instrument]
+pub async fn delete_connection(
+    State(server_state): State<AppState>,
+    Path(connection_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let connection = match Uuid::parse_str(&connection_id) {
+        Ok(id) => server_state.connections.lock().await.remove(&id),
+        Err(_) => None,
+    };
+    let Some(connection) = connection else {
+        return Err(ApiError::ConnectionNotFound(connection_id));
+    };
+
+    let source_tx = server_state
+        .parent_processor_tx
+        .lock()
+        .await
+        .get(&connection.source_processor_id)
+        .cloned();
+    match source_tx {
+        Some(source_tx) => {
+            let (oneshot_tx, oneshot_rx) = oneshot::channel();
+            let command = ProcessorCommand::Disconnect {
+                relationship: Some(connection.relationship.clone()),
+                destination_processor_id: connection.destination_processor_id,
+                resp: oneshot_tx,
+            };
+            source_tx.send(command).await.unwrap();
+            oneshot_rx.await.unwrap();
+        }
+        // Nothing sends to the queue any more.
+        None => connection.queue.close(),
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use axum::{
+        routing::{get, patch, post},
+        Router,
+    };
+    use axum_test::TestServer;
+    use commons::MainConfig;
+    use http::{header, StatusCode};
+    use serde_json::json;
+    use tokio::sync::Mutex;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        handlers::{
+            cluster_request_handlers,
+            models::{
+                ConnectionDetails, ConnectionInfo, ProcessorConnectionRequest, ProcessorInfo,
+                PurgeDetails, QueuedPacketInfo, RequestDetails, ResponseDetails,
+            },
+            processor_request_handlers,
+        },
+        processors::{
+            bulletins::BulletinBoard,
+            connection::BackPressure,
+            content_offloader::ContentOffloader,
+            models::{attributes, relationships},
+            properties::Properties,
+            provenance::ProvenanceRepository,
+        },
+    };
+
+    fn test_server() -> TestServer {
+        let state = super::AppState {
+            config: MainConfig {
+                server_port: 8080,
+                processor_queue_length: 10,
+                content_offload_threshold_bytes: None,
+                processor_restart_backoff_ms: None,
+                provenance_log_path: None,
+            },
+            cancellation_token: CancellationToken::new(),
+            peers_tx: Arc::new(Mutex::new(HashMap::new())),
+            parent_processor_tx: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            processor_cancellation_tokens: Arc::new(Mutex::new(HashMap::new())),
+            processor_registry: Arc::new(crate::processor_registry()),
+            content_offloader: ContentOffloader::disabled(),
+            bulletin_board: BulletinBoard::default(),
+            provenance: ProvenanceRepository::default(),
+        };
+        let app = Router::new()
+            .route(
+                "/processor/create",
+                post(processor_request_handlers::create_processor),
+            )
+            .route(
+                "/processor/connect",
+                post(cluster_request_handlers::connect_processors),
+            )
+            .route(
+                "/processor/start",
+                patch(processor_request_handlers::start_processor),
+            )
+            .route(
+                "/processor/stop",
+                patch(processor_request_handlers::stop_processor),
+            )
+            .route(
+                "/processor/get_info/:processor_id",
+                get(processor_request_handlers::get_processor_info),
+            )
+            .route("/connections", get(super::get_connections))
+            .route(
+                "/connections/:connection_id",
+                get(super::get_connection).delete(super::delete_connection),
+            )
+            .route(
+                "/connections/:connection_id/queue",
+                get(super::get_queued_packets).delete(super::purge_queue),
+            )
+            .route(
+                "/connections/:connection_id/queue/:packet_id/content",
+                get(super::get_queued_packet_content),
+            )
+            .with_state(state);
+        TestServer::new(app).unwrap()
+    }
+
+    async fn create_processor(test_server: &TestServer, processor_name: &str) -> String {
+        test_server
+            .post("/processor/create")
+            .json(&json!(RequestDetails {
+                processor_name: processor_name.to_string(),
+                processor_id: None,
+                properties: Properties::new(),
+                scheduling: None,
+            }))
+            .await
+            .json::<ResponseDetails>()
+            .processor_id
+    }
+
+    async fn connect(
+        test_server: &TestServer,
+        source_processor_id: &str,
+        destination_processor_id: &str,
+        object_threshold: Option<u64>,
+    ) -> String {
+        test_server
+            .post("/processor/connect")
+            .json(&json!(ProcessorConnectionRequest {
+                source_processor_id: source_processor_id.to_string(),
+                destination_processor_id: destination_processor_id.to_string(),
+                relationship: None,
+                object_threshold,
+                data_size_threshold: None,
+            }))
+            .await
+            .json::<ConnectionDetails>()
+            .connection_id
+    }
+
+    async fn update_status(test_server: &TestServer, processor_id: &str, command: &str) {
+        test_server
+            .patch(&format!("/processor/{}", command))
+            .json(&json!(RequestDetails {
+                processor_name: "adder".to_string(),
+                processor_id: Some(processor_id.to_string()),
+                properties: Properties::new(),
+                scheduling: None,
+            }))
+            .await
+            .assert_status_ok();
+    }
+
+    #[tokio::test]
+    async fn test_connections() {
+        let test_server = test_server();
+        let adder_id = create_processor(&test_server, "adder").await;
+        let doubler_id = create_processor(&test_server, "doubler").await;
+        let connection_id = connect(&test_server, &adder_id, &doubler_id, Some(5)).await;
+
+        let expected_connection = ConnectionInfo {
+            connection_id: connection_id.clone(),
+            source_processor_id: adder_id.clone(),
+            relationship: relationships::SUCCESS.to_string(),
+            destination_processor_id: doubler_id.clone(),
+            queued_count: 0,
+            queued_bytes: 0,
+            back_pressure: BackPressure {
+                object_threshold: 5,
+                data_size_threshold: "1 GB".to_string(),
+            },
+            is_full: false,
+        };
+        assert_eq!(
+            test_server
+                .get("/connections")
+                .await
+                .json::<Vec<ConnectionInfo>>(),
+            vec![expected_connection.clone()]
+        );
+        assert_eq!(
+            test_server
+                .get(&format!("/connections/{}", connection_id))
+                .await
+                .json::<ConnectionInfo>(),
+            expected_connection
+        );
+
+        // Connecting the same processors again replaces the connection.
+        let new_connection_id = connect(&test_server, &adder_id, &doubler_id, None).await;
+        let connections = test_server
+            .get("/connections")
+            .await
+            .json::<Vec<ConnectionInfo>>();
+        assert_eq!(connections.len(), 1);
+        assert_eq!(connections[0].connection_id, new_connection_id);
+        test_server
+            .get(&format!("/connections/{}", connection_id))
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+
+        test_server
+            .delete(&format!("/connections/{}", new_connection_id))
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        assert!(test_server
+            .get("/connections")
+            .await
+            .json::<Vec<ConnectionInfo>>()
+            .is_empty());
+        test_server
+            .delete(&format!("/connections/{}", new_connection_id))
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_delete_connection_disconnects_source() {
+        let test_server = test_server();
+        let adder_id = create_processor(&test_server, "adder").await;
+        let doubler_id = create_processor(&test_server, "doubler").await;
+        let connection_id = connect(&test_server, &adder_id, &doubler_id, Some(1000)).await;
+        update_status(&test_server, &adder_id, "start").await;
+        tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+
+        let connection_url = format!("/connections/{}", connection_id);
+        let queued_count = test_server
+            .get(&connection_url)
+            .await
+            .json::<ConnectionInfo>()
+            .queued_count;
+        assert!(queued_count > 0);
+        test_server
+            .delete(&connection_url)
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        test_server
+            .get(&connection_url)
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        let adder_info = test_server
+            .get(&format!("/processor/get_info/{}", adder_id))
+            .await
+            .json::<ProcessorInfo>();
+        assert!(adder_info.connections.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_inspect_and_purge_queue() {
+        let test_server = test_server();
+        let adder_id = create_processor(&test_server, "adder").await;
+        let doubler_id = create_processor(&test_server, "doubler").await;
+        let connection_id = connect(&test_server, &adder_id, &doubler_id, Some(1000)).await;
+        update_status(&test_server, &adder_id, "start").await;
+        tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+        update_status(&test_server, &adder_id, "stop").await;
+
+        let queue_url = format!("/connections/{}/queue", connection_id);
+        let queued_packets = test_server
+            .get(&queue_url)
+            .await
+            .json::<Vec<QueuedPacketInfo>>();
+        assert!(!queued_packets.is_empty());
+        let packet = &queued_packets[0].packet;
+        assert!(packet.attributes.contains_key(attributes::MIME_TYPE));
+
+        let response = test_server
+            .get(&format!("{}/{}/content", queue_url, packet.packet_id))
+            .await;
+        response.assert_status_ok();
+        response.assert_header(
+            header::CONTENT_TYPE,
+            packet.attributes[attributes::MIME_TYPE].as_str(),
+        );
+        assert_eq!(response.as_bytes().len() as u64, packet.size_bytes);
+        test_server
+            .get(&format!("{}/{}/content", queue_url, uuid::Uuid::new_v4()))
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+
+        let purge_details = test_server.delete(&queue_url).await.json::<PurgeDetails>();
+        assert_eq!(purge_details.purged_count, queued_packets.len() as u64);
+        assert!(test_server
+            .get(&queue_url)
+            .await
+            .json::<Vec<QueuedPacketInfo>>()
+            .is_empty());
+        test_server
+            .get(&format!("{}/{}/content", queue_url, packet.packet_id))
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+    }
+}
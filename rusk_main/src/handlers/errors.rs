@@ -0,0 +1,96 @@
+use std::fmt;
+
+use axum::{
+    response::{IntoResponse, Response},
+    Json,
+};
+use http::StatusCode;
+
+use crate::processors::{properties::PropertyError, scheduling::SchedulingError};
+
+use super::models::ErrorResponse;
+
+/// Error of a request, which is sent back as an `ErrorResponse`.
+#[derive(Debug)]
+pub enum ApiError {
+    UnknownProcessorType(String),
+    ProcessorNotFound(String),
+    ConnectionNotFound(String),
+    /// Packet, by id, which is not queued on the connection.
+    PacketNotFound(String),
+    /// Content of a packet which could not be read from the content repository.
+    ContentUnavailable(String),
+    /// Packet, by id, without provenance events.
+    ProvenanceNotFound(String),
+    ProcessorRunning(String),
+    /// Processor, by id, with packets queued on its incoming connections.
+    QueueNotEmpty(String),
+    InvalidProperties(Vec<PropertyError>),
+    InvalidScheduling(SchedulingError),
+}
+
+impl ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::UnknownProcessorType(_)
+            | ApiError::InvalidProperties(_)
+            | ApiError::InvalidScheduling(_) => StatusCode::BAD_REQUEST,
+            ApiError::ProcessorNotFound(_)
+            | ApiError::ConnectionNotFound(_)
+            | ApiError::PacketNotFound(_)
+            | ApiError::ProvenanceNotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::ContentUnavailable(_) => StatusCode::BAD_GATEWAY,
+            ApiError::ProcessorRunning(_) | ApiError::QueueNotEmpty(_) => StatusCode::CONFLICT,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::UnknownProcessorType(name) => write!(f, "Unknown processor type: {}", name),
+            ApiError::ProcessorNotFound(id) => write!(f, "Processor not found: {}", id),
+            ApiError::ConnectionNotFound(id) => write!(f, "Connection not found: {}", id),
+            ApiError::PacketNotFound(id) => write!(f, "Packet not queued: {}", id),
+            ApiError::ProvenanceNotFound(id) => write!(f, "No provenance events of packet: {}", id),
+            ApiError::ContentUnavailable(message) => {
+                write!(f, "Content could not be read: {}", message)
+            }
+            ApiError::ProcessorRunning(id) => {
+                write!(f, "Processor {} must be stopped first", id)
+            }
+            ApiError::QueueNotEmpty(id) => {
+                write!(f, "Processor {} has packets queued on its connections", id)
+            }
+            ApiError::InvalidProperties(errors) => {
+                write!(f, "Invalid properties: ")?;
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            }
+            ApiError::InvalidScheduling(error) => write!(f, "Invalid scheduling: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status_code = self.status_code();
+        let message = self.to_string();
+        let property_errors = match self {
+            ApiError::InvalidProperties(errors) => errors,
+            _ => vec![],
+        };
+        let body = ErrorResponse {
+            message,
+            property_errors,
+        };
+        (status_code, Json(body)).into_response()
+    }
+}
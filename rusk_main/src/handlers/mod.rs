@@ -1,3 +1,6 @@
 pub mod cluster_request_handlers;
+pub mod connection_request_handlers;
+pub mod errors;
 pub mod models;
 pub mod processor_request_handlers;
+pub mod provenance_request_handlers;
@@ -1,21 +1,66 @@
-use crate::processors::models::ProcessorStatus;
+use crate::processors::{
+    bulletins::Bulletin,
+    connection::BackPressure,
+    models::{Attributes, ProcessorStatus},
+    properties::{Properties, PropertyError},
+    scheduling::SchedulingSettings,
+};
 
 #[derive(PartialEq, Debug, serde::Deserialize, serde::Serialize)]
 pub struct ClusterInfo {
     pub cluster_name: String,
     pub processors: Vec<ProcessorInfo>,
+    /// Latest warnings and errors of the processors.
+    #[serde(default)]
+    pub bulletins: Vec<Bulletin>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct BulletinQuery {
+    /// Only bulletins of this processor when set.
+    pub processor_id: Option<String>,
+    /// Only bulletins published at or after this time, in milliseconds since the Unix epoch.
+    pub since: Option<u64>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct ProvenanceQuery {
+    pub packet_id: String,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct RequestDetails {
     pub processor_name: String,
     pub processor_id: Option<String>,
+    #[serde(default)]
+    pub properties: Properties,
+    /// Scheduling settings of a new processor. The default ones of its type when not set.
+    #[serde(default)]
+    pub scheduling: Option<SchedulingSettings>,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct ProcessorConnectionRequest {
     pub source_processor_id: String,
     pub destination_processor_id: String,
+    /// Relationship of the source processor to connect. Connects to success and disconnects
+    /// from every relationship when not set.
+    #[serde(default)]
+    pub relationship: Option<String>,
+    /// Backpressure thresholds of a new connection. `processor_queue_length` packets and 1 GB
+    /// when not set.
+    #[serde(default)]
+    pub object_threshold: Option<u64>,
+    #[serde(default)]
+    pub data_size_threshold: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct DeleteProcessorParams {
+    /// Deletes the processor even while it is running or has queued packets, which are
+    /// dropped.
+    #[serde(default)]
+    pub force: bool,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -29,4 +74,76 @@ pub struct ProcessorInfo {
     pub processor_id: String,
     pub status: ProcessorStatus,
     pub packets_processed_count: u64,
+    pub properties: Properties,
+    pub scheduling: Option<SchedulingSettings>,
+    /// Last packet the processor sent to its peers.
+    pub last_packet: Option<PacketInfo>,
+    /// Connections from the processor to its destinations.
+    #[serde(default)]
+    pub connections: Vec<ConnectionStatus>,
+    /// Whether the processor is held back because one of its connections is full.
+    #[serde(default)]
+    pub back_pressured: bool,
+    /// Why the processor last became `Errored`, like the message of a panic.
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize, PartialEq)]
+pub struct ConnectionStatus {
+    pub relationship: String,
+    pub destination_processor_id: String,
+    pub queued_count: u64,
+    pub queued_bytes: u64,
+    pub back_pressure: BackPressure,
+    /// Whether the queue has reached one of its backpressure thresholds.
+    pub is_full: bool,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, PartialEq)]
+pub struct ConnectionDetails {
+    pub connection_id: String,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize, PartialEq)]
+pub struct ConnectionInfo {
+    pub connection_id: String,
+    pub source_processor_id: String,
+    pub relationship: String,
+    pub destination_processor_id: String,
+    pub queued_count: u64,
+    pub queued_bytes: u64,
+    pub back_pressure: BackPressure,
+    /// Whether the queue has reached one of its backpressure thresholds.
+    pub is_full: bool,
+}
+
+/// Packet waiting in the queue of a connection.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize, PartialEq)]
+pub struct QueuedPacketInfo {
+    #[serde(flatten)]
+    pub packet: PacketInfo,
+    /// Time the packet was queued at, in milliseconds since the Unix epoch.
+    pub enqueued_at: u64,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, PartialEq)]
+pub struct PurgeDetails {
+    pub connection_id: String,
+    pub purged_count: u64,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize, PartialEq)]
+pub struct PacketInfo {
+    pub packet_id: String,
+    pub size_bytes: u64,
+    pub attributes: Attributes,
+}
+
+/// Body of the responses to requests which could not be handled.
+#[derive(Debug, serde::Deserialize, serde::Serialize, PartialEq)]
+pub struct ErrorResponse {
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub property_errors: Vec<PropertyError>,
 }
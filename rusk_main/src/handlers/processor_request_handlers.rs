@@ -1,126 +1,116 @@
-use std::collections::HashMap;
+use std::time::Duration;
 
 use crate::{
-    adder_func, doubler_func,
     processors::{
-        base_processor::{SinkProcessor, SourceProcessor},
-        in_memory_processor::InMemoryProcessor,
-        in_memory_source_processor::InMemorySourceProcessor,
-        models::{Message, ProcessorCommand, ProcessorStatus, ProcessorType},
+        connection::Inbox,
+        models::{
+            ProcessorCommand, ProcessorStatus, ProcessorType, UpdatePropertiesError,
+            UpdateSchedulingError,
+        },
+        properties::{ProcessorProperties, Properties},
+        provenance::{ProvenanceEvent, ProvenanceEventType},
+        registry::{ProcessorSetup, ProcessorTypeInfo},
+        scheduling::{Schedule, SchedulingSettings},
+        supervisor::RestartPolicy,
     },
     AppState,
 };
-use axum::{debug_handler, extract::Path, extract::State, Json};
+use axum::{
+    debug_handler,
+    extract::{Path, Query, State},
+    Json,
+};
 use http::StatusCode;
 use tokio::sync::{mpsc, oneshot};
-use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
-use super::models::{ProcessorInfo, RequestDetails, ResponseDetails};
+use super::{
+    errors::ApiError,
+    models::{DeleteProcessorParams, ProcessorInfo, RequestDetails, ResponseDetails},
+};
 
 const PARENT_PROCESSOR_CHANNEL_SIZE: usize = 10;
 
-fn create_source_processor(
-    processor_name: String,
-    processor_to_parent_rx: mpsc::Receiver<ProcessorCommand>,
-    cancellation_token: CancellationToken,
-) -> Uuid {
-    let mut processor = InMemorySourceProcessor::new(
-        processor_name,
-        processor_to_parent_rx,
-        HashMap::new(),
-        cancellation_token,
-    );
-
-    let processor_id = processor.processor_id;
-
-    tokio::spawn(async move {
-        processor.run(adder_func).await;
-    });
-    processor_id
-}
-
-fn create_normal_processor(
-    processor_name: String,
-    parent_rx: mpsc::Receiver<ProcessorCommand>,
-    peers_rx: mpsc::Receiver<Message>,
-    cancellation_token: CancellationToken,
-) -> Uuid {
-    let mut processor =
-        InMemoryProcessor::new(processor_name, peers_rx, parent_rx, cancellation_token);
-    let processor_id = processor.processor_id;
-    tokio::spawn(async move {
-        processor.run(doubler_func).await;
-    });
-    processor_id
-}
-
 #[tracing::instrument]
 pub async fn create_processor(
     State(server_state): State<AppState>,
     Json(payload): Json<RequestDetails>,
-) -> Result<Json<ResponseDetails>, StatusCode> {
+) -> Result<Json<ResponseDetails>, ApiError> {
+    let Some(registered_processor) = server_state
+        .processor_registry
+        .get(payload.processor_name.as_str())
+    else {
+        return Err(ApiError::UnknownProcessorType(payload.processor_name));
+    };
+    let properties = ProcessorProperties::new(
+        registered_processor.descriptor.properties.clone(),
+        &payload.properties,
+    )
+    .map_err(ApiError::InvalidProperties)?;
+    let schedule = match payload.scheduling {
+        Some(scheduling) => Schedule::new(scheduling, registered_processor.processor_type)
+            .map_err(ApiError::InvalidScheduling)?,
+        None => Schedule::default_for(registered_processor.processor_type),
+    };
+
     let (parent_to_processor_tx, processor_to_parent_rx) =
         mpsc::channel::<ProcessorCommand>(PARENT_PROCESSOR_CHANNEL_SIZE);
+    let inbox = match registered_processor.processor_type {
+        ProcessorType::SourceProcessor => None,
+        ProcessorType::Other => Some(Inbox::default()),
+    };
+    let cancellation_token = server_state.cancellation_token.child_token();
+
+    let processor_id = (registered_processor.factory)(ProcessorSetup {
+        processor_name: payload.processor_name.clone(),
+        parent_rx: processor_to_parent_rx,
+        parent_tx: parent_to_processor_tx.clone(),
+        inbox: inbox.clone(),
+        properties,
+        schedule,
+        content_offloader: server_state.content_offloader.clone(),
+        bulletin_board: server_state.bulletin_board.clone(),
+        provenance: server_state.provenance.clone(),
+        cancellation_token: cancellation_token.clone(),
+        restart_policy: server_state
+            .config
+            .processor_restart_backoff_ms
+            .map(|backoff_ms| RestartPolicy {
+                initial_backoff: Duration::from_millis(backoff_ms),
+            }),
+    });
 
-    match server_state
-        .processor_types_mappings
+    server_state
+        .parent_processor_tx
         .lock()
         .await
-        .get(payload.processor_name.as_str())
-    {
-        Some(ProcessorType::SourceProcessor) => {
-            let processor_id = create_source_processor(
-                payload.processor_name.clone(),
-                processor_to_parent_rx,
-                server_state.cancellation_token.clone(),
-            );
-
-            server_state
-                .parent_processor_tx
-                .lock()
-                .await
-                .insert(processor_id, parent_to_processor_tx);
-
-            let result = Json(ResponseDetails {
-                processor_id: processor_id.to_string(),
-                status: ProcessorStatus::Stopped,
-            });
-            return Ok(result);
-        }
-        Some(ProcessorType::Other) => {
-            let (peers_tx, peers_rx) =
-                mpsc::channel::<Message>(server_state.config.processor_queue_length);
-
-            let processor_id = create_normal_processor(
-                payload.processor_name.clone(),
-                processor_to_parent_rx,
-                peers_rx,
-                server_state.cancellation_token.clone(),
-            );
-
-            server_state
-                .parent_processor_tx
-                .lock()
-                .await
-                .insert(processor_id, parent_to_processor_tx);
+        .insert(processor_id, parent_to_processor_tx);
+    server_state
+        .processor_cancellation_tokens
+        .lock()
+        .await
+        .insert(processor_id, cancellation_token);
+
+    if let Some(inbox) = inbox {
+        server_state
+            .peers_tx
+            .lock()
+            .await
+            .insert(processor_id, inbox);
+    }
 
-            server_state
-                .peers_tx
-                .lock()
-                .await
-                .insert(processor_id, peers_tx);
+    let result = Json(ResponseDetails {
+        processor_id: processor_id.to_string(),
+        status: ProcessorStatus::Stopped,
+    });
+    Ok(result)
+}
 
-            let result = Json(ResponseDetails {
-                processor_id: processor_id.to_string(),
-                status: ProcessorStatus::Stopped,
-            });
-            return Ok(result);
-        }
-        None => {
-            return Err(StatusCode::BAD_REQUEST);
-        }
-    }
+#[tracing::instrument]
+pub async fn get_processor_types(
+    State(server_state): State<AppState>,
+) -> Json<Vec<ProcessorTypeInfo>> {
+    Json(server_state.processor_registry.processor_types())
 }
 
 #[tracing::instrument]
@@ -199,9 +189,106 @@ pub async fn stop_processor(
     }
 }
 
+/// Deletes a stopped processor whose incoming connections are empty, or any processor with
+/// `force=true`. Its connections are removed and the packets queued for it are dropped.
 #[tracing::instrument]
-pub async fn delete_processor(State(server_state): State<AppState>) -> &'static str {
-    "NOT IMPLEMENTED YET!"
+pub async fn delete_processor(
+    State(server_state): State<AppState>,
+    Path(processor_id): Path<String>,
+    Query(params): Query<DeleteProcessorParams>,
+) -> Result<StatusCode, ApiError> {
+    let Ok(id) = Uuid::parse_str(&processor_id) else {
+        return Err(ApiError::ProcessorNotFound(processor_id));
+    };
+    let Some(tx) = server_state
+        .parent_processor_tx
+        .lock()
+        .await
+        .get(&id)
+        .cloned()
+    else {
+        return Err(ApiError::ProcessorNotFound(processor_id));
+    };
+
+    if !params.force {
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        tx.send(ProcessorCommand::GetStatus { resp: oneshot_tx })
+            .await
+            .unwrap();
+        if oneshot_rx.await.unwrap() == ProcessorStatus::Running {
+            return Err(ApiError::ProcessorRunning(processor_id));
+        }
+        if server_state
+            .connections
+            .lock()
+            .await
+            .values()
+            .any(|connection| {
+                connection.destination_processor_id == id && connection.queue.len() > 0
+            })
+        {
+            return Err(ApiError::QueueNotEmpty(processor_id));
+        }
+    }
+
+    let mut removed_connections = vec![];
+    server_state
+        .connections
+        .lock()
+        .await
+        .retain(|_, connection| {
+            let removed =
+                connection.source_processor_id == id || connection.destination_processor_id == id;
+            if removed {
+                removed_connections.push(connection.clone());
+            }
+            !removed
+        });
+    for connection in removed_connections {
+        if connection.destination_processor_id != id {
+            connection.queue.close();
+            continue;
+        }
+        if connection.source_processor_id != id {
+            let source_tx = server_state
+                .parent_processor_tx
+                .lock()
+                .await
+                .get(&connection.source_processor_id)
+                .cloned();
+            if let Some(source_tx) = source_tx {
+                let (oneshot_tx, oneshot_rx) = oneshot::channel();
+                let command = ProcessorCommand::Disconnect {
+                    relationship: Some(connection.relationship.clone()),
+                    destination_processor_id: id,
+                    resp: oneshot_tx,
+                };
+                source_tx.send(command).await.unwrap();
+                oneshot_rx.await.unwrap();
+            }
+        }
+        for message in connection.queue.purge() {
+            server_state.provenance.record(
+                ProvenanceEvent::of_message(ProvenanceEventType::Drop, id, &message)
+                    .with_relationship(&connection.relationship)
+                    .with_details("Processor deleted".to_string()),
+            );
+            server_state.content_offloader.discard(message).await;
+        }
+    }
+
+    server_state.parent_processor_tx.lock().await.remove(&id);
+    server_state.peers_tx.lock().await.remove(&id);
+    if let Some(cancellation_token) = server_state
+        .processor_cancellation_tokens
+        .lock()
+        .await
+        .remove(&id)
+    {
+        cancellation_token.cancel();
+    }
+    tracing::info!("Deleted processor {}", id);
+    Ok(StatusCode::NO_CONTENT)
 }
 
 #[tracing::instrument]
@@ -211,7 +298,7 @@ pub async fn get_status(
 ) -> Result<Json<ResponseDetails>, StatusCode> {
     // TODO: How to get processor status?
     let processor_id = Uuid::parse_str(&payload.processor_id.unwrap()).unwrap();
-    println!("checking status of Processor ID: {}", processor_id);
+    tracing::debug!("checking status of Processor ID: {}", processor_id);
     match server_state
         .parent_processor_tx
         .lock()
@@ -264,23 +351,114 @@ pub async fn get_processor_info(
     }
 }
 
+#[tracing::instrument]
+pub async fn update_processor_properties(
+    State(server_state): State<AppState>,
+    Path(processor_id): Path<String>,
+    Json(properties): Json<Properties>,
+) -> Result<Json<Properties>, ApiError> {
+    let Ok(id) = Uuid::parse_str(&processor_id) else {
+        return Err(ApiError::ProcessorNotFound(processor_id));
+    };
+    let Some(tx) = server_state
+        .parent_processor_tx
+        .lock()
+        .await
+        .get(&id)
+        .cloned()
+    else {
+        return Err(ApiError::ProcessorNotFound(processor_id));
+    };
+
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+    let command = ProcessorCommand::UpdateProperties {
+        properties,
+        resp: oneshot_tx,
+    };
+    tx.send(command).await.unwrap();
+    match oneshot_rx.await.unwrap() {
+        Ok(properties) => Ok(Json(properties)),
+        Err(UpdatePropertiesError::ProcessorRunning) => {
+            Err(ApiError::ProcessorRunning(processor_id))
+        }
+        Err(UpdatePropertiesError::InvalidProperties(errors)) => {
+            Err(ApiError::InvalidProperties(errors))
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn update_processor_scheduling(
+    State(server_state): State<AppState>,
+    Path(processor_id): Path<String>,
+    Json(scheduling): Json<SchedulingSettings>,
+) -> Result<Json<SchedulingSettings>, ApiError> {
+    let Ok(id) = Uuid::parse_str(&processor_id) else {
+        return Err(ApiError::ProcessorNotFound(processor_id));
+    };
+    let Some(tx) = server_state
+        .parent_processor_tx
+        .lock()
+        .await
+        .get(&id)
+        .cloned()
+    else {
+        return Err(ApiError::ProcessorNotFound(processor_id));
+    };
+
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+    let command = ProcessorCommand::UpdateScheduling {
+        scheduling,
+        resp: oneshot_tx,
+    };
+    tx.send(command).await.unwrap();
+    match oneshot_rx.await.unwrap() {
+        Ok(scheduling) => Ok(Json(scheduling)),
+        Err(UpdateSchedulingError::ProcessorRunning) => {
+            Err(ApiError::ProcessorRunning(processor_id))
+        }
+        Err(UpdateSchedulingError::InvalidScheduling(error)) => {
+            Err(ApiError::InvalidScheduling(error))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{collections::HashMap, sync::Arc};
 
     use axum::{
-        routing::{get, patch, post},
+        routing::{delete, get, patch, post},
         Router,
     };
     use axum_test::TestServer;
+    use bytes::Bytes;
     use commons::MainConfig;
     use serde_json::json;
     use tokio::sync::Mutex;
     use tokio_util::sync::CancellationToken;
+    use uuid::Uuid;
+
+    use http::StatusCode;
+
+    use crate::processors::properties::Properties;
 
     use crate::{
-        handlers::models::{RequestDetails, ResponseDetails},
-        processors::models::ProcessorType,
+        handlers::{
+            cluster_request_handlers,
+            models::{
+                ErrorResponse, ProcessorConnectionRequest, ProcessorInfo, RequestDetails,
+                ResponseDetails,
+            },
+        },
+        processors::{
+            bulletins::BulletinBoard,
+            content_offloader::ContentOffloader,
+            models::{Attributes, InMemoryPacket, Message, ProcessorType},
+            provenance::{ProvenanceEventType, ProvenanceRepository},
+            registry::ProcessorTypeInfo,
+            scheduling::{SchedulingSettings, SchedulingStrategy},
+        },
     };
 
     #[tokio::test]
@@ -290,32 +468,35 @@ mod tests {
         let config: MainConfig = MainConfig {
             server_port: 8080,
             processor_queue_length: 10,
+            content_offload_threshold_bytes: None,
+            processor_restart_backoff_ms: None,
+            provenance_log_path: None,
         };
         let cancellation_token = CancellationToken::new();
-        let processor_mappings = HashMap::from([
-            (
-                "adder_processor".to_string(),
-                ProcessorType::SourceProcessor,
-            ),
-            ("doubler_processor".to_string(), ProcessorType::Other),
-        ]);
 
         let state = super::AppState {
             config,
             cancellation_token: cancellation_token.clone(),
             peers_tx: Arc::new(Mutex::new(HashMap::new())),
             parent_processor_tx: Arc::new(Mutex::new(HashMap::new())),
-            processor_types_mappings: Arc::new(Mutex::new(processor_mappings)),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            processor_cancellation_tokens: Arc::new(Mutex::new(HashMap::new())),
+            processor_registry: Arc::new(crate::processor_registry()),
+            content_offloader: ContentOffloader::disabled(),
+            bulletin_board: BulletinBoard::default(),
+            provenance: ProvenanceRepository::default(),
         };
 
         let app = Router::new()
-            .route(&route, post(super::create_processor))
+            .route(route, post(super::create_processor))
             .with_state(state);
 
         let test_server = TestServer::new(app).unwrap();
         let request_body: RequestDetails = RequestDetails {
-            processor_name: "adder_processor".to_string(),
+            processor_name: "adder".to_string(),
             processor_id: None,
+            properties: Properties::new(),
+            scheduling: None,
         };
 
         let response = test_server.post(route).json(&json!(request_body)).await;
@@ -327,6 +508,280 @@ mod tests {
         cancellation_token.cancel();
     }
 
+    #[tokio::test]
+    async fn test_processor_types() {
+        let state = super::AppState {
+            config: MainConfig {
+                server_port: 8080,
+                processor_queue_length: 10,
+                content_offload_threshold_bytes: None,
+                processor_restart_backoff_ms: None,
+                provenance_log_path: None,
+            },
+            cancellation_token: CancellationToken::new(),
+            peers_tx: Arc::new(Mutex::new(HashMap::new())),
+            parent_processor_tx: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            processor_cancellation_tokens: Arc::new(Mutex::new(HashMap::new())),
+            processor_registry: Arc::new(crate::processor_registry()),
+            content_offloader: ContentOffloader::disabled(),
+            bulletin_board: BulletinBoard::default(),
+            provenance: ProvenanceRepository::default(),
+        };
+        let app = Router::new()
+            .route("/create_processor", post(super::create_processor))
+            .route("/processor/types", get(super::get_processor_types))
+            .with_state(state);
+        let test_server = TestServer::new(app).unwrap();
+
+        let response = test_server
+            .post("/create_processor")
+            .json(&json!(RequestDetails {
+                processor_name: "tripler".to_string(),
+                processor_id: None,
+                properties: Properties::new(),
+                scheduling: None,
+            }))
+            .await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        let processor_types = test_server
+            .get("/processor/types")
+            .await
+            .json::<Vec<ProcessorTypeInfo>>();
+        let names: Vec<&str> = processor_types
+            .iter()
+            .map(|processor_type| processor_type.descriptor.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["adder", "doubler"]);
+        assert_eq!(
+            processor_types[0].processor_type,
+            ProcessorType::SourceProcessor
+        );
+        assert!(processor_types[1]
+            .descriptor
+            .relationships
+            .contains(&"failure".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_processor_properties() {
+        let cancellation_token = CancellationToken::new();
+        let state = super::AppState {
+            config: MainConfig {
+                server_port: 8080,
+                processor_queue_length: 10,
+                content_offload_threshold_bytes: None,
+                processor_restart_backoff_ms: None,
+                provenance_log_path: None,
+            },
+            cancellation_token: cancellation_token.clone(),
+            peers_tx: Arc::new(Mutex::new(HashMap::new())),
+            parent_processor_tx: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            processor_cancellation_tokens: Arc::new(Mutex::new(HashMap::new())),
+            processor_registry: Arc::new(crate::processor_registry()),
+            content_offloader: ContentOffloader::disabled(),
+            bulletin_board: BulletinBoard::default(),
+            provenance: ProvenanceRepository::default(),
+        };
+        let app = Router::new()
+            .route("/create_processor", post(super::create_processor))
+            .route("/start_processor", patch(super::start_processor))
+            .route(
+                "/processor/:processor_id/properties",
+                patch(super::update_processor_properties),
+            )
+            .with_state(state);
+        let test_server = TestServer::new(app).unwrap();
+
+        let response = test_server
+            .post("/create_processor")
+            .json(&json!(RequestDetails {
+                processor_name: "adder".to_string(),
+                processor_id: None,
+                properties: Properties::from([
+                    ("packet_size".to_string(), "0".to_string()),
+                    ("colour".to_string(), "red".to_string()),
+                ]),
+                scheduling: None,
+            }))
+            .await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let error_response = response.json::<ErrorResponse>();
+        let invalid_properties: Vec<&str> = error_response
+            .property_errors
+            .iter()
+            .map(|error| error.property.as_str())
+            .collect();
+        assert_eq!(invalid_properties, vec!["colour", "packet_size"]);
+
+        let processor_id = test_server
+            .post("/create_processor")
+            .json(&json!(RequestDetails {
+                processor_name: "adder".to_string(),
+                processor_id: None,
+                properties: Properties::from([("packet_size".to_string(), "8".to_string())]),
+                scheduling: None,
+            }))
+            .await
+            .json::<ResponseDetails>()
+            .processor_id;
+        let properties_route = format!("/processor/{}/properties", processor_id);
+
+        let response = test_server
+            .patch(&properties_route)
+            .json(&json!({"max_value": "300"}))
+            .await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        let response = test_server
+            .patch(&properties_route)
+            .json(&json!({"max_value": "10"}))
+            .await;
+        response.assert_status_ok();
+        assert_eq!(
+            response.json::<Properties>(),
+            Properties::from([
+                ("packet_size".to_string(), "8".to_string()),
+                ("max_value".to_string(), "10".to_string()),
+            ])
+        );
+
+        test_server
+            .patch("/start_processor")
+            .json(&json!(RequestDetails {
+                processor_name: "adder".to_string(),
+                processor_id: Some(processor_id),
+                properties: Properties::new(),
+                scheduling: None,
+            }))
+            .await
+            .assert_status_ok();
+        let response = test_server
+            .patch(&properties_route)
+            .json(&json!({"max_value": "20"}))
+            .await;
+        response.assert_status(StatusCode::CONFLICT);
+
+        test_server
+            .patch(&format!("/processor/{}/properties", uuid::Uuid::new_v4()))
+            .json(&json!({}))
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_processor_scheduling() {
+        let cancellation_token = CancellationToken::new();
+        let state = super::AppState {
+            config: MainConfig {
+                server_port: 8080,
+                processor_queue_length: 10,
+                content_offload_threshold_bytes: None,
+                processor_restart_backoff_ms: None,
+                provenance_log_path: None,
+            },
+            cancellation_token: cancellation_token.clone(),
+            peers_tx: Arc::new(Mutex::new(HashMap::new())),
+            parent_processor_tx: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            processor_cancellation_tokens: Arc::new(Mutex::new(HashMap::new())),
+            processor_registry: Arc::new(crate::processor_registry()),
+            content_offloader: ContentOffloader::disabled(),
+            bulletin_board: BulletinBoard::default(),
+            provenance: ProvenanceRepository::default(),
+        };
+        let app = Router::new()
+            .route("/create_processor", post(super::create_processor))
+            .route("/start_processor", patch(super::start_processor))
+            .route(
+                "/processor/get_info/:processor_id",
+                get(super::get_processor_info),
+            )
+            .route(
+                "/processor/:processor_id/scheduling",
+                patch(super::update_processor_scheduling),
+            )
+            .with_state(state);
+        let test_server = TestServer::new(app).unwrap();
+        let cron_scheduling = SchedulingSettings {
+            strategy: SchedulingStrategy::Cron,
+            run_schedule: Some("*/5 * * * * *".to_string()),
+            concurrent_tasks: 2,
+            run_duration: "10 ms".to_string(),
+        };
+
+        test_server
+            .post("/create_processor")
+            .json(&json!(RequestDetails {
+                processor_name: "doubler".to_string(),
+                processor_id: None,
+                properties: Properties::new(),
+                scheduling: Some(cron_scheduling.clone()),
+            }))
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+
+        let processor_id = test_server
+            .post("/create_processor")
+            .json(&json!(RequestDetails {
+                processor_name: "adder".to_string(),
+                processor_id: None,
+                properties: Properties::new(),
+                scheduling: Some(cron_scheduling.clone()),
+            }))
+            .await
+            .json::<ResponseDetails>()
+            .processor_id;
+        let processor_info = test_server
+            .get(&format!("/processor/get_info/{}", processor_id))
+            .await
+            .json::<ProcessorInfo>();
+        assert_eq!(processor_info.scheduling, Some(cron_scheduling));
+
+        let scheduling_route = format!("/processor/{}/scheduling", processor_id);
+        test_server
+            .patch(&scheduling_route)
+            .json(&json!({"strategy": "timer_driven"}))
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+        let response = test_server
+            .patch(&scheduling_route)
+            .json(&json!({"strategy": "timer_driven", "run_schedule": "1 sec"}))
+            .await;
+        response.assert_status_ok();
+        assert_eq!(
+            response.json::<SchedulingSettings>(),
+            SchedulingSettings {
+                strategy: SchedulingStrategy::Timer,
+                run_schedule: Some("1 sec".to_string()),
+                concurrent_tasks: 1,
+                run_duration: "0 ms".to_string(),
+            }
+        );
+
+        test_server
+            .patch("/start_processor")
+            .json(&json!(RequestDetails {
+                processor_name: "adder".to_string(),
+                processor_id: Some(processor_id),
+                properties: Properties::new(),
+                scheduling: None,
+            }))
+            .await
+            .assert_status_ok();
+        test_server
+            .patch(&scheduling_route)
+            .json(&json!({"strategy": "event_driven"}))
+            .await
+            .assert_status(StatusCode::CONFLICT);
+
+        cancellation_token.cancel();
+    }
+
     #[tokio::test]
     async fn test_get_processor_status() {
         let create_processor_route = "/create_processor";
@@ -337,14 +792,10 @@ mod tests {
         let config: MainConfig = MainConfig {
             server_port: 8080,
             processor_queue_length: 10,
+            content_offload_threshold_bytes: None,
+            processor_restart_backoff_ms: None,
+            provenance_log_path: None,
         };
-        let processor_mappings = HashMap::from([
-            (
-                "adder_processor".to_string(),
-                ProcessorType::SourceProcessor,
-            ),
-            ("doubler_processor".to_string(), ProcessorType::Other),
-        ]);
 
         let cancellation_token = CancellationToken::new();
         let state = super::AppState {
@@ -352,14 +803,19 @@ mod tests {
             cancellation_token: cancellation_token.clone(),
             peers_tx: Arc::new(Mutex::new(HashMap::new())),
             parent_processor_tx: Arc::new(Mutex::new(HashMap::new())),
-            processor_types_mappings: Arc::new(Mutex::new(processor_mappings)),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            processor_cancellation_tokens: Arc::new(Mutex::new(HashMap::new())),
+            processor_registry: Arc::new(crate::processor_registry()),
+            content_offloader: ContentOffloader::disabled(),
+            bulletin_board: BulletinBoard::default(),
+            provenance: ProvenanceRepository::default(),
         };
 
         let app = Router::new()
-            .route(&create_processor_route, post(super::create_processor))
-            .route(&start_processor_route, patch(super::start_processor))
-            .route(&stop_processor_route, patch(super::stop_processor))
-            .route(&get_status_route, get(super::get_status))
+            .route(create_processor_route, post(super::create_processor))
+            .route(start_processor_route, patch(super::start_processor))
+            .route(stop_processor_route, patch(super::stop_processor))
+            .route(get_status_route, get(super::get_status))
             .with_state(state);
 
         let test_server = TestServer::new(app).unwrap();
@@ -367,8 +823,10 @@ mod tests {
         let create_processor_response = test_server
             .post(create_processor_route)
             .json(&json!(RequestDetails {
-                processor_name: "adder_processor".to_string(),
+                processor_name: "adder".to_string(),
                 processor_id: None,
+                properties: Properties::new(),
+                scheduling: None,
             }))
             .await;
         create_processor_response.assert_status_ok();
@@ -376,8 +834,10 @@ mod tests {
         assert_eq!(response_details.status, super::ProcessorStatus::Stopped);
 
         let request_body: RequestDetails = RequestDetails {
-            processor_name: "adder_processor".to_string(),
+            processor_name: "adder".to_string(),
             processor_id: Some(response_details.processor_id),
+            properties: Properties::new(),
+            scheduling: None,
         };
         let get_status_response = test_server
             .get(get_status_route)
@@ -441,34 +901,37 @@ mod tests {
         let config: MainConfig = MainConfig {
             server_port: 8080,
             processor_queue_length: 10,
+            content_offload_threshold_bytes: None,
+            processor_restart_backoff_ms: None,
+            provenance_log_path: None,
         };
 
-        let processor_mappings = HashMap::from([
-            (
-                "adder_processor".to_string(),
-                ProcessorType::SourceProcessor,
-            ),
-            ("doubler_processor".to_string(), ProcessorType::Other),
-        ]);
         let cancellation_token = CancellationToken::new();
         let state = super::AppState {
             config,
             cancellation_token: cancellation_token.clone(),
             peers_tx: Arc::new(Mutex::new(HashMap::new())),
             parent_processor_tx: Arc::new(Mutex::new(HashMap::new())),
-            processor_types_mappings: Arc::new(Mutex::new(processor_mappings)),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            processor_cancellation_tokens: Arc::new(Mutex::new(HashMap::new())),
+            processor_registry: Arc::new(crate::processor_registry()),
+            content_offloader: ContentOffloader::disabled(),
+            bulletin_board: BulletinBoard::default(),
+            provenance: ProvenanceRepository::default(),
         };
 
         let app = Router::new()
-            .route(&create_processor_route, post(super::create_processor))
-            .route(&start_processor_route, patch(super::start_processor))
-            .route(&stop_processor_route, patch(super::stop_processor))
+            .route(create_processor_route, post(super::create_processor))
+            .route(start_processor_route, patch(super::start_processor))
+            .route(stop_processor_route, patch(super::stop_processor))
             .with_state(state);
 
         let test_server = TestServer::new(app).unwrap();
         let request_body: RequestDetails = RequestDetails {
-            processor_name: "adder_processor".to_string(),
+            processor_name: "adder".to_string(),
             processor_id: None,
+            properties: Properties::new(),
+            scheduling: None,
         };
 
         let response = test_server
@@ -481,8 +944,10 @@ mod tests {
         assert_eq!(response_details.status, super::ProcessorStatus::Stopped);
 
         let request_body: RequestDetails = RequestDetails {
-            processor_name: "adder_processor".to_string(),
+            processor_name: "adder".to_string(),
             processor_id: Some(response_details.processor_id),
+            properties: Properties::new(),
+            scheduling: None,
         };
 
         let response = test_server
@@ -511,33 +976,36 @@ mod tests {
         let config: MainConfig = MainConfig {
             server_port: 8080,
             processor_queue_length: 10,
+            content_offload_threshold_bytes: None,
+            processor_restart_backoff_ms: None,
+            provenance_log_path: None,
         };
 
-        let processor_mappings = HashMap::from([
-            (
-                "adder_processor".to_string(),
-                ProcessorType::SourceProcessor,
-            ),
-            ("doubler_processor".to_string(), ProcessorType::Other),
-        ]);
         let cancellation_token = CancellationToken::new();
         let state = super::AppState {
             config,
             cancellation_token: cancellation_token.clone(),
             peers_tx: Arc::new(Mutex::new(HashMap::new())),
             parent_processor_tx: Arc::new(Mutex::new(HashMap::new())),
-            processor_types_mappings: Arc::new(Mutex::new(processor_mappings)),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            processor_cancellation_tokens: Arc::new(Mutex::new(HashMap::new())),
+            processor_registry: Arc::new(crate::processor_registry()),
+            content_offloader: ContentOffloader::disabled(),
+            bulletin_board: BulletinBoard::default(),
+            provenance: ProvenanceRepository::default(),
         };
 
         let app = Router::new()
-            .route(&create_processor_route, post(super::create_processor))
-            .route(&start_processor_route, patch(super::start_processor))
+            .route(create_processor_route, post(super::create_processor))
+            .route(start_processor_route, patch(super::start_processor))
             .with_state(state);
 
         let test_server = TestServer::new(app).unwrap();
         let request_body: RequestDetails = RequestDetails {
-            processor_name: "adder_processor".to_string(),
+            processor_name: "adder".to_string(),
             processor_id: None,
+            properties: Properties::new(),
+            scheduling: None,
         };
 
         let response = test_server
@@ -550,8 +1018,10 @@ mod tests {
         assert_eq!(response_details.status, super::ProcessorStatus::Stopped);
 
         let request_body: RequestDetails = RequestDetails {
-            processor_name: "adder_processor".to_string(),
+            processor_name: "adder".to_string(),
             processor_id: Some(response_details.processor_id),
+            properties: Properties::new(),
+            scheduling: None,
         };
 
         let response = test_server
@@ -563,4 +1033,180 @@ mod tests {
         assert_eq!(response_details.status, super::ProcessorStatus::Running);
         cancellation_token.cancel();
     }
+
+    #[tokio::test]
+    async fn test_delete_processor() {
+        let cancellation_token = CancellationToken::new();
+        let state = super::AppState {
+            config: MainConfig {
+                server_port: 8080,
+                processor_queue_length: 1000,
+                content_offload_threshold_bytes: None,
+                processor_restart_backoff_ms: None,
+                provenance_log_path: None,
+            },
+            cancellation_token: cancellation_token.clone(),
+            peers_tx: Arc::new(Mutex::new(HashMap::new())),
+            parent_processor_tx: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            processor_cancellation_tokens: Arc::new(Mutex::new(HashMap::new())),
+            processor_registry: Arc::new(crate::processor_registry()),
+            content_offloader: ContentOffloader::disabled(),
+            bulletin_board: BulletinBoard::default(),
+            provenance: ProvenanceRepository::default(),
+        };
+        let app = Router::new()
+            .route("/create_processor", post(super::create_processor))
+            .route("/start_processor", patch(super::start_processor))
+            .route("/stop_processor", patch(super::stop_processor))
+            .route(
+                "/processor/get_info/:processor_id",
+                get(super::get_processor_info),
+            )
+            .route("/processor/:processor_id", delete(super::delete_processor))
+            .route(
+                "/connect",
+                post(cluster_request_handlers::connect_processors),
+            )
+            .with_state(state.clone());
+        let test_server = TestServer::new(app).unwrap();
+        let request = |processor_name: &str, processor_id: Option<String>| {
+            json!(RequestDetails {
+                processor_name: processor_name.to_string(),
+                processor_id,
+                properties: Properties::new(),
+                scheduling: None,
+            })
+        };
+
+        let adder_id = test_server
+            .post("/create_processor")
+            .json(&request("adder", None))
+            .await
+            .json::<ResponseDetails>()
+            .processor_id;
+        let doubler_id = test_server
+            .post("/create_processor")
+            .json(&request("doubler", None))
+            .await
+            .json::<ResponseDetails>()
+            .processor_id;
+        test_server
+            .post("/connect")
+            .json(&json!(ProcessorConnectionRequest {
+                source_processor_id: adder_id.clone(),
+                destination_processor_id: doubler_id.clone(),
+                relationship: None,
+                object_threshold: None,
+                data_size_threshold: None,
+            }))
+            .await
+            .assert_status_ok();
+        test_server
+            .patch("/start_processor")
+            .json(&request("adder", Some(adder_id.clone())))
+            .await
+            .assert_status_ok();
+        tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+        test_server
+            .patch("/stop_processor")
+            .json(&request("adder", Some(adder_id.clone())))
+            .await
+            .assert_status_ok();
+
+        let doubler_url = format!("/processor/{}", doubler_id);
+        let response = test_server.delete(&doubler_url).await;
+        response.assert_status(StatusCode::CONFLICT);
+        assert!(response
+            .json::<ErrorResponse>()
+            .message
+            .contains("packets queued"));
+        test_server
+            .patch("/start_processor")
+            .json(&request("doubler", Some(doubler_id.clone())))
+            .await
+            .assert_status_ok();
+        let response = test_server.delete(&doubler_url).await;
+        response.assert_status(StatusCode::CONFLICT);
+        assert!(response
+            .json::<ErrorResponse>()
+            .message
+            .contains("must be stopped"));
+
+        test_server
+            .delete(&format!("{}?force=true", doubler_url))
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        let doubler_id = Uuid::parse_str(&doubler_id).unwrap();
+        assert!(!state
+            .parent_processor_tx
+            .lock()
+            .await
+            .contains_key(&doubler_id));
+        assert!(state.peers_tx.lock().await.is_empty());
+        assert!(state.connections.lock().await.is_empty());
+        let adder_info = test_server
+            .get(&format!("/processor/get_info/{}", adder_id))
+            .await
+            .json::<ProcessorInfo>();
+        assert!(adder_info.connections.is_empty());
+
+        let adder_url = format!("/processor/{}", adder_id);
+        test_server
+            .delete(&adder_url)
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        test_server
+            .delete(&adder_url)
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        assert!(state.processor_cancellation_tokens.lock().await.is_empty());
+        assert!(!cancellation_token.is_cancelled());
+
+        // Packets a processor queued for itself are dropped with it.
+        let looping_id = test_server
+            .post("/create_processor")
+            .json(&request("doubler", None))
+            .await
+            .json::<ResponseDetails>()
+            .processor_id;
+        test_server
+            .post("/connect")
+            .json(&json!(ProcessorConnectionRequest {
+                source_processor_id: looping_id.clone(),
+                destination_processor_id: looping_id.clone(),
+                relationship: None,
+                object_threshold: None,
+                data_size_threshold: None,
+            }))
+            .await
+            .assert_status_ok();
+        let self_connection = state
+            .connections
+            .lock()
+            .await
+            .values()
+            .next()
+            .cloned()
+            .unwrap();
+        let packet_id = Uuid::new_v4();
+        self_connection
+            .queue
+            .push(Message::InMemoryMessage(InMemoryPacket {
+                id: packet_id,
+                attributes: Attributes::new(),
+                data: Bytes::from_static(&[1, 2, 3]),
+            }));
+        test_server
+            .delete(&format!("/processor/{}?force=true", looping_id))
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        assert_eq!(self_connection.queue.len(), 0);
+        assert!(state.connections.lock().await.is_empty());
+        let events = state.provenance.events(&packet_id.to_string());
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, ProvenanceEventType::Drop);
+
+        cancellation_token.cancel();
+    }
 }
@@ -0,0 +1,164 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+
+use crate::{
+    processors::provenance::{Lineage, ProvenanceEvent},
+    AppState,
+};
+
+use super::{errors::ApiError, models::ProvenanceQuery};
+
+/// Events of the packet, the oldest first.
+#[tracing::instrument]
+pub async fn get_provenance_events(
+    State(server_state): State<AppState>,
+    Query(query): Query<ProvenanceQuery>,
+) -> Json<Vec<ProvenanceEvent>> {
+    Json(server_state.provenance.events(&query.packet_id))
+}
+
+/// Events of the packet and of the packets it was forked from or forked into, with the links
+/// between them.
+#[tracing::instrument]
+pub async fn get_lineage(
+    State(server_state): State<AppState>,
+    Path(packet_id): Path<String>,
+) -> Result<Json<Lineage>, ApiError> {
+    server_state
+        .provenance
+        .lineage(&packet_id)
+        .map(Json)
+        .ok_or(ApiError::ProvenanceNotFound(packet_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use axum::{routing::get, Router};
+    use axum_test::TestServer;
+    use bytes::Bytes;
+    use commons::MainConfig;
+    use http::StatusCode;
+    use tokio::sync::Mutex;
+    use tokio_util::sync::CancellationToken;
+    use uuid::Uuid;
+
+    use crate::processors::{
+        bulletins::BulletinBoard,
+        content_offloader::ContentOffloader,
+        models::{Attributes, InMemoryPacket},
+        processor::ProcessContext,
+        provenance::{
+            Lineage, LineageLink, ProvenanceEvent, ProvenanceEventType, ProvenanceRepository,
+        },
+    };
+
+    fn packet(data: &'static [u8]) -> InMemoryPacket {
+        InMemoryPacket {
+            id: Uuid::new_v4(),
+            attributes: Attributes::new(),
+            data: Bytes::from_static(data),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provenance() {
+        let provenance = ProvenanceRepository::default();
+        let context = ProcessContext {
+            processor_id: Uuid::new_v4(),
+            processor_name: "test".to_string(),
+            properties: Default::default(),
+            bulletin_board: BulletinBoard::default(),
+            provenance: provenance.clone(),
+        };
+        let (parent, child, other) = (packet(b"a"), packet(b"aa"), packet(b"b"));
+        for event in [
+            ProvenanceEvent::of_packet(ProvenanceEventType::Create, &context, &parent),
+            ProvenanceEvent::of_packet(ProvenanceEventType::Create, &context, &other),
+            ProvenanceEvent::of_packet(ProvenanceEventType::Fork, &context, &child)
+                .with_parents(&[parent.id]),
+            ProvenanceEvent::of_packet(ProvenanceEventType::Route, &context, &parent)
+                .with_relationship("original"),
+        ] {
+            provenance.record(event);
+        }
+
+        let state = super::AppState {
+            config: MainConfig {
+                server_port: 8080,
+                processor_queue_length: 10,
+                content_offload_threshold_bytes: None,
+                processor_restart_backoff_ms: None,
+                provenance_log_path: None,
+            },
+            cancellation_token: CancellationToken::new(),
+            peers_tx: Arc::new(Mutex::new(HashMap::new())),
+            parent_processor_tx: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            processor_cancellation_tokens: Arc::new(Mutex::new(HashMap::new())),
+            processor_registry: Arc::new(crate::processor_registry()),
+            content_offloader: ContentOffloader::disabled(),
+            bulletin_board: BulletinBoard::default(),
+            provenance,
+        };
+        let app = Router::new()
+            .route("/provenance", get(super::get_provenance_events))
+            .route("/provenance/lineage/:packet_id", get(super::get_lineage))
+            .with_state(state);
+        let test_server = TestServer::new(app).unwrap();
+
+        let events = test_server
+            .get("/provenance")
+            .add_query_param("packet_id", parent.id.to_string())
+            .await
+            .json::<Vec<ProvenanceEvent>>();
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| (event.event_id, event.event_type))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, ProvenanceEventType::Create),
+                (3, ProvenanceEventType::Route)
+            ]
+        );
+
+        let lineage = test_server
+            .get(&format!("/provenance/lineage/{}", child.id))
+            .await
+            .json::<Lineage>();
+        assert_eq!(
+            lineage.packet_ids,
+            vec![child.id.to_string(), parent.id.to_string()]
+        );
+        assert_eq!(
+            lineage
+                .events
+                .iter()
+                .map(|event| event.event_id)
+                .collect::<Vec<_>>(),
+            vec![0, 2, 3]
+        );
+        assert_eq!(
+            lineage.links,
+            vec![
+                LineageLink {
+                    from_event_id: 0,
+                    to_event_id: 2
+                },
+                LineageLink {
+                    from_event_id: 0,
+                    to_event_id: 3
+                },
+            ]
+        );
+
+        test_server
+            .get(&format!("/provenance/lineage/{}", Uuid::new_v4()))
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+    }
+}
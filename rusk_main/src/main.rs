@@ -6,10 +6,21 @@ use axum::{
     Router,
 };
 use commons::MainConfig;
-use handlers::{cluster_request_handlers, processor_request_handlers};
+use content_repository_client::ContentRepositoryClient;
+use handlers::{
+    cluster_request_handlers, connection_request_handlers, processor_request_handlers,
+    provenance_request_handlers,
+};
 use http::{header, Method};
-use processors::models::{InMemoryPacket, Message, ProcessorCommand, ProcessorType};
-use rand::Rng;
+use processor_functions::{adder::Adder, doubler::Doubler};
+use processors::{
+    bulletins::BulletinBoard,
+    connection::{Connection, Inbox},
+    content_offloader::ContentOffloader,
+    models::{ProcessorCommand, ProcessorType},
+    provenance::ProvenanceRepository,
+    registry::{self, ProcessorRegistry},
+};
 use tokio::{
     signal,
     sync::{mpsc, Mutex},
@@ -19,15 +30,24 @@ use tower_http::cors::{Any, CorsLayer};
 use uuid::Uuid;
 
 mod handlers;
+mod processor_functions;
 mod processors;
 
 #[derive(Clone, Debug, FromRef)]
 struct AppState {
     config: MainConfig,
     cancellation_token: CancellationToken,
-    peers_tx: Arc<Mutex<HashMap<Uuid, mpsc::Sender<Message>>>>,
+    /// Inboxes of the processors which receive packets, by processor id.
+    peers_tx: Arc<Mutex<HashMap<Uuid, Inbox>>>,
     parent_processor_tx: Arc<Mutex<HashMap<Uuid, mpsc::Sender<ProcessorCommand>>>>,
-    processor_types_mappings: Arc<Mutex<HashMap<String, ProcessorType>>>,
+    /// Connections between processors, by connection id.
+    connections: Arc<Mutex<HashMap<Uuid, Connection>>>,
+    /// Tokens which cancel single processors, children of `cancellation_token`.
+    processor_cancellation_tokens: Arc<Mutex<HashMap<Uuid, CancellationToken>>>,
+    processor_registry: Arc<ProcessorRegistry>,
+    content_offloader: ContentOffloader,
+    bulletin_board: BulletinBoard,
+    provenance: ProvenanceRepository,
 }
 
 #[tokio::main]
@@ -35,25 +55,34 @@ async fn main() {
     commons::enable_tracing();
     //console_subscriber::init();
 
-    let main_config: MainConfig = commons::get_config().rusk_main;
+    let config = commons::get_config();
+    let main_config: MainConfig = config.rusk_main;
     let cancellation_token = CancellationToken::new();
+    let content_offloader = ContentOffloader::new(
+        ContentRepositoryClient::new(config.content_repository_client),
+        main_config.content_offload_threshold_bytes,
+    );
 
-    // TODO: Read these mappings from a config file
-    let processor_mappings = HashMap::from([
-        ("adder".to_string(), ProcessorType::SourceProcessor),
-        ("doubler".to_string(), ProcessorType::Other),
-    ]);
+    let provenance = match &main_config.provenance_log_path {
+        Some(path) => ProvenanceRepository::open(path).expect("Failed to open provenance log"),
+        None => ProvenanceRepository::default(),
+    };
 
     let state = AppState {
         config: main_config.clone(),
         cancellation_token: cancellation_token.clone(),
         peers_tx: Arc::new(Mutex::new(HashMap::new())),
         parent_processor_tx: Arc::new(Mutex::new(HashMap::new())),
-        processor_types_mappings: Arc::new(Mutex::new(processor_mappings)),
+        connections: Arc::new(Mutex::new(HashMap::new())),
+        processor_cancellation_tokens: Arc::new(Mutex::new(HashMap::new())),
+        processor_registry: Arc::new(processor_registry()),
+        content_offloader,
+        bulletin_board: BulletinBoard::default(),
+        provenance,
     };
 
     let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
         .allow_headers([header::CONTENT_TYPE])
         .allow_origin(Any);
 
@@ -64,7 +93,7 @@ async fn main() {
             get(cluster_request_handlers::get_cluster_info),
         )
         .route(
-            "/processor/delete",
+            "/processor/:processor_id",
             delete(processor_request_handlers::delete_processor),
         )
         .route(
@@ -83,6 +112,18 @@ async fn main() {
             "/processor/get_status",
             get(processor_request_handlers::get_status),
         )
+        .route(
+            "/processor/:processor_id/properties",
+            patch(processor_request_handlers::update_processor_properties),
+        )
+        .route(
+            "/processor/:processor_id/scheduling",
+            patch(processor_request_handlers::update_processor_scheduling),
+        )
+        .route(
+            "/processor/types",
+            get(processor_request_handlers::get_processor_types),
+        )
         .route(
             "/processor/get_info/:processor_id",
             get(processor_request_handlers::get_processor_info),
@@ -95,6 +136,33 @@ async fn main() {
             "/processor/disconnect",
             delete(cluster_request_handlers::disconnect_processors),
         )
+        .route("/bulletins", get(cluster_request_handlers::get_bulletins))
+        .route(
+            "/connections",
+            get(connection_request_handlers::get_connections),
+        )
+        .route(
+            "/connections/:connection_id",
+            get(connection_request_handlers::get_connection)
+                .delete(connection_request_handlers::delete_connection),
+        )
+        .route(
+            "/connections/:connection_id/queue",
+            get(connection_request_handlers::get_queued_packets)
+                .delete(connection_request_handlers::purge_queue),
+        )
+        .route(
+            "/connections/:connection_id/queue/:packet_id/content",
+            get(connection_request_handlers::get_queued_packet_content),
+        )
+        .route(
+            "/provenance",
+            get(provenance_request_handlers::get_provenance_events),
+        )
+        .route(
+            "/provenance/lineage/:packet_id",
+            get(provenance_request_handlers::get_lineage),
+        )
         .layer(cors)
         .with_state(state);
 
@@ -140,25 +208,16 @@ async fn shutdown_signal(cancellation_token: CancellationToken) {
     }
 }
 
-fn adder_func() -> Option<InMemoryPacket> {
-    let mut rng = rand::thread_rng();
-    let data: Vec<u8> = (0..3).map(|_| rng.gen_range(1..100)).collect();
-    Some(InMemoryPacket {
-        id: Uuid::new_v4(),
-        data,
-    })
-}
-
-fn doubler_func(packet: InMemoryPacket) -> Option<InMemoryPacket> {
-    let new_data = packet.data.iter().map(|x| x * 2).collect();
-    let new_packet = InMemoryPacket {
-        id: packet.id,
-        data: new_data,
-    };
-    tracing::info!(
-        "old data: {:?}, new data: {:?}",
-        packet.data,
-        new_packet.data
+/// Registers every kind of processor which can be created.
+fn processor_registry() -> ProcessorRegistry {
+    let mut registry = ProcessorRegistry::default();
+    registry.register(
+        Adder::descriptor(),
+        ProcessorType::SourceProcessor,
+        |setup| registry::spawn_source_processor(setup, || Box::new(Adder::default())),
     );
-    Some(new_packet)
+    registry.register(Doubler::descriptor(), ProcessorType::Other, |setup| {
+        registry::spawn_in_memory_processor(setup, || Box::new(Doubler::default()))
+    });
+    registry
 }
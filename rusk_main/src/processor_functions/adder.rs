@@ -0,0 +1,87 @@
+use async_trait::async_trait;
+use rand::Rng;
+
+use crate::processors::{
+    models::{attributes, relationships},
+    processor::{ProcessContext, ProcessSession, Processor, ProcessorError},
+    properties::{PropertyDescriptor, PropertyType},
+    registry::ProcessorDescriptor,
+};
+
+const PACKET_SIZE: &str = "packet_size";
+const MAX_VALUE: &str = "max_value";
+
+/// Source processor which generates packets of random bytes.
+#[derive(Debug, Default)]
+pub struct Adder {
+    packet_size: usize,
+    max_value: u8,
+}
+
+impl Adder {
+    pub fn descriptor() -> ProcessorDescriptor {
+        ProcessorDescriptor {
+            name: "adder".to_string(),
+            description: "Generates packets of random bytes.".to_string(),
+            properties: vec![
+                PropertyDescriptor {
+                    name: PACKET_SIZE.to_string(),
+                    description: "Number of bytes in every packet.".to_string(),
+                    required: true,
+                    default_value: Some("3".to_string()),
+                    allowed_values: vec![],
+                    property_type: PropertyType::Integer {
+                        min: Some(1),
+                        max: Some(1024 * 1024),
+                    },
+                },
+                PropertyDescriptor {
+                    name: MAX_VALUE.to_string(),
+                    description: "Largest value of a generated byte.".to_string(),
+                    required: true,
+                    default_value: Some("99".to_string()),
+                    allowed_values: vec![],
+                    property_type: PropertyType::Integer {
+                        min: Some(1),
+                        max: Some(255),
+                    },
+                },
+            ],
+            relationships: vec![relationships::SUCCESS.to_string()],
+        }
+    }
+}
+
+#[async_trait]
+impl Processor for Adder {
+    async fn on_scheduled(&mut self, context: &ProcessContext) -> Result<(), ProcessorError> {
+        // Properties are validated, so they can be parsed without checking.
+        self.packet_size = context.property(PACKET_SIZE).unwrap().parse().unwrap();
+        self.max_value = context.property(MAX_VALUE).unwrap().parse().unwrap();
+        tracing::info!(
+            "{} ({}): Generating packets of {} bytes",
+            context.processor_name,
+            context.processor_id,
+            self.packet_size
+        );
+        Ok(())
+    }
+
+    async fn on_trigger(
+        &mut self,
+        _context: &ProcessContext,
+        session: &mut ProcessSession,
+    ) -> Result<(), ProcessorError> {
+        let mut rng = rand::thread_rng();
+        let data: Vec<u8> = (0..self.packet_size)
+            .map(|_| rng.gen_range(1..=self.max_value))
+            .collect();
+        let mut packet = session.create(data.into());
+        packet.attributes.insert(
+            attributes::MIME_TYPE.to_string(),
+            "application/octet-stream".to_string(),
+        );
+        session.transfer(packet, relationships::SUCCESS);
+        Ok(())
+    }
+}
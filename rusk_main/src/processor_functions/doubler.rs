@@ -0,0 +1,114 @@
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+
+use crate::processors::{
+    models::relationships,
+    processor::{ProcessContext, ProcessSession, Processor, ProcessorError},
+    properties::{PropertyDescriptor, PropertyType},
+    registry::ProcessorDescriptor,
+};
+
+const ON_OVERFLOW: &str = "on_overflow";
+
+#[derive(Debug, Default, PartialEq)]
+enum OnOverflow {
+    #[default]
+    Fail,
+    Drop,
+    Wrap,
+    Saturate,
+}
+
+/// Doubles every byte of a packet, routing the doubled packet to success and the received one
+/// to original. Unless configured to wrap or saturate, packets with a byte which would overflow
+/// are routed to failure unchanged, or dropped.
+#[derive(Debug, Default)]
+pub struct Doubler {
+    on_overflow: OnOverflow,
+}
+
+impl Doubler {
+    pub fn descriptor() -> ProcessorDescriptor {
+        ProcessorDescriptor {
+            name: "doubler".to_string(),
+            description: "Doubles every byte of a packet.".to_string(),
+            properties: vec![PropertyDescriptor {
+                name: ON_OVERFLOW.to_string(),
+                description: "What to do when a doubled byte does not fit in a byte: route \
+                    the packet to failure, drop it, wrap around or saturate at 255."
+                    .to_string(),
+                required: true,
+                default_value: Some("fail".to_string()),
+                allowed_values: vec![
+                    "fail".to_string(),
+                    "drop".to_string(),
+                    "wrap".to_string(),
+                    "saturate".to_string(),
+                ],
+                property_type: PropertyType::String { pattern: None },
+            }],
+            relationships: vec![
+                relationships::SUCCESS.to_string(),
+                relationships::FAILURE.to_string(),
+                relationships::ORIGINAL.to_string(),
+            ],
+        }
+    }
+
+    fn double(&self, data: Bytes) -> Bytes {
+        // Data is copied here if it is shared with the original packet.
+        let mut data = BytesMut::from(data);
+        data.iter_mut().for_each(|x| {
+            *x = match self.on_overflow {
+                OnOverflow::Saturate => x.saturating_mul(2),
+                _ => x.wrapping_mul(2),
+            }
+        });
+        data.freeze()
+    }
+}
+
+#[async_trait]
+impl Processor for Doubler {
+    async fn on_scheduled(&mut self, context: &ProcessContext) -> Result<(), ProcessorError> {
+        self.on_overflow = match context.property(ON_OVERFLOW) {
+            Some("drop") => OnOverflow::Drop,
+            Some("wrap") => OnOverflow::Wrap,
+            Some("saturate") => OnOverflow::Saturate,
+            _ => OnOverflow::Fail,
+        };
+        Ok(())
+    }
+
+    async fn on_trigger(
+        &mut self,
+        context: &ProcessContext,
+        session: &mut ProcessSession,
+    ) -> Result<(), ProcessorError> {
+        while let Some(packet) = session.get() {
+            let data = session.read(&packet).await?;
+            tracing::info!("old data: {:?}", data);
+            let overflows = data.iter().any(|x| x.checked_mul(2).is_none());
+            match self.on_overflow {
+                OnOverflow::Fail | OnOverflow::Drop if overflows => {
+                    context.warn(format!(
+                        "Cannot double data of packet {} without overflow",
+                        packet.id()
+                    ));
+                    if self.on_overflow == OnOverflow::Fail {
+                        session.transfer(packet, relationships::FAILURE);
+                    } else {
+                        session.remove(packet);
+                    }
+                }
+                _ => {
+                    let doubled_packet = session.create_child(&packet, self.double(data));
+                    tracing::info!("new data: {:?}", doubled_packet.data);
+                    session.transfer(packet, relationships::ORIGINAL);
+                    session.transfer(doubled_packet, relationships::SUCCESS);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,2 @@
+pub mod adder;
+pub mod doubler;
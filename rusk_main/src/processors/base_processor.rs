@@ -1,6 +1,14 @@
-use std::collections::HashMap;
+use std::{sync::Arc, time::Duration};
 
-use super::models::{Message, ProcessorCommand};
+use super::bulletins::BulletinBoard;
+use super::connection::{ConnectionQueue, Inbox};
+use super::content_offloader::ContentOffloader;
+use super::models::{PeersTx, ProcessorCommand, RoutedPacket};
+use super::processor::ProcessContext;
+use super::properties::ProcessorProperties;
+use super::provenance::{ProvenanceEvent, ProvenanceEventType, ProvenanceRepository};
+use super::scheduling::Schedule;
+use crate::handlers::models::{ConnectionStatus, PacketInfo};
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
@@ -8,26 +16,162 @@ use uuid::Uuid;
 pub trait ProcessorConnection {
     fn connect_processor(
         &mut self,
+        relationship: String,
         receiver_processor_id: Uuid,
-        tx_for_receiver: mpsc::Sender<Message>,
+        queue: Arc<ConnectionQueue>,
     );
-    fn disconnect_processor(&mut self, receiver_processor_id: Uuid);
+    /// Disconnects the receiver from the relationship, or from every relationship when none
+    /// is given.
+    fn disconnect_processor(&mut self, relationship: Option<&str>, receiver_processor_id: Uuid);
 }
 
 pub trait SourceProcessor {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         processor_name: String,
         parent_rx: mpsc::Receiver<ProcessorCommand>,
-        peer_processors_tx: HashMap<Uuid, mpsc::Sender<Message>>,
+        peer_processors_tx: PeersTx,
+        properties: ProcessorProperties,
+        schedule: Schedule,
+        content_offloader: ContentOffloader,
+        bulletin_board: BulletinBoard,
+        provenance: ProvenanceRepository,
         cancellation_token: CancellationToken,
     ) -> Self;
 }
 
 pub trait SinkProcessor {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         processor_name: String,
-        peers_rx: mpsc::Receiver<Message>,
+        inbox: Inbox,
         parent_rx: mpsc::Receiver<ProcessorCommand>,
+        properties: ProcessorProperties,
+        schedule: Schedule,
+        content_offloader: ContentOffloader,
+        bulletin_board: BulletinBoard,
+        provenance: ProvenanceRepository,
         cancellation_token: CancellationToken,
     ) -> Self;
 }
+
+/// How often a processor checks whether the connections which hold back its triggers have
+/// room again.
+pub(crate) const BACK_PRESSURE_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+pub(crate) fn connect_peer(
+    peers_tx: &mut PeersTx,
+    relationship: String,
+    receiver_processor_id: Uuid,
+    queue: Arc<ConnectionQueue>,
+) {
+    if let Some(replaced_queue) = peers_tx
+        .entry(relationship)
+        .or_default()
+        .insert(receiver_processor_id, queue)
+    {
+        replaced_queue.close();
+    }
+}
+
+pub(crate) fn disconnect_peer(
+    peers_tx: &mut PeersTx,
+    relationship: Option<&str>,
+    receiver_processor_id: Uuid,
+) {
+    peers_tx.retain(|peer_relationship, relationship_peers_tx| {
+        if relationship.is_none_or(|relationship| relationship == peer_relationship) {
+            if let Some(queue) = relationship_peers_tx.remove(&receiver_processor_id) {
+                queue.close();
+            }
+        }
+        !relationship_peers_tx.is_empty()
+    });
+}
+
+/// Whether one of the connections of the processor is full, so that it must not be triggered.
+pub(crate) fn is_back_pressured(peers_tx: &PeersTx) -> bool {
+    peers_tx
+        .values()
+        .flat_map(|relationship_peers_tx| relationship_peers_tx.values())
+        .any(|queue| queue.is_full())
+}
+
+pub(crate) fn connection_statuses(peers_tx: &PeersTx) -> Vec<ConnectionStatus> {
+    let mut connection_statuses: Vec<ConnectionStatus> = peers_tx
+        .iter()
+        .flat_map(|(relationship, relationship_peers_tx)| {
+            relationship_peers_tx
+                .iter()
+                .map(|(destination_processor_id, queue)| ConnectionStatus {
+                    relationship: relationship.clone(),
+                    destination_processor_id: destination_processor_id.to_string(),
+                    queued_count: queue.len() as u64,
+                    queued_bytes: queue.size_bytes(),
+                    back_pressure: queue.back_pressure().clone(),
+                    is_full: queue.is_full(),
+                })
+        })
+        .collect();
+    connection_statuses.sort_by(|a, b| {
+        (&a.relationship, &a.destination_processor_id)
+            .cmp(&(&b.relationship, &b.destination_processor_id))
+    });
+    connection_statuses
+}
+
+/// Sends the packets to the processors connected to their relationships. Packets routed to a
+/// relationship without connections are dropped, releasing their content if it was offloaded.
+/// Returns the last packet which was sent.
+pub(crate) async fn route_packets(
+    context: &ProcessContext,
+    peers_tx: &PeersTx,
+    content_offloader: &ContentOffloader,
+    routed_packets: Vec<RoutedPacket>,
+) -> Option<PacketInfo> {
+    let mut last_packet = None;
+    for RoutedPacket {
+        relationship,
+        packet,
+    } in routed_packets
+    {
+        let Some(relationship_peers_tx) = peers_tx.get(&relationship) else {
+            tracing::debug!(
+                "{}: No processor connected to {}, dropping packet {}",
+                context.processor_name,
+                relationship,
+                packet.id()
+            );
+            context.provenance.record(
+                ProvenanceEvent::of_message(
+                    ProvenanceEventType::Drop,
+                    context.processor_id,
+                    &packet,
+                )
+                .with_relationship(&relationship)
+                .with_details("No connection for the relationship".to_string()),
+            );
+            content_offloader.discard(packet).await;
+            continue;
+        };
+        last_packet = Some(PacketInfo::from(&packet));
+        context.provenance.record(
+            ProvenanceEvent::of_message(ProvenanceEventType::Send, context.processor_id, &packet)
+                .with_relationship(&relationship)
+                .with_details(format!(
+                    "Sent to {} connections",
+                    relationship_peers_tx.len()
+                )),
+        );
+        let sent_count = content_offloader
+            .send_to_peers(packet, relationship_peers_tx)
+            .await;
+        tracing::info!(
+            "{}: Sent packet to {} processors via {}",
+            context.processor_name,
+            sent_count,
+            relationship
+        );
+    }
+    last_packet
+}
@@ -0,0 +1,132 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use uuid::Uuid;
+
+/// Most bulletins the board keeps. The oldest ones are dropped first.
+pub const MAX_BULLETINS: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Warning or error a processor published, for users to see without reading the logs.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Bulletin {
+    /// Increases with every bulletin.
+    pub id: u64,
+    pub processor_id: String,
+    pub processor_name: String,
+    pub severity: Severity,
+    pub message: String,
+    /// Time the bulletin was published at, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Default)]
+struct BoardState {
+    bulletins: VecDeque<Bulletin>,
+    next_id: u64,
+}
+
+/// Latest bulletins of all processors, shared by the processors and the API.
+#[derive(Clone, Debug)]
+pub struct BulletinBoard {
+    state: Arc<Mutex<BoardState>>,
+    capacity: usize,
+}
+
+impl Default for BulletinBoard {
+    fn default() -> Self {
+        BulletinBoard::with_capacity(MAX_BULLETINS)
+    }
+}
+
+impl BulletinBoard {
+    pub fn with_capacity(capacity: usize) -> Self {
+        BulletinBoard {
+            state: Arc::default(),
+            capacity,
+        }
+    }
+
+    pub fn publish(
+        &self,
+        processor_id: Uuid,
+        processor_name: &str,
+        severity: Severity,
+        message: String,
+    ) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        if state.bulletins.len() == self.capacity {
+            state.bulletins.pop_front();
+        }
+        state.bulletins.push_back(Bulletin {
+            id,
+            processor_id: processor_id.to_string(),
+            processor_name: processor_name.to_string(),
+            severity,
+            message,
+            timestamp,
+        });
+    }
+
+    /// Bulletins of the processor, or of all processors, published at or after `since`, in
+    /// milliseconds since the Unix epoch. The oldest come first.
+    pub fn bulletins(&self, processor_id: Option<&str>, since: Option<u64>) -> Vec<Bulletin> {
+        self.state
+            .lock()
+            .unwrap()
+            .bulletins
+            .iter()
+            .filter(|bulletin| processor_id.is_none_or(|id| bulletin.processor_id == id))
+            .filter(|bulletin| since.is_none_or(|since| bulletin.timestamp >= since))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bulletin_board_is_bounded() {
+        let bulletin_board = BulletinBoard::with_capacity(2);
+        let (first_id, second_id) = (Uuid::new_v4(), Uuid::new_v4());
+        for (processor_id, message) in [(first_id, "a"), (second_id, "b"), (first_id, "c")] {
+            bulletin_board.publish(processor_id, "test", Severity::Error, message.to_string());
+        }
+
+        let messages = |bulletins: Vec<Bulletin>| -> Vec<String> {
+            bulletins
+                .into_iter()
+                .map(|bulletin| bulletin.message)
+                .collect()
+        };
+        assert_eq!(
+            messages(bulletin_board.bulletins(None, None)),
+            vec!["b", "c"]
+        );
+        assert_eq!(
+            messages(bulletin_board.bulletins(Some(&first_id.to_string()), None)),
+            vec!["c"]
+        );
+        let bulletins = bulletin_board.bulletins(None, None);
+        assert_eq!(bulletins[1].id, 2);
+        assert!(bulletin_board
+            .bulletins(None, Some(bulletins[1].timestamp + 1))
+            .is_empty());
+    }
+}
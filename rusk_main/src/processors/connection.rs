@@ -0,0 +1,360 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use super::{models::Message, properties::parse_data_size};
+
+/// Data size threshold of connections which do not set one.
+pub const DEFAULT_DATA_SIZE_THRESHOLD: &str = "1 GB";
+
+/// Backpressure thresholds of a connection. Once its queue holds `object_threshold` packets or
+/// `data_size_threshold` of data, like `64 MB`, the source processor is not triggered until
+/// the destination has taken enough packets.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BackPressure {
+    pub object_threshold: u64,
+    pub data_size_threshold: String,
+}
+
+impl BackPressure {
+    /// Validates the thresholds and returns the data size threshold in bytes.
+    fn validate(&self) -> Result<u64, String> {
+        if self.object_threshold == 0 {
+            return Err("object_threshold must be at least 1".to_string());
+        }
+        match parse_data_size(&self.data_size_threshold) {
+            Ok(0) => Err("data_size_threshold must be larger than 0 B".to_string()),
+            Ok(bytes) => Ok(bytes),
+            Err(message) => Err(format!("data_size_threshold: {}", message)),
+        }
+    }
+}
+
+/// Packet waiting in the queue of a connection.
+#[derive(Clone, Debug)]
+pub struct QueuedMessage {
+    pub message: Message,
+    pub enqueued_at: SystemTime,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    messages: VecDeque<QueuedMessage>,
+    size_bytes: u64,
+    /// Set once the connection is removed. The queue is dropped once it is empty.
+    closed: bool,
+}
+
+/// Packets sent through a connection which its destination has not taken yet.
+///
+/// Packets are always accepted, so a queue can grow past its thresholds by the packets of one
+/// trigger of the source processor.
+#[derive(Debug)]
+pub struct ConnectionQueue {
+    state: Mutex<QueueState>,
+    back_pressure: BackPressure,
+    data_size_threshold_bytes: u64,
+    destination_waker: Arc<Notify>,
+}
+
+impl ConnectionQueue {
+    /// Creates the queue of a new connection to the processor of the inbox.
+    pub fn new(back_pressure: BackPressure, inbox: &Inbox) -> Result<Arc<Self>, String> {
+        let queue = Arc::new(ConnectionQueue {
+            data_size_threshold_bytes: back_pressure.validate()?,
+            back_pressure,
+            state: Mutex::default(),
+            destination_waker: inbox.waker.clone(),
+        });
+        inbox.state.lock().unwrap().queues.push(queue.clone());
+        Ok(queue)
+    }
+
+    pub fn push(&self, message: Message) {
+        let mut state = self.state.lock().unwrap();
+        state.size_bytes += message.size_bytes();
+        state.messages.push_back(QueuedMessage {
+            message,
+            enqueued_at: SystemTime::now(),
+        });
+        drop(state);
+        self.destination_waker.notify_one();
+    }
+
+    fn pop(&self) -> Option<Message> {
+        let mut state = self.state.lock().unwrap();
+        let queued_message = state.messages.pop_front()?;
+        state.size_bytes -= queued_message.message.size_bytes();
+        Some(queued_message.message)
+    }
+
+    /// Packets in the queue, in the order the destination takes them.
+    pub fn queued(&self) -> Vec<QueuedMessage> {
+        self.state
+            .lock()
+            .unwrap()
+            .messages
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// The queued packet with the id, which stays in the queue.
+    pub fn find(&self, packet_id: Uuid) -> Option<Message> {
+        self.state
+            .lock()
+            .unwrap()
+            .messages
+            .iter()
+            .find(|queued_message| queued_message.message.id() == packet_id)
+            .map(|queued_message| queued_message.message.clone())
+    }
+
+    /// Empties the queue and returns the packets it held.
+    pub fn purge(&self) -> Vec<Message> {
+        let mut state = self.state.lock().unwrap();
+        state.size_bytes = 0;
+        state
+            .messages
+            .drain(..)
+            .map(|queued_message| queued_message.message)
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().messages.len()
+    }
+
+    pub fn size_bytes(&self) -> u64 {
+        self.state.lock().unwrap().size_bytes
+    }
+
+    pub fn back_pressure(&self) -> &BackPressure {
+        &self.back_pressure
+    }
+
+    /// Whether the queue has reached one of its thresholds.
+    pub fn is_full(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.messages.len() as u64 >= self.back_pressure.object_threshold
+            || state.size_bytes >= self.data_size_threshold_bytes
+    }
+
+    /// Marks the connection as removed. Packets which are already queued are still taken by
+    /// the destination.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.destination_waker.notify_one();
+    }
+}
+
+/// Connection from a relationship of a source processor to a destination processor.
+#[derive(Clone, Debug)]
+pub struct Connection {
+    pub id: Uuid,
+    pub source_processor_id: Uuid,
+    pub relationship: String,
+    pub destination_processor_id: Uuid,
+    pub queue: Arc<ConnectionQueue>,
+}
+
+impl Connection {
+    /// Whether the connection subscribes the destination to the relationship of the source, or
+    /// to any relationship when none is given.
+    pub fn links(
+        &self,
+        source_processor_id: Uuid,
+        relationship: Option<&str>,
+        destination_processor_id: Uuid,
+    ) -> bool {
+        self.source_processor_id == source_processor_id
+            && self.destination_processor_id == destination_processor_id
+            && relationship.is_none_or(|relationship| self.relationship == relationship)
+    }
+}
+
+#[derive(Debug, Default)]
+struct InboxState {
+    queues: Vec<Arc<ConnectionQueue>>,
+    /// Queue to take the next packet from, so that every connection gets its turn.
+    next: usize,
+}
+
+/// Queues of the connections to a processor.
+#[derive(Clone, Debug, Default)]
+pub struct Inbox {
+    waker: Arc<Notify>,
+    state: Arc<Mutex<InboxState>>,
+}
+
+impl Inbox {
+    /// Takes the next packet, going round the queues of the connections. Queues of removed
+    /// connections are dropped once they are empty.
+    pub fn pop(&self) -> Option<Message> {
+        let mut state = self.state.lock().unwrap();
+        state.queues.retain(|queue| {
+            let queue_state = queue.state.lock().unwrap();
+            !queue_state.closed || !queue_state.messages.is_empty()
+        });
+        for _ in 0..state.queues.len() {
+            let index = state.next % state.queues.len();
+            state.next = index + 1;
+            if let Some(message) = state.queues[index].pop() {
+                return Some(message);
+            }
+        }
+        None
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .queues
+            .iter()
+            .all(|queue| queue.len() == 0)
+    }
+
+    /// Waits until a packet is queued or `wake` is called. A packet queued in the meantime,
+    /// while nobody waits, is not missed.
+    pub async fn notified(&self) {
+        self.waker.notified().await;
+    }
+
+    pub fn wake(&self) {
+        self.waker.notify_one();
+    }
+}
+
+#[cfg(test)]
+impl Inbox {
+    /// Waits for the next packet.
+    pub async fn recv(&self) -> Message {
+        loop {
+            if let Some(message) = self.pop() {
+                return message;
+            }
+            self.notified().await;
+        }
+    }
+}
+
+#[cfg(test)]
+impl Default for BackPressure {
+    fn default() -> Self {
+        BackPressure {
+            object_threshold: 10,
+            data_size_threshold: DEFAULT_DATA_SIZE_THRESHOLD.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::processors::models::{Attributes, InMemoryPacket};
+
+    fn message(data: &'static [u8]) -> Message {
+        Message::InMemoryMessage(InMemoryPacket {
+            id: Uuid::new_v4(),
+            attributes: Attributes::new(),
+            data: Bytes::from_static(data),
+        })
+    }
+
+    #[test]
+    fn test_back_pressure_thresholds() {
+        let inbox = Inbox::default();
+        let by_count = ConnectionQueue::new(
+            BackPressure {
+                object_threshold: 2,
+                data_size_threshold: "1 KB".to_string(),
+            },
+            &inbox,
+        )
+        .unwrap();
+        by_count.push(message(&[1]));
+        assert!(!by_count.is_full());
+        by_count.push(message(&[2]));
+        assert!(by_count.is_full());
+
+        let by_size = ConnectionQueue::new(
+            BackPressure {
+                object_threshold: 10,
+                data_size_threshold: "4 B".to_string(),
+            },
+            &inbox,
+        )
+        .unwrap();
+        by_size.push(message(&[1, 2, 3, 4]));
+        assert!(by_size.is_full());
+        assert_eq!(by_size.size_bytes(), 4);
+
+        assert!(ConnectionQueue::new(
+            BackPressure {
+                object_threshold: 0,
+                data_size_threshold: "4 B".to_string(),
+            },
+            &inbox,
+        )
+        .is_err());
+        assert!(ConnectionQueue::new(
+            BackPressure {
+                object_threshold: 1,
+                data_size_threshold: "lots".to_string(),
+            },
+            &inbox,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_inbox_takes_turns_between_connections() {
+        let inbox = Inbox::default();
+        let first = ConnectionQueue::new(BackPressure::default(), &inbox).unwrap();
+        let second = ConnectionQueue::new(BackPressure::default(), &inbox).unwrap();
+        first.push(message(&[1]));
+        first.push(message(&[2]));
+        second.push(message(&[3]));
+        first.close();
+
+        let mut received = vec![];
+        while let Some(Message::InMemoryMessage(packet)) = inbox.pop() {
+            received.push(packet.data[0]);
+        }
+        assert_eq!(received, vec![1, 3, 2]);
+        assert!(first.len() == 0 && first.size_bytes() == 0);
+        assert!(inbox.is_empty());
+        // The closed queue is dropped once it is empty.
+        inbox.pop();
+        assert_eq!(inbox.state.lock().unwrap().queues.len(), 1);
+    }
+
+    #[test]
+    fn test_inspect_and_purge_queue() {
+        let queue = ConnectionQueue::new(BackPressure::default(), &Inbox::default()).unwrap();
+        let first = message(&[1, 2]);
+        queue.push(first.clone());
+        queue.push(message(&[3]));
+
+        let queued = queue.queued();
+        assert_eq!(queued.len(), 2);
+        assert_eq!(queued[0].message.id(), first.id());
+        assert!(queued[0].enqueued_at <= queued[1].enqueued_at);
+        assert!(queue.find(first.id()).is_some());
+        assert!(queue.find(Uuid::new_v4()).is_none());
+        assert_eq!(queue.len(), 2);
+
+        assert_eq!(queue.purge().len(), 2);
+        assert_eq!(queue.len(), 0);
+        assert_eq!(queue.size_bytes(), 0);
+    }
+}
@@ -0,0 +1,408 @@
+use std::{collections::HashMap, sync::Arc};
+
+use bytes::Bytes;
+use content_repository_client::{ClientError, ContentReference, ContentRepositoryClient};
+use uuid::Uuid;
+
+use super::{
+    connection::ConnectionQueue,
+    models::{InMemoryPacket, Message, ReferencePacket},
+};
+
+/// Keeps the data of large packets out of memory by storing it in the content repository and
+/// passing `ReferencePacket`s between processors instead, until a processor needs the data.
+///
+/// Every processor a `ReferencePacket` is sent to holds one claim on its content, which is
+/// released once the processor has loaded the content or dropped the packet.
+#[derive(Clone, Debug)]
+pub struct ContentOffloader {
+    client: ContentRepositoryClient,
+    /// Packets with more data than this are offloaded. Nothing is offloaded when not set.
+    threshold_bytes: Option<usize>,
+}
+
+impl ContentOffloader {
+    pub fn new(client: ContentRepositoryClient, threshold_bytes: Option<usize>) -> Self {
+        ContentOffloader {
+            client,
+            threshold_bytes,
+        }
+    }
+
+    /// Sends the packet to every peer. Packets in memory are offloaded first if they are large
+    /// enough, while `ReferenceMessage`s are sent on as they are, taking over the claim of the
+    /// processor which holds them. Returns the number of peers it was sent to.
+    pub async fn send_to_peers(
+        &self,
+        message: Message,
+        peers_tx: &HashMap<Uuid, Arc<ConnectionQueue>>,
+    ) -> usize {
+        if peers_tx.is_empty() {
+            self.discard(message).await;
+            return 0;
+        }
+        let message = match message {
+            Message::InMemoryMessage(packet) => self.offload(packet).await,
+            reference_message => reference_message,
+        };
+        // Content starts with the claim of the first peer. It is copied for the others before
+        // anything is sent, so that no peer can release it in between.
+        let mut queues = peers_tx.values();
+        let first_queue = queues.next().expect("Peers are not empty");
+        let mut copies = vec![];
+        for queue in queues {
+            match self.copy(&message).await {
+                Ok(copy) => copies.push((queue, copy)),
+                Err(e) => tracing::error!(
+                    "Failed to send packet {} to a peer, its content can neither be claimed \
+                    nor loaded: {}",
+                    message.id(),
+                    e
+                ),
+            }
+        }
+        let sent_count = copies.len() + 1;
+        first_queue.push(message);
+        for (queue, copy) in copies {
+            queue.push(copy);
+        }
+        sent_count
+    }
+
+    /// Copy of the packet for one more holder. The content of a `ReferenceMessage` is claimed
+    /// once more, or loaded into memory when it cannot be claimed, so that a reference is never
+    /// handed out without a claim of its own.
+    pub async fn copy(&self, message: &Message) -> Result<Message, ClientError> {
+        let Message::ReferenceMessage(reference_packet) = message else {
+            return Ok(message.clone());
+        };
+        let reference = content_reference(reference_packet);
+        if let Err(e) = self.client.claim(&reference).await {
+            tracing::warn!(
+                "Failed to claim content of {:?}, copying it into memory: {}",
+                reference_packet,
+                e
+            );
+            return Ok(Message::InMemoryMessage(InMemoryPacket {
+                id: reference_packet.id,
+                attributes: reference_packet.attributes.clone(),
+                data: self.client.get(&reference).await?,
+            }));
+        }
+        Ok(message.clone())
+    }
+
+    /// Returns the data of the packet without taking it, so the claim of the processor it
+    /// was sent to is kept.
+    pub async fn read(&self, message: &Message) -> Result<Bytes, ClientError> {
+        match message {
+            Message::InMemoryMessage(packet) => Ok(packet.data.clone()),
+            Message::ReferenceMessage(reference_packet) => {
+                self.client.get(&content_reference(reference_packet)).await
+            }
+        }
+    }
+
+    /// Drops a packet which will not be processed any further.
+    pub async fn discard(&self, message: Message) {
+        if let Message::ReferenceMessage(reference_packet) = message {
+            self.release(&reference_packet).await;
+        }
+    }
+
+    async fn offload(&self, packet: InMemoryPacket) -> Message {
+        if self
+            .threshold_bytes
+            .is_none_or(|threshold_bytes| packet.data.len() <= threshold_bytes)
+        {
+            return Message::InMemoryMessage(packet);
+        }
+        let data_length = packet.data.len();
+        match self.client.put(packet.data.clone()).await {
+            Ok(reference) => {
+                tracing::debug!(
+                    "Offloaded {} bytes of packet {} to {:?}",
+                    data_length,
+                    packet.id,
+                    reference
+                );
+                Message::ReferenceMessage(ReferencePacket {
+                    id: packet.id,
+                    attributes: packet.attributes,
+                    file_name: reference.segment,
+                    offset: reference.offset,
+                    length: reference.length,
+                })
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to offload {} bytes of packet {}, keeping it in memory: {}",
+                    data_length,
+                    packet.id,
+                    e
+                );
+                Message::InMemoryMessage(packet)
+            }
+        }
+    }
+
+    async fn release(&self, reference_packet: &ReferencePacket) {
+        if let Err(e) = self
+            .client
+            .release(&content_reference(reference_packet))
+            .await
+        {
+            tracing::error!("Failed to release content of {:?}: {}", reference_packet, e);
+        }
+    }
+}
+
+#[cfg(test)]
+impl ContentOffloader {
+    /// Offloader which keeps every packet in memory.
+    pub fn disabled() -> Self {
+        ContentOffloader::new(
+            ContentRepositoryClient::new(tests::client_config("127.0.0.1:0".to_string())),
+            None,
+        )
+    }
+}
+
+pub(crate) fn content_reference(reference_packet: &ReferencePacket) -> ContentReference {
+    ContentReference {
+        segment: reference_packet.file_name.clone(),
+        offset: reference_packet.offset,
+        length: reference_packet.length,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use bytes::Bytes;
+    use commons::{
+        protocol::{self, Frame, OpCode},
+        ContentRepositoryClientConfig,
+    };
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::processors::{
+        connection::{BackPressure, Inbox},
+        models::Attributes,
+    };
+
+    pub fn client_config(address: String) -> ContentRepositoryClientConfig {
+        ContentRepositoryClientConfig {
+            address,
+            max_connections: 4,
+            connect_timeout_ms: 1000,
+            request_timeout_ms: 1000,
+            max_retries: 0,
+            retry_backoff_ms: 10,
+        }
+    }
+
+    pub type Store = Arc<Mutex<HashMap<ContentReference, (Bytes, u64)>>>;
+
+    /// Serves content repository requests from memory. Content is kept when its claims are
+    /// released, so that tests can check how many claims are left, but it cannot be claimed
+    /// again.
+    pub async fn spawn_content_repository() -> (String, Store) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let store = Store::default();
+        let server_store = store.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let store = server_store.clone();
+                tokio::spawn(async move {
+                    while let Ok(Some(request)) = protocol::read_frame(&mut socket).await {
+                        let response = handle_request(&store, request);
+                        for frame in response {
+                            protocol::write_frame(&mut socket, &frame).await.unwrap();
+                        }
+                    }
+                });
+            }
+        });
+        (address, store)
+    }
+
+    fn handle_request(store: &Store, request: Frame) -> Vec<Frame> {
+        let mut store = store.lock().unwrap();
+        if request.opcode == OpCode::Write {
+            let reference = ContentReference {
+                segment: "wal-000001.seg".to_string(),
+                offset: store.len() as u64,
+                length: request.payload.len() as u64,
+            };
+            store.insert(reference.clone(), (request.payload, 1));
+            return vec![Frame::reference(&reference)];
+        }
+        let reference = ContentReference::decode(request.opcode, request.payload).unwrap();
+        let (content, claims) = store.get_mut(&reference).unwrap();
+        match request.opcode {
+            OpCode::Read => vec![
+                Frame::new(OpCode::Content, content.clone()),
+                Frame::new(OpCode::ContentEnd, Bytes::new()),
+            ],
+            OpCode::Claim if *claims == 0 => vec![Frame::error("Content is not claimed")],
+            OpCode::Claim => {
+                *claims += 1;
+                vec![Frame::claim_count(*claims)]
+            }
+            _ => {
+                *claims -= 1;
+                vec![Frame::claim_count(*claims)]
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_large_packets_are_offloaded() {
+        let (address, store) = spawn_content_repository().await;
+        let offloader = ContentOffloader::new(
+            ContentRepositoryClient::new(client_config(address)),
+            Some(4),
+        );
+        let (first_inbox, second_inbox) = (Inbox::default(), Inbox::default());
+        let peers_tx = HashMap::from([
+            (
+                Uuid::new_v4(),
+                ConnectionQueue::new(BackPressure::default(), &first_inbox).unwrap(),
+            ),
+            (
+                Uuid::new_v4(),
+                ConnectionQueue::new(BackPressure::default(), &second_inbox).unwrap(),
+            ),
+        ]);
+
+        let small_packet = InMemoryPacket {
+            id: Uuid::new_v4(),
+            attributes: Attributes::new(),
+            data: Bytes::from_static(&[1, 2, 3, 4]),
+        };
+        assert_eq!(
+            offloader
+                .send_to_peers(small_packet.into(), &peers_tx)
+                .await,
+            2
+        );
+        assert!(matches!(
+            first_inbox.recv().await,
+            Message::InMemoryMessage(_)
+        ));
+        assert!(matches!(
+            second_inbox.recv().await,
+            Message::InMemoryMessage(_)
+        ));
+        assert!(store.lock().unwrap().is_empty());
+
+        let large_packet = InMemoryPacket {
+            id: Uuid::new_v4(),
+            attributes: Attributes::from([("filename".to_string(), "large.bin".to_string())]),
+            data: Bytes::from_static(&[1, 2, 3, 4, 5]),
+        };
+        assert_eq!(
+            offloader
+                .send_to_peers(large_packet.clone().into(), &peers_tx)
+                .await,
+            2
+        );
+        let first_message = first_inbox.recv().await;
+        let Message::ReferenceMessage(reference_packet) = &first_message else {
+            panic!("Expected ReferenceMessage, got {:?}", first_message);
+        };
+        assert_eq!(reference_packet.id, large_packet.id);
+        assert_eq!(reference_packet.length, 5);
+        assert_eq!(reference_packet.attributes, large_packet.attributes);
+        let reference = content_reference(reference_packet);
+        let claims = || store.lock().unwrap()[&reference].1;
+        assert_eq!(claims(), 2);
+
+        assert_eq!(
+            offloader.read(&first_message).await.unwrap(),
+            large_packet.data
+        );
+        assert_eq!(claims(), 2);
+        offloader.discard(first_message).await;
+        assert_eq!(claims(), 1);
+
+        offloader.discard(second_inbox.recv().await).await;
+        assert_eq!(claims(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_packets_stay_in_memory_when_content_repository_is_unavailable() {
+        let offloader = ContentOffloader::new(
+            ContentRepositoryClient::new(client_config("127.0.0.1:1".to_string())),
+            Some(0),
+        );
+        let inbox = Inbox::default();
+        let peers_tx = HashMap::from([(
+            Uuid::new_v4(),
+            ConnectionQueue::new(BackPressure::default(), &inbox).unwrap(),
+        )]);
+        let packet = InMemoryPacket {
+            id: Uuid::new_v4(),
+            attributes: Attributes::new(),
+            data: Bytes::from_static(&[1, 2, 3]),
+        };
+        assert_eq!(offloader.send_to_peers(packet.into(), &peers_tx).await, 1);
+        match inbox.recv().await {
+            Message::InMemoryMessage(received_packet) => {
+                assert_eq!(&received_packet.data[..], &[1, 2, 3])
+            }
+            other => panic!("Expected InMemoryMessage, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_packets_are_copied_into_memory_when_content_cannot_be_claimed() {
+        let (address, store) = spawn_content_repository().await;
+        let client = ContentRepositoryClient::new(client_config(address));
+        let offloader = ContentOffloader::new(client.clone(), Some(0));
+        let (first_inbox, second_inbox) = (Inbox::default(), Inbox::default());
+        let peers_tx = HashMap::from([
+            (
+                Uuid::new_v4(),
+                ConnectionQueue::new(BackPressure::default(), &first_inbox).unwrap(),
+            ),
+            (
+                Uuid::new_v4(),
+                ConnectionQueue::new(BackPressure::default(), &second_inbox).unwrap(),
+            ),
+        ]);
+
+        // Content without claims cannot be claimed again.
+        let reference = client.put(Bytes::from_static(&[1, 2, 3])).await.unwrap();
+        client.release(&reference).await.unwrap();
+        let reference_packet = ReferencePacket {
+            id: Uuid::new_v4(),
+            attributes: Attributes::new(),
+            file_name: reference.segment.clone(),
+            offset: reference.offset,
+            length: reference.length,
+        };
+        assert_eq!(
+            offloader
+                .send_to_peers(Message::ReferenceMessage(reference_packet), &peers_tx)
+                .await,
+            2
+        );
+
+        let messages = [first_inbox.recv().await, second_inbox.recv().await];
+        let references = messages
+            .iter()
+            .filter(|message| matches!(message, Message::ReferenceMessage(_)))
+            .count();
+        assert_eq!(references, 1);
+        for message in messages.iter() {
+            assert_eq!(&offloader.read(message).await.unwrap()[..], &[1, 2, 3]);
+        }
+        assert_eq!(store.lock().unwrap()[&reference].1, 0);
+    }
+}
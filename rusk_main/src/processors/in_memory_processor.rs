@@ -1,10 +1,24 @@
-use crate::handlers::models::ProcessorInfo;
+use crate::handlers::models::{PacketInfo, ProcessorInfo};
 
-use super::base_processor::{ProcessorConnection, SinkProcessor};
-use super::models::{InMemoryPacket, Message, ProcessorCommand, ProcessorStatus};
+use super::base_processor::{
+    connect_peer, connection_statuses, disconnect_peer, is_back_pressured, route_packets,
+    ProcessorConnection, SinkProcessor, BACK_PRESSURE_CHECK_INTERVAL,
+};
+use super::bulletins::BulletinBoard;
+use super::connection::{ConnectionQueue, Inbox};
+use super::content_offloader::ContentOffloader;
+use super::models::{
+    PeersTx, ProcessorCommand, ProcessorStatus, ProcessorType, UpdatePropertiesError,
+    UpdateSchedulingError,
+};
+use super::processor::{NewProcessor, ProcessContext, ProcessSession};
+use super::properties::ProcessorProperties;
+use super::provenance::{ProvenanceEvent, ProvenanceEventType, ProvenanceRepository};
+use super::scheduling::Schedule;
+use super::supervisor::SupervisedProcessor;
 
-use std::collections::HashMap;
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use tokio::{sync::mpsc, time::sleep};
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
@@ -13,67 +27,133 @@ pub struct InMemoryProcessor {
     pub processor_id: Uuid,
     status: super::models::ProcessorStatus,
     parent_rx: mpsc::Receiver<ProcessorCommand>,
-    peers_rx: mpsc::Receiver<Message>,
-    peers_tx: HashMap<Uuid, mpsc::Sender<Message>>,
+    inbox: Inbox,
+    peers_tx: PeersTx,
+    properties: ProcessorProperties,
+    schedule: Schedule,
+    context: ProcessContext,
+    content_offloader: ContentOffloader,
     cancellation_token: CancellationToken,
     packets_processed_count: u64,
+    last_packet: Option<PacketInfo>,
+    /// Why the processor last became `Errored`.
+    last_error: Option<String>,
 }
 
 impl SinkProcessor for InMemoryProcessor {
     fn new(
         processor_name: String,
-        peers_rx: mpsc::Receiver<Message>,
+        inbox: Inbox,
         parent_rx: mpsc::Receiver<ProcessorCommand>,
+        properties: ProcessorProperties,
+        schedule: Schedule,
+        content_offloader: ContentOffloader,
+        bulletin_board: BulletinBoard,
+        provenance: ProvenanceRepository,
         cancellation_token: CancellationToken,
     ) -> Self {
+        let processor_id = Uuid::new_v4();
+        let context = ProcessContext {
+            processor_id,
+            processor_name: processor_name.clone(),
+            properties: properties.values().clone(),
+            bulletin_board,
+            provenance,
+        };
         InMemoryProcessor {
             processor_name,
-            processor_id: Uuid::new_v4(),
+            processor_id,
             status: super::models::ProcessorStatus::Stopped,
             parent_rx,
-            peers_rx,
-            peers_tx: HashMap::new(),
+            inbox,
+            peers_tx: PeersTx::new(),
+            properties,
+            schedule,
+            context,
+            content_offloader,
             cancellation_token,
             packets_processed_count: 0,
+            last_packet: None,
+            last_error: None,
         }
     }
 }
 
 impl ProcessorConnection for InMemoryProcessor {
-    fn connect_processor(&mut self, receiver_processor_id: Uuid, tx: mpsc::Sender<Message>) {
-        self.peers_tx.insert(receiver_processor_id, tx);
+    fn connect_processor(
+        &mut self,
+        relationship: String,
+        receiver_processor_id: Uuid,
+        queue: Arc<ConnectionQueue>,
+    ) {
+        connect_peer(
+            &mut self.peers_tx,
+            relationship,
+            receiver_processor_id,
+            queue,
+        );
     }
 
-    fn disconnect_processor(&mut self, receiver_processor_id: Uuid) {
-        self.peers_tx.remove(&receiver_processor_id);
+    fn disconnect_processor(&mut self, relationship: Option<&str>, receiver_processor_id: Uuid) {
+        disconnect_peer(&mut self.peers_tx, relationship, receiver_processor_id);
+    }
+}
+
+#[async_trait::async_trait]
+impl SupervisedProcessor for InMemoryProcessor {
+    async fn run(&mut self, new_processor: NewProcessor) {
+        InMemoryProcessor::run(self, new_processor).await;
+    }
+
+    fn panicked(&mut self, message: String) {
+        self.context.error(format!("Panicked: {}", message));
+        self.status = ProcessorStatus::Errored;
+        self.last_error = Some(format!("Panicked: {}", message));
     }
 }
 
 impl InMemoryProcessor {
-    pub async fn run(&mut self, process_packet_func: fn(InMemoryPacket) -> Option<InMemoryPacket>) {
+    pub async fn run(&mut self, new_processor: NewProcessor) {
+        let mut processor = new_processor();
         loop {
             tokio::select! {
                 Some(command) = self.parent_rx.recv() => {
                     match command {
                         ProcessorCommand::Stop {resp} => {
+                            if self.status == ProcessorStatus::Running {
+                                processor.on_stopped(&self.context).await;
+                            }
                             self.status = ProcessorStatus::Stopped;
-                            println!("{}: Stopped", self.processor_name);
+                            tracing::debug!("{}: Stopped", self.processor_name);
                             resp.send(self.status).unwrap();
                         }
                         ProcessorCommand::Start {resp} => {
-                            self.status = ProcessorStatus::Running;
-                            println!("{}: Started running...", self.processor_name);
+                            if self.status != ProcessorStatus::Running {
+                                self.status = match processor.on_scheduled(&self.context).await {
+                                    Ok(()) => {
+                                        // Packets may have been queued while it was stopped.
+                                        self.inbox.wake();
+                                        ProcessorStatus::Running
+                                    }
+                                    Err(e) => {
+                                        self.context.error(format!("Failed to start: {}", e));
+                                        self.last_error = Some(format!("Failed to start: {}", e));
+                                        ProcessorStatus::Errored
+                                    }
+                                };
+                            }
+                            tracing::debug!("{}: Started running...", self.processor_name);
                             resp.send(self.status).unwrap();
                         }
                         ProcessorCommand::GetStatus {resp} => {
                             resp.send(self.status).unwrap();
                         }
-                        ProcessorCommand::Connect {destination_processor_id, destination_processor_tx, resp} => {
-                            self.connect_processor(destination_processor_id, destination_processor_tx);
+                        ProcessorCommand::Connect {relationship, destination_processor_id, queue, resp} => {
+                            self.connect_processor(relationship, destination_processor_id, queue);
                             resp.send(self.status).unwrap();
                         }
-                        ProcessorCommand::Disconnect {destination_processor_id, resp} => {
-                            self.disconnect_processor(destination_processor_id);
+                        ProcessorCommand::Disconnect {relationship, destination_processor_id, resp} => {
+                            self.disconnect_processor(relationship.as_deref(), destination_processor_id);
                             resp.send(self.status).unwrap();
                         }
                         ProcessorCommand::GetInfo {resp} => {
@@ -81,55 +161,89 @@ impl InMemoryProcessor {
                                 processor_id: self.processor_id.to_string(),
                                 status: self.status,
                                 packets_processed_count: self.packets_processed_count,
+                                properties: self.properties.values().clone(),
+                                scheduling: Some(self.schedule.settings().clone()),
+                                last_packet: self.last_packet.clone(),
+                                connections: connection_statuses(&self.peers_tx),
+                                back_pressured: is_back_pressured(&self.peers_tx),
+                                last_error: self.last_error.clone(),
                             };
                             resp.send(processor_info).unwrap();
                         }
-                    }
-                }
-                Some(command) = self.peers_rx.recv() => {
-                    println!("Received command from a peer");
-                    match command {
-                        Message::InMemoryMessage(packet) if self.status == ProcessorStatus::Running => {
-                            tracing::info!(
-                                "{}: Packet received.",
-                                self.processor_name
-                            );
-                            println!(
-                                "{}: Packet received : {:?}",
-                                self.processor_name, packet
-                            );
-
-                            if !self.peers_tx.is_empty() {
-                            let processed_packet = process_packet_func(packet);
-                            match processed_packet {
-                                Some(packet) => {
-                                    for tx in self.peers_tx.values() {
-                                        let _ = tx
-                                            .send(Message::InMemoryMessage(packet.clone()))
-                                            .await;
-                                    }
-                                    tracing::info!("{}: Sent packet to {} processors", self.processor_name, self.peers_tx.len());
-                                    self.packets_processed_count += 1;
-                                    tracing::info!("{}: Processed {} packets.", self.processor_name, self.packets_processed_count);
-                                }
-                                None => {
-                                    tracing::error!(
-                                        "{}: Error processing packet", self.processor_name);
-                                }
-                            }
-                            }
+                        ProcessorCommand::UpdateProperties {properties, resp} => {
+                            let result = match self.status {
+                                ProcessorStatus::Running => Err(UpdatePropertiesError::ProcessorRunning),
+                                _ => self.properties
+                                    .update(&properties)
+                                    .map(|_| self.properties.values().clone())
+                                    .map_err(UpdatePropertiesError::InvalidProperties),
+                            };
+                            self.context.properties = self.properties.values().clone();
+                            resp.send(result).unwrap();
                         }
-                        other => {
-                            tracing::error!(
-                                "{}: Received an unexpected message : {:?}",
-                                self.processor_name, other
-                            );
+                        ProcessorCommand::UpdateScheduling {scheduling, resp} => {
+                            let result = match self.status {
+                                ProcessorStatus::Running => Err(UpdateSchedulingError::ProcessorRunning),
+                                _ => Schedule::new(scheduling, ProcessorType::Other)
+                                    .map(|schedule| {
+                                        self.schedule = schedule;
+                                        self.schedule.settings().clone()
+                                    })
+                                    .map_err(UpdateSchedulingError::InvalidScheduling),
+                            };
+                            resp.send(result).unwrap();
                         }
                     }
                 }
+                _ = self.inbox.notified(),
+                    if self.status == ProcessorStatus::Running && !is_back_pressured(&self.peers_tx) => {
+                    let Some(message) = self.inbox.pop() else {
+                        continue;
+                    };
+                    // Takes the next packet once this one is processed.
+                    if !self.inbox.is_empty() {
+                        self.inbox.wake();
+                    }
+                    tracing::debug!("{}: Packet {} received.", self.processor_name, message.id());
+
+                    let received = ProvenanceEvent::of_message(
+                        ProvenanceEventType::Receive,
+                        self.processor_id,
+                        &message,
+                    );
+                    self.context.provenance.record(received.clone());
+
+                    if self.peers_tx.is_empty() {
+                        self.context.provenance.record(
+                            ProvenanceEvent { event_type: ProvenanceEventType::Drop, ..received }
+                                .with_details("Processor has no connections".to_string()),
+                        );
+                        self.content_offloader.discard(message).await;
+                        continue;
+                    }
+                    // Offloaded content is only loaded if the processor reads it.
+                    let mut session =
+                        ProcessSession::new(vec![message], self.content_offloader.clone());
+                    let result = processor.on_trigger(&self.context, &mut session).await;
+                    let routed_packets = session.commit(&self.context, result).await;
+                    if let Some(last_packet) = route_packets(
+                        &self.context,
+                        &self.peers_tx,
+                        &self.content_offloader,
+                        routed_packets,
+                    ).await {
+                        self.last_packet = Some(last_packet);
+                    }
+                    self.packets_processed_count += 1;
+                    tracing::info!("{}: Processed {} packets.", self.processor_name, self.packets_processed_count);
+                }
+                _ = sleep(BACK_PRESSURE_CHECK_INTERVAL),
+                    if self.status == ProcessorStatus::Running && is_back_pressured(&self.peers_tx) => {}
                 _ = self.cancellation_token.cancelled() => {
+                    if self.status == ProcessorStatus::Running {
+                        processor.on_stopped(&self.context).await;
+                    }
                     tracing::info!("{}: Cancellation token received. Shutting down.", self.processor_name);
-                    println!("{}: Cancellation token received. Shutting down.", self.processor_name);
                     break;
                 }
             }
@@ -141,25 +255,48 @@ impl InMemoryProcessor {
 mod tests {
     use std::{thread::sleep, time::Duration};
 
+    use std::collections::HashMap;
+
     use super::*;
-    use crate::{doubler_func, processors::models::ProcessorStatus};
+    use crate::processors::{
+        connection::BackPressure,
+        models::Message,
+        processor::{Processor, ProcessorError},
+    };
+    use crate::{
+        processor_functions::doubler::Doubler,
+        processors::{
+            content_offloader,
+            models::{
+                attributes, relationships, Attributes, InMemoryPacket, ProcessorStatus,
+                ReferencePacket,
+            },
+        },
+    };
+    use content_repository_client::{ContentReference, ContentRepositoryClient};
     use tokio::sync::{mpsc, oneshot};
 
     #[tokio::test]
     async fn test_in_memory_processor() {
         let (parent_tx, parent_rx) = mpsc::channel(10);
-        let (peers_tx, peers_rx) = mpsc::channel(10);
+        let inbox = Inbox::default();
+        let queue = ConnectionQueue::new(BackPressure::default(), &inbox).unwrap();
         let cancellation_token = CancellationToken::new();
 
         let mut processor = InMemoryProcessor::new(
             "test_in_memory_processor".to_string(),
-            peers_rx,
+            inbox.clone(),
             parent_rx,
+            crate::processor_registry().default_properties("doubler"),
+            Schedule::default_for(ProcessorType::Other),
+            ContentOffloader::disabled(),
+            BulletinBoard::default(),
+            ProvenanceRepository::default(),
             cancellation_token.clone(),
         );
 
         tokio::spawn(async move {
-            processor.run(doubler_func).await;
+            processor.run(|| Box::new(Doubler::default())).await;
         });
 
         let (oneshot_tx, oneshot_rx) = oneshot::channel::<ProcessorStatus>();
@@ -169,11 +306,12 @@ mod tests {
         let status = oneshot_rx.await.unwrap();
         assert_eq!(status, ProcessorStatus::Running);
 
-        let (sink_tx, mut sink_rx) = mpsc::channel::<Message>(10);
+        let sink_inbox = Inbox::default();
         let (oneshot_tx, oneshot_rx) = oneshot::channel::<ProcessorStatus>();
         let connect_processor_command = ProcessorCommand::Connect {
+            relationship: relationships::SUCCESS.to_string(),
             destination_processor_id: Uuid::new_v4(),
-            destination_processor_tx: sink_tx,
+            queue: ConnectionQueue::new(BackPressure::default(), &sink_inbox).unwrap(),
             resp: oneshot_tx,
         };
         parent_tx.send(connect_processor_command).await.unwrap();
@@ -182,21 +320,384 @@ mod tests {
 
         sleep(Duration::from_millis(500));
 
+        let attributes =
+            Attributes::from([(attributes::FILENAME.to_string(), "numbers.bin".to_string())]);
         let message = Message::InMemoryMessage(InMemoryPacket {
             id: Uuid::new_v4(),
-            data: vec![1, 2, 3, 4],
+            attributes: attributes.clone(),
+            data: vec![1, 2, 3, 4].into(),
         });
 
-        peers_tx.send(message).await.unwrap();
+        queue.push(message);
 
-        let message_from_processor = sink_rx.recv().await.unwrap();
+        let message_from_processor = sink_inbox.recv().await;
         match message_from_processor {
             Message::InMemoryMessage(packet) => {
                 assert_eq!(packet.data, vec![2, 4, 6, 8]);
+                assert_eq!(packet.attributes, attributes);
             }
             _ => panic!("Expected InMemoryMessage"),
         }
 
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::GetInfo { resp: oneshot_tx })
+            .await
+            .unwrap();
+        let last_packet = oneshot_rx.await.unwrap().last_packet.unwrap();
+        assert_eq!(last_packet.size_bytes, 4);
+        assert_eq!(last_packet.attributes, attributes);
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_offloaded_packets_are_loaded_for_processing() {
+        let (address, store) = content_offloader::tests::spawn_content_repository().await;
+        let client = ContentRepositoryClient::new(content_offloader::tests::client_config(address));
+        let (parent_tx, parent_rx) = mpsc::channel(10);
+        let inbox = Inbox::default();
+        let queue = ConnectionQueue::new(BackPressure::default(), &inbox).unwrap();
+        let cancellation_token = CancellationToken::new();
+        let mut processor = InMemoryProcessor::new(
+            "test_offloading_processor".to_string(),
+            inbox.clone(),
+            parent_rx,
+            crate::processor_registry().default_properties("doubler"),
+            Schedule::default_for(ProcessorType::Other),
+            ContentOffloader::new(client.clone(), Some(2)),
+            BulletinBoard::default(),
+            ProvenanceRepository::default(),
+            cancellation_token.clone(),
+        );
+        tokio::spawn(async move {
+            processor.run(|| Box::new(Doubler::default())).await;
+        });
+
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::Start { resp: oneshot_tx })
+            .await
+            .unwrap();
+        oneshot_rx.await.unwrap();
+        let (sink_inbox, original_inbox) = (Inbox::default(), Inbox::default());
+        for (relationship, inbox) in [
+            (relationships::SUCCESS, &sink_inbox),
+            (relationships::ORIGINAL, &original_inbox),
+        ] {
+            let (oneshot_tx, oneshot_rx) = oneshot::channel();
+            parent_tx
+                .send(ProcessorCommand::Connect {
+                    relationship: relationship.to_string(),
+                    destination_processor_id: Uuid::new_v4(),
+                    queue: ConnectionQueue::new(BackPressure::default(), inbox).unwrap(),
+                    resp: oneshot_tx,
+                })
+                .await
+                .unwrap();
+            oneshot_rx.await.unwrap();
+        }
+
+        let reference = client
+            .put(bytes::Bytes::from_static(&[1, 2, 3]))
+            .await
+            .unwrap();
+        let packet_id = Uuid::new_v4();
+        queue.push(Message::ReferenceMessage(ReferencePacket {
+            id: packet_id,
+            attributes: Attributes::from([(
+                attributes::MIME_TYPE.to_string(),
+                "application/octet-stream".to_string(),
+            )]),
+            file_name: reference.segment.clone(),
+            offset: reference.offset,
+            length: reference.length,
+        }));
+
+        // Processed packet is still above the threshold, so it is offloaded again.
+        match sink_inbox.recv().await {
+            Message::ReferenceMessage(packet) => {
+                assert_ne!(packet.id, packet_id);
+                assert_eq!(
+                    packet.attributes[attributes::MIME_TYPE],
+                    "application/octet-stream"
+                );
+                let reference = ContentReference {
+                    segment: packet.file_name,
+                    offset: packet.offset,
+                    length: packet.length,
+                };
+                assert_eq!(&client.get(&reference).await.unwrap()[..], &[2, 4, 6]);
+            }
+            other => panic!("Expected ReferenceMessage, got {:?}", other),
+        }
+
+        // Received packet is sent on unchanged, by reference, without storing it again.
+        match original_inbox.recv().await {
+            Message::ReferenceMessage(packet) => {
+                assert_eq!(packet.id, packet_id);
+                assert_eq!(packet.offset, reference.offset);
+                assert_eq!(packet.file_name, reference.segment);
+            }
+            other => panic!("Expected ReferenceMessage, got {:?}", other),
+        }
+        let store = store.lock().unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store[&reference].1, 1);
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_packets_are_routed_by_relationship() {
+        let (parent_tx, parent_rx) = mpsc::channel(10);
+        let inbox = Inbox::default();
+        let queue = ConnectionQueue::new(BackPressure::default(), &inbox).unwrap();
+        let cancellation_token = CancellationToken::new();
+        let mut processor = InMemoryProcessor::new(
+            "test_routing_processor".to_string(),
+            inbox.clone(),
+            parent_rx,
+            crate::processor_registry().default_properties("doubler"),
+            Schedule::default_for(ProcessorType::Other),
+            ContentOffloader::disabled(),
+            BulletinBoard::default(),
+            ProvenanceRepository::default(),
+            cancellation_token.clone(),
+        );
+        tokio::spawn(async move {
+            processor.run(|| Box::new(Doubler::default())).await;
+        });
+
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::Start { resp: oneshot_tx })
+            .await
+            .unwrap();
+        oneshot_rx.await.unwrap();
+        let mut sink_inboxes = HashMap::new();
+        for relationship in [relationships::SUCCESS, relationships::FAILURE] {
+            let sink_inbox = Inbox::default();
+            let (oneshot_tx, oneshot_rx) = oneshot::channel();
+            parent_tx
+                .send(ProcessorCommand::Connect {
+                    relationship: relationship.to_string(),
+                    destination_processor_id: Uuid::new_v4(),
+                    queue: ConnectionQueue::new(BackPressure::default(), &sink_inbox).unwrap(),
+                    resp: oneshot_tx,
+                })
+                .await
+                .unwrap();
+            oneshot_rx.await.unwrap();
+            sink_inboxes.insert(relationship, sink_inbox);
+        }
+
+        for data in [vec![200, 1], vec![1, 2]] {
+            queue.push(Message::InMemoryMessage(InMemoryPacket {
+                id: Uuid::new_v4(),
+                attributes: Attributes::new(),
+                data: data.into(),
+            }));
+        }
+
+        match sink_inboxes[relationships::FAILURE].recv().await {
+            Message::InMemoryMessage(packet) => assert_eq!(packet.data, vec![200, 1]),
+            other => panic!("Expected InMemoryMessage, got {:?}", other),
+        }
+        let success_inbox = &sink_inboxes[relationships::SUCCESS];
+        match success_inbox.recv().await {
+            Message::InMemoryMessage(packet) => assert_eq!(packet.data, vec![2, 4]),
+            other => panic!("Expected InMemoryMessage, got {:?}", other),
+        }
+        assert!(success_inbox.pop().is_none());
+        assert!(sink_inboxes[relationships::FAILURE].is_empty());
+
+        cancellation_token.cancel();
+    }
+
+    struct UnschedulableProcessor;
+
+    #[async_trait::async_trait]
+    impl Processor for UnschedulableProcessor {
+        async fn on_scheduled(&mut self, _context: &ProcessContext) -> Result<(), ProcessorError> {
+            Err(ProcessorError("cannot be scheduled".to_string()))
+        }
+
+        async fn on_trigger(
+            &mut self,
+            _context: &ProcessContext,
+            _session: &mut ProcessSession,
+        ) -> Result<(), ProcessorError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_processor_errors_when_it_cannot_be_scheduled() {
+        let (parent_tx, parent_rx) = mpsc::channel(10);
+        let cancellation_token = CancellationToken::new();
+        let mut processor = InMemoryProcessor::new(
+            "test_unschedulable_processor".to_string(),
+            Inbox::default(),
+            parent_rx,
+            crate::processor_registry().default_properties("doubler"),
+            Schedule::default_for(ProcessorType::Other),
+            ContentOffloader::disabled(),
+            BulletinBoard::default(),
+            ProvenanceRepository::default(),
+            cancellation_token.clone(),
+        );
+        tokio::spawn(async move {
+            processor.run(|| Box::new(UnschedulableProcessor)).await;
+        });
+
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::Start { resp: oneshot_tx })
+            .await
+            .unwrap();
+        let status = oneshot_rx.await.unwrap();
+        assert_eq!(status, ProcessorStatus::Errored);
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_full_connection_holds_back_processor() {
+        let (parent_tx, parent_rx) = mpsc::channel(10);
+        let inbox = Inbox::default();
+        let queue = ConnectionQueue::new(BackPressure::default(), &inbox).unwrap();
+        let cancellation_token = CancellationToken::new();
+        let mut processor = InMemoryProcessor::new(
+            "test_back_pressured_processor".to_string(),
+            inbox.clone(),
+            parent_rx,
+            crate::processor_registry().default_properties("doubler"),
+            Schedule::default_for(ProcessorType::Other),
+            ContentOffloader::disabled(),
+            BulletinBoard::default(),
+            ProvenanceRepository::default(),
+            cancellation_token.clone(),
+        );
+        tokio::spawn(async move {
+            processor.run(|| Box::new(Doubler::default())).await;
+        });
+        let sink_inbox = Inbox::default();
+        let back_pressure = BackPressure {
+            object_threshold: 1,
+            data_size_threshold: "1 KB".to_string(),
+        };
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::Connect {
+                relationship: relationships::SUCCESS.to_string(),
+                destination_processor_id: Uuid::new_v4(),
+                queue: ConnectionQueue::new(back_pressure.clone(), &sink_inbox).unwrap(),
+                resp: oneshot_tx,
+            })
+            .await
+            .unwrap();
+        oneshot_rx.await.unwrap();
+
+        // Packets wait in the queue while the processor is stopped.
+        for data in [1, 2] {
+            queue.push(Message::InMemoryMessage(InMemoryPacket {
+                id: Uuid::new_v4(),
+                attributes: Attributes::new(),
+                data: vec![data].into(),
+            }));
+        }
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::Start { resp: oneshot_tx })
+            .await
+            .unwrap();
+        oneshot_rx.await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::GetInfo { resp: oneshot_tx })
+            .await
+            .unwrap();
+        let processor_info = oneshot_rx.await.unwrap();
+        assert!(processor_info.back_pressured);
+        assert_eq!(processor_info.connections[0].queued_count, 1);
+        assert_eq!(processor_info.connections[0].back_pressure, back_pressure);
+        assert_eq!(queue.len(), 1);
+
+        // The second packet is processed once the first one is taken.
+        for expected_data in [2, 4] {
+            let Message::InMemoryMessage(packet) = sink_inbox.recv().await else {
+                panic!("Expected InMemoryMessage");
+            };
+            assert_eq!(packet.data, vec![expected_data]);
+        }
+        assert_eq!(queue.len(), 0);
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_packets_which_cannot_be_loaded_are_routed_to_failure() {
+        let (address, _) = content_offloader::tests::spawn_content_repository().await;
+        let client = ContentRepositoryClient::new(content_offloader::tests::client_config(address));
+        let (parent_tx, parent_rx) = mpsc::channel(10);
+        let inbox = Inbox::default();
+        let queue = ConnectionQueue::new(BackPressure::default(), &inbox).unwrap();
+        let cancellation_token = CancellationToken::new();
+        let mut processor = InMemoryProcessor::new(
+            "test_failing_processor".to_string(),
+            inbox.clone(),
+            parent_rx,
+            crate::processor_registry().default_properties("doubler"),
+            Schedule::default_for(ProcessorType::Other),
+            ContentOffloader::new(client.clone(), Some(2)),
+            BulletinBoard::default(),
+            ProvenanceRepository::default(),
+            cancellation_token.clone(),
+        );
+        tokio::spawn(async move {
+            processor.run(|| Box::new(Doubler::default())).await;
+        });
+
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::Start { resp: oneshot_tx })
+            .await
+            .unwrap();
+        oneshot_rx.await.unwrap();
+        let failure_inbox = Inbox::default();
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::Connect {
+                relationship: relationships::FAILURE.to_string(),
+                destination_processor_id: Uuid::new_v4(),
+                queue: ConnectionQueue::new(BackPressure::default(), &failure_inbox).unwrap(),
+                resp: oneshot_tx,
+            })
+            .await
+            .unwrap();
+        oneshot_rx.await.unwrap();
+
+        // Content repository does not know the content, so it cannot be loaded.
+        let reference_packet = ReferencePacket {
+            id: Uuid::new_v4(),
+            attributes: Attributes::new(),
+            file_name: "wal-000009.seg".to_string(),
+            offset: 0,
+            length: 3,
+        };
+        queue.push(Message::ReferenceMessage(reference_packet.clone()));
+
+        match failure_inbox.recv().await {
+            Message::ReferenceMessage(packet) => {
+                assert_eq!(packet.id, reference_packet.id);
+                assert_eq!(packet.file_name, reference_packet.file_name);
+            }
+            other => panic!("Expected ReferenceMessage, got {:?}", other),
+        }
+
         cancellation_token.cancel();
     }
 }
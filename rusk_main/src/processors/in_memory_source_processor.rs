@@ -1,81 +1,253 @@
-use std::{collections::HashMap, time::Duration};
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use crate::handlers::models::ProcessorInfo;
+use crate::handlers::models::{PacketInfo, ProcessorInfo};
 
-use super::base_processor::{ProcessorConnection, SourceProcessor};
-use super::models::{InMemoryPacket, Message, ProcessorCommand, ProcessorStatus};
-use tokio::{sync::mpsc, time::sleep};
+use super::base_processor::{
+    connect_peer, connection_statuses, disconnect_peer, is_back_pressured, route_packets,
+    ProcessorConnection, SourceProcessor, BACK_PRESSURE_CHECK_INTERVAL,
+};
+use super::bulletins::BulletinBoard;
+use super::connection::ConnectionQueue;
+use super::content_offloader::ContentOffloader;
+use super::models::{
+    attributes, Message, PeersTx, ProcessorCommand, ProcessorStatus, ProcessorType, RoutedPacket,
+    UpdatePropertiesError, UpdateSchedulingError,
+};
+use super::processor::{
+    schedule_all, stop_all, NewProcessor, ProcessContext, ProcessSession, Processor,
+};
+use super::properties::ProcessorProperties;
+use super::provenance::ProvenanceRepository;
+use super::scheduling::Schedule;
+use super::supervisor::SupervisedProcessor;
+use futures::stream::{FuturesUnordered, StreamExt};
+use tokio::{
+    sync::mpsc,
+    task::{JoinError, JoinHandle},
+    time::{sleep, sleep_until, Instant},
+};
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+/// Task handed back after it was triggered, with the packets it produced.
+type TriggeredTask = (Box<dyn Processor>, Vec<RoutedPacket>);
+type Running = FuturesUnordered<JoinHandle<TriggeredTask>>;
+
 pub struct InMemorySourceProcessor {
     pub processor_name: String,
     pub processor_id: Uuid,
     pub status: super::models::ProcessorStatus,
     parent_rx: mpsc::Receiver<ProcessorCommand>,
-    peers_tx: HashMap<Uuid, mpsc::Sender<Message>>,
+    peers_tx: PeersTx,
+    properties: ProcessorProperties,
+    schedule: Schedule,
+    context: ProcessContext,
+    content_offloader: ContentOffloader,
     cancellation_token: CancellationToken,
     packets_processed_count: u64,
+    last_packet: Option<PacketInfo>,
+    /// Why the processor last became `Errored`.
+    last_error: Option<String>,
 }
 
 impl SourceProcessor for InMemorySourceProcessor {
     fn new(
         processor_name: String,
         parent_rx: mpsc::Receiver<ProcessorCommand>,
-        peer_processors_tx: HashMap<Uuid, mpsc::Sender<Message>>,
+        peer_processors_tx: PeersTx,
+        properties: ProcessorProperties,
+        schedule: Schedule,
+        content_offloader: ContentOffloader,
+        bulletin_board: BulletinBoard,
+        provenance: ProvenanceRepository,
         cancellation_token: CancellationToken,
     ) -> Self {
+        let processor_id = Uuid::new_v4();
+        let context = ProcessContext {
+            processor_id,
+            processor_name: processor_name.clone(),
+            properties: properties.values().clone(),
+            bulletin_board,
+            provenance,
+        };
         InMemorySourceProcessor {
             processor_name,
-            processor_id: Uuid::new_v4(),
+            processor_id,
             status: super::models::ProcessorStatus::Stopped,
             parent_rx,
             peers_tx: peer_processors_tx,
+            properties,
+            schedule,
+            context,
+            content_offloader,
             cancellation_token,
             packets_processed_count: 0,
+            last_packet: None,
+            last_error: None,
         }
     }
 }
 
 impl ProcessorConnection for InMemorySourceProcessor {
-    fn disconnect_processor(&mut self, receiver_processor_id: Uuid) {
-        self.peers_tx.remove(&receiver_processor_id);
+    fn disconnect_processor(&mut self, relationship: Option<&str>, receiver_processor_id: Uuid) {
+        disconnect_peer(&mut self.peers_tx, relationship, receiver_processor_id);
     }
 
     fn connect_processor(
         &mut self,
+        relationship: String,
         receiver_processor_id: Uuid,
-        tx_for_receiver: mpsc::Sender<Message>,
+        queue: Arc<ConnectionQueue>,
     ) {
-        self.peers_tx.insert(receiver_processor_id, tx_for_receiver);
+        connect_peer(
+            &mut self.peers_tx,
+            relationship,
+            receiver_processor_id,
+            queue,
+        );
+    }
+}
+
+#[async_trait::async_trait]
+impl SupervisedProcessor for InMemorySourceProcessor {
+    async fn run(&mut self, new_processor: NewProcessor) {
+        InMemorySourceProcessor::run(self, new_processor).await;
+    }
+
+    fn panicked(&mut self, message: String) {
+        self.context.error(format!("Panicked: {}", message));
+        self.status = ProcessorStatus::Errored;
+        self.last_error = Some(format!("Panicked: {}", message));
     }
 }
 
 impl InMemorySourceProcessor {
-    pub async fn run(&mut self, generate_packet_func: fn() -> Option<InMemoryPacket>) {
+    /// Adds the common attributes which the processor has not set itself.
+    fn add_core_attributes(&self, packet: &mut Message) {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        for (key, value) in [
+            (attributes::FILENAME, packet.id().to_string()),
+            (attributes::SOURCE, self.processor_name.clone()),
+            (attributes::CREATED_AT, created_at.to_string()),
+        ] {
+            packet
+                .attributes_mut()
+                .entry(key.to_string())
+                .or_insert(value);
+        }
+    }
+
+    /// Triggers the task once on a tokio task of its own, so that concurrent tasks run in
+    /// parallel. The task is handed back together with the packets it produced.
+    fn trigger(&self, mut task: Box<dyn Processor>) -> JoinHandle<TriggeredTask> {
+        let context = self.context.clone();
+        let content_offloader = self.content_offloader.clone();
+        tokio::spawn(async move {
+            let mut session = ProcessSession::new(vec![], content_offloader);
+            let result = task.on_trigger(&context, &mut session).await;
+            let routed_packets = session.commit(&context, result).await;
+            (task, routed_packets)
+        })
+    }
+
+    /// Routes the packets of a finished trigger and returns its task, along with whether it
+    /// produced any packets. A panic of the task is passed on to the supervisor.
+    async fn finish_trigger(
+        &mut self,
+        triggered: Result<TriggeredTask, JoinError>,
+    ) -> (Box<dyn Processor>, bool) {
+        let (task, mut routed_packets) = match triggered {
+            Ok(triggered) => triggered,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        };
+        for routed_packet in routed_packets.iter_mut() {
+            self.add_core_attributes(&mut routed_packet.packet);
+        }
+        let produced_packets = !routed_packets.is_empty();
+        self.packets_processed_count += routed_packets.len() as u64;
+        if let Some(last_packet) = route_packets(
+            &self.context,
+            &self.peers_tx,
+            &self.content_offloader,
+            routed_packets,
+        )
+        .await
+        {
+            self.last_packet = Some(last_packet);
+        }
+        (task, produced_packets)
+    }
+
+    /// Waits for the triggered tasks to finish, routes what they produced and puts them back.
+    async fn finish_all(&mut self, running: &mut Running, tasks: &mut Vec<Box<dyn Processor>>) {
+        while let Some(triggered) = running.next().await {
+            tasks.push(self.finish_trigger(triggered).await.0);
+        }
+    }
+
+    /// When the processor is triggered next, if ever.
+    fn next_trigger(&self, produced_packets: bool) -> Option<Instant> {
+        self.schedule
+            .next_delay(produced_packets)
+            .and_then(|delay| Instant::now().checked_add(delay))
+    }
+
+    pub async fn run(&mut self, new_processor: NewProcessor) {
+        // One instance of the processor for every concurrent task.
+        let mut tasks: Vec<Box<dyn Processor>> = vec![];
+        // Tasks which are being triggered, handed back when they return.
+        let mut running: Running = FuturesUnordered::new();
+        let mut next_trigger = None;
+        // Tasks are triggered again until the run duration has passed, and whether they
+        // produced packets meanwhile.
+        let mut run_until = Instant::now();
+        let mut produced_packets = false;
         loop {
             tokio::select! {
                 Some(command) = self.parent_rx.recv() => {
                     match command {
                         ProcessorCommand::Start {resp} => {
-                            self.status = ProcessorStatus::Running;
-                            println!("{}: Started running...", self.processor_name);
+                            if self.status != ProcessorStatus::Running {
+                                tasks.resize_with(self.schedule.concurrent_tasks(), new_processor);
+                                self.status = match schedule_all(&mut tasks, &self.context).await {
+                                    Ok(()) => {
+                                        next_trigger = self.next_trigger(true);
+                                        ProcessorStatus::Running
+                                    }
+                                    Err(e) => {
+                                        self.context.error(format!("Failed to start: {}", e));
+                                        self.last_error = Some(format!("Failed to start: {}", e));
+                                        ProcessorStatus::Errored
+                                    }
+                                };
+                            }
+                            tracing::debug!("{}: Started running...", self.processor_name);
                             resp.send(self.status).unwrap();
                         }
                         ProcessorCommand::Stop {resp} => {
+                            if self.status == ProcessorStatus::Running {
+                                self.finish_all(&mut running, &mut tasks).await;
+                                stop_all(&mut tasks, &self.context).await;
+                            }
                             self.status = ProcessorStatus::Stopped;
                             resp.send(self.status).unwrap();
                         }
                         ProcessorCommand::GetStatus {resp} => {
-                            println!("status of {}: {:?}", self.processor_name, self.status);
+                            tracing::debug!("status of {}: {:?}", self.processor_name, self.status);
                             resp.send(self.status).unwrap();
                         }
-                        ProcessorCommand::Connect {destination_processor_id, destination_processor_tx, resp} => {
-                            self.connect_processor(destination_processor_id, destination_processor_tx);
+                        ProcessorCommand::Connect {relationship, destination_processor_id, queue, resp} => {
+                            self.connect_processor(relationship, destination_processor_id, queue);
                             resp.send(self.status).unwrap();
                         }
-                        ProcessorCommand::Disconnect {destination_processor_id, resp} => {
-                            self.disconnect_processor(destination_processor_id);
+                        ProcessorCommand::Disconnect {relationship, destination_processor_id, resp} => {
+                            self.disconnect_processor(relationship.as_deref(), destination_processor_id);
                             resp.send(self.status).unwrap();
                         }
                         ProcessorCommand::GetInfo {resp} => {
@@ -83,28 +255,78 @@ impl InMemorySourceProcessor {
                                 processor_id: self.processor_id.to_string(),
                                 status: self.status,
                                 packets_processed_count: self.packets_processed_count,
+                                properties: self.properties.values().clone(),
+                                scheduling: Some(self.schedule.settings().clone()),
+                                last_packet: self.last_packet.clone(),
+                                connections: connection_statuses(&self.peers_tx),
+                                back_pressured: is_back_pressured(&self.peers_tx),
+                                last_error: self.last_error.clone(),
                             };
                             resp.send(processor_info).unwrap();
                         }
+                        ProcessorCommand::UpdateProperties {properties, resp} => {
+                            let result = match self.status {
+                                ProcessorStatus::Running => Err(UpdatePropertiesError::ProcessorRunning),
+                                _ => self.properties
+                                    .update(&properties)
+                                    .map(|_| self.properties.values().clone())
+                                    .map_err(UpdatePropertiesError::InvalidProperties),
+                            };
+                            self.context.properties = self.properties.values().clone();
+                            resp.send(result).unwrap();
+                        }
+                        ProcessorCommand::UpdateScheduling {scheduling, resp} => {
+                            let result = match self.status {
+                                ProcessorStatus::Running => Err(UpdateSchedulingError::ProcessorRunning),
+                                _ => Schedule::new(scheduling, ProcessorType::SourceProcessor)
+                                    .map(|schedule| {
+                                        self.schedule = schedule;
+                                        self.schedule.settings().clone()
+                                    })
+                                    .map_err(UpdateSchedulingError::InvalidScheduling),
+                            };
+                            resp.send(result).unwrap();
+                        }
                     }
                 }
                 _ = self.cancellation_token.cancelled() => {
+                    if self.status == ProcessorStatus::Running {
+                        self.finish_all(&mut running, &mut tasks).await;
+                        stop_all(&mut tasks, &self.context).await;
+                    }
                     tracing::info!("{}: Cancellation token received. Shutting down.", self.processor_name);
-                    println!("{}: Cancellation token received. Shutting down.", self.processor_name);
                     break;
                 }
-                _ = sleep(Duration::from_millis(100)) => {
-                    if self.status == ProcessorStatus::Running && !self.peers_tx.is_empty() {
-                            if let Some(packet) = generate_packet_func() {
-                                for tx in self.peers_tx.values() {
-                                    tx.send(Message::InMemoryMessage(packet.clone())).await.unwrap();
-                                }
-                                tracing::info!("{}: Sent packet to {} processors", self.processor_name, self.peers_tx.len());
-                                self.packets_processed_count += 1;
-                            tracing::info!("{}: Processed {} packets. Will sleep now for a while.", self.processor_name, self.packets_processed_count);
-                            }
+                _ = sleep_until(next_trigger.unwrap_or_else(Instant::now)),
+                    if self.status == ProcessorStatus::Running
+                        && running.is_empty()
+                        && !self.peers_tx.is_empty()
+                        && !is_back_pressured(&self.peers_tx)
+                        && next_trigger.is_some() => {
+                    run_until = Instant::now() + self.schedule.run_duration();
+                    produced_packets = false;
+                    for task in tasks.drain(..) {
+                        running.push(self.trigger(task));
                     }
                 }
+                Some(triggered) = running.next(), if !running.is_empty() => {
+                    let (task, produced) = self.finish_trigger(triggered).await;
+                    produced_packets |= produced;
+                    if Instant::now() < run_until
+                        && !is_back_pressured(&self.peers_tx)
+                        && !self.cancellation_token.is_cancelled()
+                    {
+                        running.push(self.trigger(task));
+                    } else {
+                        tasks.push(task);
+                    }
+                    if running.is_empty() {
+                        next_trigger = self.next_trigger(produced_packets);
+                        tracing::info!("{}: Processed {} packets.", self.processor_name, self.packets_processed_count);
+                    }
+                }
+                _ = sleep(BACK_PRESSURE_CHECK_INTERVAL),
+                    if self.status == ProcessorStatus::Running && is_back_pressured(&self.peers_tx) => {}
             }
         }
     }
@@ -112,9 +334,19 @@ impl InMemorySourceProcessor {
 
 #[cfg(test)]
 mod tests {
+    use std::{collections::HashMap, time::Duration};
+
     use super::*;
-    use crate::adder_func;
-    use tokio::sync::{mpsc, oneshot};
+    use crate::processor_functions::adder::Adder;
+    use crate::processors::{
+        connection::{BackPressure, Inbox},
+        models::{relationships, Message},
+        scheduling::{SchedulingSettings, SchedulingStrategy},
+    };
+    use tokio::{
+        sync::{mpsc, oneshot},
+        time::sleep,
+    };
 
     #[tokio::test]
     async fn test_in_memory_source_processor() {
@@ -123,11 +355,16 @@ mod tests {
         let mut processor = InMemorySourceProcessor::new(
             "test_processor".to_string(),
             parent_rx,
-            HashMap::new(),
+            PeersTx::new(),
+            crate::processor_registry().default_properties("adder"),
+            Schedule::default_for(ProcessorType::SourceProcessor),
+            ContentOffloader::disabled(),
+            BulletinBoard::default(),
+            ProvenanceRepository::default(),
             cancellation_token.clone(),
         );
         tokio::spawn(async move {
-            processor.run(adder_func).await;
+            processor.run(|| Box::new(Adder::default())).await;
         });
         let (oneshot_tx, oneshot_rx) = oneshot::channel();
         let command = ProcessorCommand::Start { resp: oneshot_tx };
@@ -142,7 +379,7 @@ mod tests {
         let command = ProcessorCommand::GetInfo { resp: oneshot_tx };
         parent_tx.send(command).await.unwrap();
         let processor_info = oneshot_rx.await.unwrap();
-        println!("{:?}", processor_info);
+        tracing::debug!("{:?}", processor_info);
         //assert_eq!(processor_info.packets_processed_count, 1);
 
         let (oneshot_tx, oneshot_rx) = oneshot::channel();
@@ -150,7 +387,217 @@ mod tests {
         sleep(Duration::from_secs(2)).await;
         parent_tx.send(command).await.unwrap();
         let processor_info = oneshot_rx.await.unwrap();
-        println!("{:?}", processor_info);
+        tracing::debug!("{:?}", processor_info);
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_core_attributes_are_added() {
+        let (parent_tx, parent_rx) = mpsc::channel(5);
+        let peer_inbox = Inbox::default();
+        let queue = ConnectionQueue::new(BackPressure::default(), &peer_inbox).unwrap();
+        let cancellation_token = CancellationToken::new();
+        let mut processor = InMemorySourceProcessor::new(
+            "test_source".to_string(),
+            parent_rx,
+            PeersTx::from([(
+                relationships::SUCCESS.to_string(),
+                HashMap::from([(Uuid::new_v4(), queue.clone())]),
+            )]),
+            crate::processor_registry().default_properties("adder"),
+            Schedule::default_for(ProcessorType::SourceProcessor),
+            ContentOffloader::disabled(),
+            BulletinBoard::default(),
+            ProvenanceRepository::default(),
+            cancellation_token.clone(),
+        );
+        tokio::spawn(async move {
+            processor.run(|| Box::new(Adder::default())).await;
+        });
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::Start { resp: oneshot_tx })
+            .await
+            .unwrap();
+        oneshot_rx.await.unwrap();
+
+        let Message::InMemoryMessage(packet) = peer_inbox.recv().await else {
+            panic!("Expected InMemoryMessage");
+        };
+        assert_eq!(
+            packet.attributes[attributes::FILENAME],
+            packet.id.to_string()
+        );
+        assert_eq!(packet.attributes[attributes::SOURCE], "test_source");
+        assert!(packet.attributes[attributes::CREATED_AT]
+            .parse::<u128>()
+            .is_ok());
+        // Attributes set by the processor are kept.
+        assert_eq!(
+            packet.attributes[attributes::MIME_TYPE],
+            "application/octet-stream"
+        );
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_tasks() {
+        let (parent_tx, parent_rx) = mpsc::channel(5);
+        let queue = ConnectionQueue::new(BackPressure::default(), &Inbox::default()).unwrap();
+        let cancellation_token = CancellationToken::new();
+        let settings = SchedulingSettings {
+            strategy: SchedulingStrategy::Timer,
+            run_schedule: Some("1 sec".to_string()),
+            concurrent_tasks: 3,
+            run_duration: "0 ms".to_string(),
+        };
+        let mut processor = InMemorySourceProcessor::new(
+            "test_concurrent_source".to_string(),
+            parent_rx,
+            PeersTx::from([(
+                relationships::SUCCESS.to_string(),
+                HashMap::from([(Uuid::new_v4(), queue.clone())]),
+            )]),
+            crate::processor_registry().default_properties("adder"),
+            Schedule::new(settings, ProcessorType::SourceProcessor).unwrap(),
+            ContentOffloader::disabled(),
+            BulletinBoard::default(),
+            ProvenanceRepository::default(),
+            cancellation_token.clone(),
+        );
+        tokio::spawn(async move {
+            processor.run(|| Box::new(Adder::default())).await;
+        });
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::Start { resp: oneshot_tx })
+            .await
+            .unwrap();
+        oneshot_rx.await.unwrap();
+
+        // Every task is triggered once after a second.
+        sleep(Duration::from_millis(1500)).await;
+        assert_eq!(queue.len(), 3);
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_full_connection_stops_triggers() {
+        let (parent_tx, parent_rx) = mpsc::channel(5);
+        let queue = ConnectionQueue::new(
+            BackPressure {
+                object_threshold: 5,
+                data_size_threshold: "1 KB".to_string(),
+            },
+            &Inbox::default(),
+        )
+        .unwrap();
+        let cancellation_token = CancellationToken::new();
+        let settings = SchedulingSettings {
+            strategy: SchedulingStrategy::Event,
+            run_schedule: None,
+            concurrent_tasks: 1,
+            run_duration: "0 ms".to_string(),
+        };
+        let mut processor = InMemorySourceProcessor::new(
+            "test_back_pressured_source".to_string(),
+            parent_rx,
+            PeersTx::from([(
+                relationships::SUCCESS.to_string(),
+                HashMap::from([(Uuid::new_v4(), queue.clone())]),
+            )]),
+            crate::processor_registry().default_properties("adder"),
+            Schedule::new(settings, ProcessorType::SourceProcessor).unwrap(),
+            ContentOffloader::disabled(),
+            BulletinBoard::default(),
+            ProvenanceRepository::default(),
+            cancellation_token.clone(),
+        );
+        tokio::spawn(async move {
+            processor.run(|| Box::new(Adder::default())).await;
+        });
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::Start { resp: oneshot_tx })
+            .await
+            .unwrap();
+        oneshot_rx.await.unwrap();
+
+        sleep(Duration::from_millis(200)).await;
+        assert_eq!(queue.len(), 5);
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::GetInfo { resp: oneshot_tx })
+            .await
+            .unwrap();
+        let processor_info = oneshot_rx.await.unwrap();
+        assert!(processor_info.back_pressured);
+        assert_eq!(processor_info.packets_processed_count, 5);
+        assert!(processor_info.connections[0].is_full);
+
+        cancellation_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_run_duration_is_interrupted() {
+        let (parent_tx, parent_rx) = mpsc::channel(5);
+        let queue = ConnectionQueue::new(
+            BackPressure {
+                object_threshold: 5,
+                data_size_threshold: "1 KB".to_string(),
+            },
+            &Inbox::default(),
+        )
+        .unwrap();
+        let cancellation_token = CancellationToken::new();
+        let settings = SchedulingSettings {
+            strategy: SchedulingStrategy::Event,
+            run_schedule: None,
+            concurrent_tasks: 1,
+            run_duration: "2 sec".to_string(),
+        };
+        let mut processor = InMemorySourceProcessor::new(
+            "test_interrupted_source".to_string(),
+            parent_rx,
+            PeersTx::from([(
+                relationships::SUCCESS.to_string(),
+                HashMap::from([(Uuid::new_v4(), queue.clone())]),
+            )]),
+            crate::processor_registry().default_properties("adder"),
+            Schedule::new(settings, ProcessorType::SourceProcessor).unwrap(),
+            ContentOffloader::disabled(),
+            BulletinBoard::default(),
+            ProvenanceRepository::default(),
+            cancellation_token.clone(),
+        );
+        tokio::spawn(async move {
+            processor.run(|| Box::new(Adder::default())).await;
+        });
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::Start { resp: oneshot_tx })
+            .await
+            .unwrap();
+        oneshot_rx.await.unwrap();
+
+        // Packets are routed while the run lasts, which ends once the connection is full.
+        sleep(Duration::from_millis(200)).await;
+        assert_eq!(queue.len(), 5);
+
+        // Commands are answered without waiting for the run duration.
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::Stop { resp: oneshot_tx })
+            .await
+            .unwrap();
+        let status = tokio::time::timeout(Duration::from_millis(500), oneshot_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(status, ProcessorStatus::Stopped);
 
         cancellation_token.cancel();
     }
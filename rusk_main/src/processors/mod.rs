@@ -1,4 +1,13 @@
 pub mod base_processor;
+pub mod bulletins;
+pub mod connection;
+pub mod content_offloader;
 pub mod in_memory_processor;
 pub mod in_memory_source_processor;
 pub mod models;
+pub mod processor;
+pub mod properties;
+pub mod provenance;
+pub mod registry;
+pub mod scheduling;
+pub mod supervisor;
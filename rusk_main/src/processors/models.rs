@@ -1,31 +1,27 @@
+use std::{collections::HashMap, sync::Arc};
+
+use bytes::{Bytes, BytesMut};
 use tokio::sync::oneshot;
 use uuid::Uuid;
 
-use crate::handlers::models::ProcessorInfo;
+use super::connection::ConnectionQueue;
+use super::properties::{Properties, PropertyError};
+use super::scheduling::{SchedulingError, SchedulingSettings};
+use crate::handlers::models::{PacketInfo, ProcessorInfo};
 
-#[derive(Copy, Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Copy, Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum ProcessorStatus {
     Running,
     Stopped,
     Errored,
 }
 
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum ProcessorType {
     SourceProcessor,
     Other,
 }
 
-impl PartialEq for ProcessorStatus {
-    fn eq(&self, other: &Self) -> bool {
-        matches!(
-            (self, other),
-            (ProcessorStatus::Running, ProcessorStatus::Running)
-                | (ProcessorStatus::Stopped, ProcessorStatus::Stopped)
-        )
-    }
-}
-
 pub type Responder<T> = oneshot::Sender<T>;
 
 #[derive(Debug)]
@@ -37,11 +33,15 @@ pub enum ProcessorCommand {
         resp: Responder<ProcessorStatus>,
     },
     Connect {
+        relationship: String,
         destination_processor_id: Uuid,
-        destination_processor_tx: tokio::sync::mpsc::Sender<Message>,
+        queue: Arc<ConnectionQueue>,
         resp: Responder<ProcessorStatus>,
     },
+    /// Disconnects the destination from the relationship, or from every relationship when
+    /// none is given.
     Disconnect {
+        relationship: Option<String>,
         destination_processor_id: Uuid,
         resp: Responder<ProcessorStatus>,
     },
@@ -51,6 +51,29 @@ pub enum ProcessorCommand {
     GetInfo {
         resp: Responder<ProcessorInfo>,
     },
+    /// Sets the given properties, which is only allowed while the processor is stopped.
+    /// Replies with all properties of the processor.
+    UpdateProperties {
+        properties: Properties,
+        resp: Responder<Result<Properties, UpdatePropertiesError>>,
+    },
+    /// Replaces the scheduling settings, which is only allowed while the processor is stopped.
+    UpdateScheduling {
+        scheduling: SchedulingSettings,
+        resp: Responder<Result<SchedulingSettings, UpdateSchedulingError>>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum UpdatePropertiesError {
+    ProcessorRunning,
+    InvalidProperties(Vec<PropertyError>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum UpdateSchedulingError {
+    ProcessorRunning,
+    InvalidScheduling(SchedulingError),
 }
 
 #[derive(Clone, Debug)]
@@ -59,16 +82,155 @@ pub enum Message {
     ReferenceMessage(ReferencePacket),
 }
 
+impl Message {
+    /// Size of the packet data, whether it is held in memory or in the content repository.
+    pub fn size_bytes(&self) -> u64 {
+        match self {
+            Message::InMemoryMessage(packet) => packet.data.len() as u64,
+            Message::ReferenceMessage(packet) => packet.length,
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        match self {
+            Message::InMemoryMessage(packet) => packet.id,
+            Message::ReferenceMessage(packet) => packet.id,
+        }
+    }
+
+    pub fn attributes(&self) -> &Attributes {
+        match self {
+            Message::InMemoryMessage(packet) => &packet.attributes,
+            Message::ReferenceMessage(packet) => &packet.attributes,
+        }
+    }
+
+    pub fn attributes_mut(&mut self) -> &mut Attributes {
+        match self {
+            Message::InMemoryMessage(packet) => &mut packet.attributes,
+            Message::ReferenceMessage(packet) => &mut packet.attributes,
+        }
+    }
+}
+
+impl From<InMemoryPacket> for Message {
+    fn from(packet: InMemoryPacket) -> Self {
+        Message::InMemoryMessage(packet)
+    }
+}
+
+/// Queues of the connections of each relationship of a processor, by destination processor.
+pub type PeersTx = HashMap<String, HashMap<Uuid, Arc<ConnectionQueue>>>;
+
+/// Names of the relationships every processor can route packets to. Processors may define
+/// relationships of their own as well.
+pub mod relationships {
+    pub const SUCCESS: &str = "success";
+    pub const FAILURE: &str = "failure";
+    /// Packet a processor received, when it emits new packets derived from it.
+    pub const ORIGINAL: &str = "original";
+}
+
+/// Packet emitted by a processor to one of its relationships.
+#[derive(Debug)]
+pub struct RoutedPacket {
+    pub relationship: String,
+    pub packet: Message,
+}
+
+impl RoutedPacket {
+    pub fn new(relationship: &str, packet: Message) -> Self {
+        RoutedPacket {
+            relationship: relationship.to_string(),
+            packet,
+        }
+    }
+
+    pub fn failure(packet: Message) -> Self {
+        RoutedPacket::new(relationships::FAILURE, packet)
+    }
+}
+
+/// String key/value metadata of a packet, e.g. its file name or mime type.
+pub type Attributes = HashMap<String, String>;
+
+/// Keys of the attributes which have a common meaning for all processors.
+pub mod attributes {
+    pub const FILENAME: &str = "filename";
+    pub const MIME_TYPE: &str = "mime.type";
+    /// Name of the processor which created the packet.
+    pub const SOURCE: &str = "source";
+    /// Time the packet was created at, in milliseconds since the Unix epoch.
+    pub const CREATED_AT: &str = "created.at";
+}
+
+/// Packet with its data held in memory. Data is immutable and reference counted, so cloning a
+/// packet, e.g. to send it to several processors, does not copy the data.
 #[derive(Clone, Debug)]
 pub struct InMemoryPacket {
     pub id: Uuid,
-    pub data: Vec<u8>,
+    pub attributes: Attributes,
+    pub data: Bytes,
+}
+
+impl InMemoryPacket {
+    /// Takes the data for modification. The data is only copied if it is shared with other
+    /// packets.
+    pub fn into_data_mut(self) -> BytesMut {
+        BytesMut::from(self.data)
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct ReferencePacket {
     pub id: Uuid,
+    pub attributes: Attributes,
     pub file_name: String,
     pub offset: u64,
     pub length: u64,
 }
+
+impl From<&InMemoryPacket> for PacketInfo {
+    fn from(packet: &InMemoryPacket) -> Self {
+        PacketInfo {
+            packet_id: packet.id.to_string(),
+            size_bytes: packet.data.len() as u64,
+            attributes: packet.attributes.clone(),
+        }
+    }
+}
+
+impl From<&Message> for PacketInfo {
+    fn from(message: &Message) -> Self {
+        PacketInfo {
+            packet_id: message.id().to_string(),
+            size_bytes: message.size_bytes(),
+            attributes: message.attributes().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_data_is_copied_only_when_shared() {
+        let packet = InMemoryPacket {
+            id: Uuid::new_v4(),
+            attributes: Attributes::new(),
+            data: Bytes::from(vec![1, 2, 3]),
+        };
+        let data_ptr = packet.data.as_ptr();
+        let shared_packet = packet.clone();
+        assert_eq!(shared_packet.data.as_ptr(), data_ptr);
+
+        let mut data = packet.into_data_mut();
+        data[0] = 4;
+        assert_ne!(data.as_ptr(), data_ptr);
+        assert_eq!(&shared_packet.data[..], &[1, 2, 3]);
+
+        let data = shared_packet.into_data_mut();
+        assert_eq!(data.as_ptr(), data_ptr);
+    }
+}
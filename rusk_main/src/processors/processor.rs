@@ -0,0 +1,599 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use content_repository_client::ContentReference;
+use uuid::Uuid;
+
+use super::{
+    bulletins::{BulletinBoard, Severity},
+    content_offloader::{content_reference, ContentOffloader},
+    models::{Attributes, InMemoryPacket, Message, RoutedPacket},
+    properties::Properties,
+    provenance::{ProvenanceEvent, ProvenanceEventType, ProvenanceRepository},
+};
+
+/// Logic of a kind of processor, run by `InMemorySourceProcessor` or `InMemoryProcessor`.
+///
+/// `on_scheduled` is called when the processor is started and `on_stopped` when it is stopped
+/// or shut down. In between, `on_trigger` is called with a new session every time there is work
+/// to do: for every received packet, or periodically for source processors.
+#[async_trait]
+pub trait Processor: Send {
+    async fn on_scheduled(&mut self, _context: &ProcessContext) -> Result<(), ProcessorError> {
+        Ok(())
+    }
+
+    async fn on_trigger(
+        &mut self,
+        context: &ProcessContext,
+        session: &mut ProcessSession,
+    ) -> Result<(), ProcessorError>;
+
+    async fn on_stopped(&mut self, _context: &ProcessContext) {}
+}
+
+/// Creates an instance of a processor. Runners create one instance for every concurrent task.
+pub type NewProcessor = fn() -> Box<dyn Processor>;
+
+/// Schedules every instance of a processor. When one fails, the instances which are already
+/// scheduled are stopped again.
+pub(crate) async fn schedule_all(
+    processors: &mut [Box<dyn Processor>],
+    context: &ProcessContext,
+) -> Result<(), ProcessorError> {
+    for i in 0..processors.len() {
+        if let Err(e) = processors[i].on_scheduled(context).await {
+            stop_all(&mut processors[..i], context).await;
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn stop_all(processors: &mut [Box<dyn Processor>], context: &ProcessContext) {
+    for processor in processors.iter_mut() {
+        processor.on_stopped(context).await;
+    }
+}
+
+/// What a processor knows about itself while it runs.
+#[derive(Clone, Debug)]
+pub struct ProcessContext {
+    pub processor_id: Uuid,
+    pub processor_name: String,
+    pub properties: Properties,
+    pub bulletin_board: BulletinBoard,
+    pub provenance: ProvenanceRepository,
+}
+
+impl ProcessContext {
+    /// Value of a property. Properties are validated before the processor is scheduled, so
+    /// this only returns `None` for optional properties which are not set.
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.properties.get(name).map(String::as_str)
+    }
+
+    /// Logs the warning and publishes it as a bulletin of the processor.
+    pub fn warn(&self, message: String) {
+        tracing::warn!("{}: {}", self.processor_name, message);
+        self.publish(Severity::Warning, message);
+    }
+
+    /// Logs the error and publishes it as a bulletin of the processor.
+    pub fn error(&self, message: String) {
+        tracing::error!("{}: {}", self.processor_name, message);
+        self.publish(Severity::Error, message);
+    }
+
+    fn publish(&self, severity: Severity, message: String) {
+        self.bulletin_board
+            .publish(self.processor_id, &self.processor_name, severity, message);
+    }
+}
+
+#[derive(Debug)]
+pub struct ProcessorError(pub String);
+
+impl fmt::Display for ProcessorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ProcessorError {}
+
+/// Packets a processor works on during one trigger. Every packet taken with `get` has to be
+/// either transferred to a relationship or removed.
+///
+/// Packets offloaded to the content repository are handed out by reference, and their data is
+/// only loaded when the processor `read`s it. Packets transferred by reference are sent on
+/// with the claim the processor holds on their content, without storing it again.
+///
+/// Nothing is sent until the session is committed after `on_trigger` returns. When
+/// `on_trigger` fails, the packets it produced are dropped and its input packets are routed
+/// to failure as they were received.
+#[derive(Debug)]
+pub struct ProcessSession {
+    content_offloader: ContentOffloader,
+    input: Vec<Message>,
+    /// Unchanged copies of the input packets, routed to failure when the trigger fails.
+    received: Vec<Message>,
+    /// Ids of the packets taken with `get` which are not transferred or removed yet.
+    pending: HashSet<Uuid>,
+    output: Vec<RoutedPacket>,
+    removed: Vec<Message>,
+}
+
+impl ProcessSession {
+    pub(crate) fn new(input: Vec<Message>, content_offloader: ContentOffloader) -> Self {
+        ProcessSession {
+            content_offloader,
+            received: input.clone(),
+            // Packets are handed out in the order they were received.
+            input: input.into_iter().rev().collect(),
+            pending: HashSet::new(),
+            output: vec![],
+            removed: vec![],
+        }
+    }
+
+    /// Takes the next input packet.
+    pub fn get(&mut self) -> Option<Message> {
+        let packet = self.input.pop()?;
+        self.pending.insert(packet.id());
+        Some(packet)
+    }
+
+    /// Data of the packet, loaded from the content repository if the packet was offloaded.
+    pub async fn read(&self, packet: &Message) -> Result<Bytes, ProcessorError> {
+        self.content_offloader.read(packet).await.map_err(|e| {
+            ProcessorError(format!(
+                "Failed to load content of packet {}: {}",
+                packet.id(),
+                e
+            ))
+        })
+    }
+
+    /// Creates a new packet with the data and no attributes. Use `create_child` for packets
+    /// derived from a received one.
+    pub fn create(&self, data: Bytes) -> InMemoryPacket {
+        InMemoryPacket {
+            id: Uuid::new_v4(),
+            attributes: Attributes::new(),
+            data,
+        }
+    }
+
+    /// Creates a new packet with the data and a copy of the attributes of its parent.
+    pub fn create_child(&self, parent: &Message, data: Bytes) -> InMemoryPacket {
+        InMemoryPacket {
+            attributes: parent.attributes().clone(),
+            ..self.create(data)
+        }
+    }
+
+    pub fn transfer(&mut self, packet: impl Into<Message>, relationship: &str) {
+        let packet = packet.into();
+        self.pending.remove(&packet.id());
+        self.output.push(RoutedPacket::new(relationship, packet));
+    }
+
+    /// Drops a packet without routing it anywhere.
+    pub fn remove(&mut self, packet: impl Into<Message>) {
+        let packet = packet.into();
+        self.pending.remove(&packet.id());
+        self.removed.push(packet);
+    }
+
+    /// Returns the packets to route, given the result of the trigger, and records what
+    /// happened to every packet of the session.
+    pub(crate) async fn commit(
+        self,
+        context: &ProcessContext,
+        result: Result<(), ProcessorError>,
+    ) -> Vec<RoutedPacket> {
+        let routed_packets = match result {
+            Ok(()) => {
+                self.record_output(context);
+                self.output
+            }
+            Err(e) => {
+                context.error(format!("Error processing packets: {}", e));
+                self.received
+                    .iter()
+                    .map(|packet| {
+                        let routed_packet = RoutedPacket::failure(packet.clone());
+                        record_route(context, &routed_packet);
+                        routed_packet
+                    })
+                    .collect()
+            }
+        };
+        pass_on_claims(
+            context,
+            &self.content_offloader,
+            self.received,
+            routed_packets,
+        )
+        .await
+    }
+
+    fn record_output(&self, context: &ProcessContext) {
+        let received: HashMap<Uuid, &Message> = self
+            .received
+            .iter()
+            .map(|packet| (packet.id(), packet))
+            .collect();
+        let parent_ids: Vec<Uuid> = self.received.iter().map(Message::id).collect();
+        for routed_packet in self.output.iter() {
+            let packet = &routed_packet.packet;
+            let event = match received.get(&packet.id()) {
+                Some(original) if !same_content(original, packet) => Some(
+                    ProvenanceEvent::of_message(
+                        ProvenanceEventType::Modify,
+                        context.processor_id,
+                        packet,
+                    )
+                    .with_details("Content modified".to_string()),
+                ),
+                Some(original) if original.attributes() != packet.attributes() => Some(
+                    ProvenanceEvent::of_message(
+                        ProvenanceEventType::Modify,
+                        context.processor_id,
+                        packet,
+                    )
+                    .with_details("Attributes modified".to_string()),
+                ),
+                Some(_) => None,
+                None if parent_ids.is_empty() => Some(ProvenanceEvent::of_message(
+                    ProvenanceEventType::Create,
+                    context.processor_id,
+                    packet,
+                )),
+                None => Some(
+                    ProvenanceEvent::of_message(
+                        ProvenanceEventType::Fork,
+                        context.processor_id,
+                        packet,
+                    )
+                    .with_parents(&parent_ids),
+                ),
+            };
+            if let Some(event) = event {
+                context.provenance.record(event);
+            }
+            record_route(context, routed_packet);
+        }
+        for packet in self.removed.iter() {
+            context.provenance.record(
+                ProvenanceEvent::of_message(
+                    ProvenanceEventType::Drop,
+                    context.processor_id,
+                    packet,
+                )
+                .with_details("Removed by the processor".to_string()),
+            );
+        }
+
+        if !self.pending.is_empty() {
+            context.error(format!(
+                "Packets {:?} were neither transferred nor removed, dropping them",
+                self.pending
+            ));
+        }
+        if !self.input.is_empty() {
+            context.warn(format!(
+                "{} packets were not processed, dropping them",
+                self.input.len()
+            ));
+        }
+        for packet in self
+            .received
+            .iter()
+            .filter(|packet| self.pending.contains(&packet.id()))
+            .chain(self.input.iter())
+        {
+            context.provenance.record(
+                ProvenanceEvent::of_message(
+                    ProvenanceEventType::Drop,
+                    context.processor_id,
+                    packet,
+                )
+                .with_details("Not processed".to_string()),
+            );
+        }
+    }
+}
+
+/// Passes the claim the session holds on the content of every packet it received by reference
+/// on to the first packet routed with that content. Packets routed with the same content again
+/// are copied, and claims which are not passed on are released.
+async fn pass_on_claims(
+    context: &ProcessContext,
+    content_offloader: &ContentOffloader,
+    received: Vec<Message>,
+    routed_packets: Vec<RoutedPacket>,
+) -> Vec<RoutedPacket> {
+    let mut claims: HashMap<ContentReference, usize> = HashMap::new();
+    for packet in received.iter() {
+        if let Message::ReferenceMessage(reference_packet) = packet {
+            *claims
+                .entry(content_reference(reference_packet))
+                .or_default() += 1;
+        }
+    }
+    let mut claimed_packets = Vec::with_capacity(routed_packets.len());
+    for mut routed_packet in routed_packets {
+        let Message::ReferenceMessage(reference_packet) = &routed_packet.packet else {
+            claimed_packets.push(routed_packet);
+            continue;
+        };
+        match claims.get_mut(&content_reference(reference_packet)) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => match content_offloader.copy(&routed_packet.packet).await {
+                Ok(copy) => routed_packet.packet = copy,
+                Err(e) => {
+                    context.error(format!(
+                        "Failed to copy packet {}, dropping it: {}",
+                        routed_packet.packet.id(),
+                        e
+                    ));
+                    context.provenance.record(
+                        ProvenanceEvent::of_message(
+                            ProvenanceEventType::Drop,
+                            context.processor_id,
+                            &routed_packet.packet,
+                        )
+                        .with_relationship(&routed_packet.relationship)
+                        .with_details(format!("Failed to copy: {}", e)),
+                    );
+                    continue;
+                }
+            },
+        }
+        claimed_packets.push(routed_packet);
+    }
+    for packet in received {
+        if let Message::ReferenceMessage(reference_packet) = &packet {
+            if let Some(count) = claims.get_mut(&content_reference(reference_packet)) {
+                if *count > 0 {
+                    *count -= 1;
+                    content_offloader.discard(packet).await;
+                }
+            }
+        }
+    }
+    claimed_packets
+}
+
+/// Whether both packets have the same data, without loading the data of offloaded packets.
+fn same_content(packet: &Message, other: &Message) -> bool {
+    match (packet, other) {
+        (Message::InMemoryMessage(packet), Message::InMemoryMessage(other)) => {
+            packet.data == other.data
+        }
+        (Message::ReferenceMessage(packet), Message::ReferenceMessage(other)) => {
+            content_reference(packet) == content_reference(other)
+        }
+        _ => false,
+    }
+}
+fn record_route(context: &ProcessContext, routed_packet: &RoutedPacket) {
+    context.provenance.record(
+        ProvenanceEvent::of_message(
+            ProvenanceEventType::Route,
+            context.processor_id,
+            &routed_packet.packet,
+        )
+        .with_relationship(&routed_packet.relationship),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::{
+        content_offloader,
+        models::{relationships, ReferencePacket},
+    };
+    use content_repository_client::ContentRepositoryClient;
+
+    fn context() -> ProcessContext {
+        ProcessContext {
+            processor_id: Uuid::new_v4(),
+            processor_name: "test".to_string(),
+            properties: Properties::new(),
+            bulletin_board: BulletinBoard::default(),
+            provenance: ProvenanceRepository::default(),
+        }
+    }
+
+    fn packet(data: &'static [u8]) -> InMemoryPacket {
+        InMemoryPacket {
+            id: Uuid::new_v4(),
+            attributes: Attributes::new(),
+            data: Bytes::from_static(data),
+        }
+    }
+
+    fn session(input: Vec<InMemoryPacket>) -> ProcessSession {
+        ProcessSession::new(
+            input.into_iter().map(Message::from).collect(),
+            ContentOffloader::disabled(),
+        )
+    }
+
+    fn data(packet: &Message) -> Vec<u8> {
+        match packet {
+            Message::InMemoryMessage(packet) => packet.data.to_vec(),
+            other => panic!("Expected InMemoryMessage, got {:?}", other),
+        }
+    }
+
+    fn in_memory(packet: Message) -> InMemoryPacket {
+        match packet {
+            Message::InMemoryMessage(packet) => packet,
+            other => panic!("Expected InMemoryMessage, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session() {
+        let mut session = session(vec![packet(&[1]), packet(&[2])]);
+        let first = session.get().unwrap();
+        assert_eq!(session.read(&first).await.unwrap(), vec![1]);
+        let created = session.create(Bytes::from_static(&[3]));
+        session.transfer(created, relationships::SUCCESS);
+        session.transfer(first, relationships::ORIGINAL);
+        let second = session.get().unwrap();
+        session.remove(second);
+        assert!(session.get().is_none());
+
+        let routed: Vec<(String, Vec<u8>)> = session
+            .commit(&context(), Ok(()))
+            .await
+            .into_iter()
+            .map(|routed| (routed.relationship, data(&routed.packet)))
+            .collect();
+        assert_eq!(
+            routed,
+            vec![
+                (relationships::SUCCESS.to_string(), vec![3]),
+                (relationships::ORIGINAL.to_string(), vec![1]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_children_inherit_attributes() {
+        let mut parent = packet(&[1]);
+        parent
+            .attributes
+            .insert("filename".to_string(), "parent.bin".to_string());
+        let mut session = session(vec![parent]);
+        let parent = session.get().unwrap();
+
+        let created = session.create(Bytes::from_static(&[2]));
+        assert!(created.attributes.is_empty());
+
+        let child = session.create_child(&parent, Bytes::from_static(&[3]));
+        assert_ne!(child.id, parent.id());
+        assert_eq!(&child.attributes, parent.attributes());
+        assert_eq!(child.data, Bytes::from_static(&[3]));
+    }
+
+    #[tokio::test]
+    async fn test_failed_session_routes_input_to_failure() {
+        let mut session = session(vec![packet(&[1])]);
+        let mut received = in_memory(session.get().unwrap());
+        received.data = Bytes::from_static(&[2]);
+        session.transfer(received, relationships::SUCCESS);
+
+        let context = context();
+        let routed = session
+            .commit(&context, Err(ProcessorError("broken".to_string())))
+            .await;
+        assert_eq!(routed.len(), 1);
+        assert_eq!(routed[0].relationship, relationships::FAILURE);
+        assert_eq!(data(&routed[0].packet), vec![1]);
+        let bulletins = context.bulletin_board.bulletins(None, None);
+        assert_eq!(bulletins.len(), 1);
+        assert_eq!(bulletins[0].severity, Severity::Error);
+        assert_eq!(bulletins[0].message, "Error processing packets: broken");
+    }
+
+    #[tokio::test]
+    async fn test_session_records_provenance() {
+        let (first, second) = (packet(&[1]), packet(&[2]));
+        let (first_id, second_id) = (first.id, second.id);
+        let mut session = session(vec![first, second]);
+        let mut modified = in_memory(session.get().unwrap());
+        modified.data = Bytes::from_static(&[4]);
+        let created = session.create(Bytes::from_static(&[3]));
+        let created_id = created.id;
+        session.transfer(created, relationships::SUCCESS);
+        session.transfer(modified, relationships::SUCCESS);
+        let removed = session.get().unwrap();
+        session.remove(removed);
+
+        let context = context();
+        session.commit(&context, Ok(())).await;
+        let event_types = |packet_id: Uuid| -> Vec<ProvenanceEventType> {
+            context
+                .provenance
+                .events(&packet_id.to_string())
+                .into_iter()
+                .map(|event| event.event_type)
+                .collect()
+        };
+        assert_eq!(
+            event_types(created_id),
+            vec![ProvenanceEventType::Fork, ProvenanceEventType::Route]
+        );
+        assert_eq!(
+            event_types(first_id),
+            vec![ProvenanceEventType::Modify, ProvenanceEventType::Route]
+        );
+        assert_eq!(event_types(second_id), vec![ProvenanceEventType::Drop]);
+        let fork = &context.provenance.events(&created_id.to_string())[0];
+        assert_eq!(
+            fork.parent_packet_ids,
+            vec![first_id.to_string(), second_id.to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unread_packets_are_routed_by_reference() {
+        let (address, store) = content_offloader::tests::spawn_content_repository().await;
+        let client = ContentRepositoryClient::new(content_offloader::tests::client_config(address));
+        let mut received = vec![];
+        for data in [&[1, 2, 3][..], &[4, 5, 6]] {
+            let reference = client.put(Bytes::from_static(data)).await.unwrap();
+            received.push(Message::ReferenceMessage(ReferencePacket {
+                id: Uuid::new_v4(),
+                attributes: Attributes::new(),
+                file_name: reference.segment,
+                offset: reference.offset,
+                length: reference.length,
+            }));
+        }
+        let references: Vec<ContentReference> = received
+            .iter()
+            .map(|packet| match packet {
+                Message::ReferenceMessage(reference_packet) => content_reference(reference_packet),
+                other => panic!("Expected ReferenceMessage, got {:?}", other),
+            })
+            .collect();
+        let mut session = ProcessSession::new(received, ContentOffloader::new(client, Some(0)));
+        let transferred = session.get().unwrap();
+        let copy = transferred.clone();
+        session.transfer(transferred, relationships::SUCCESS);
+        session.transfer(copy, relationships::ORIGINAL);
+        let removed = session.get().unwrap();
+        session.remove(removed);
+
+        let context = context();
+        let routed = session.commit(&context, Ok(())).await;
+        assert_eq!(routed.len(), 2);
+        for routed_packet in routed.iter() {
+            match &routed_packet.packet {
+                Message::ReferenceMessage(reference_packet) => {
+                    assert_eq!(content_reference(reference_packet), references[0])
+                }
+                other => panic!("Expected ReferenceMessage, got {:?}", other),
+            }
+        }
+        // Content is not stored again. Both routed packets hold a claim on the content of the
+        // transferred packet, and the content of the removed one is released.
+        let store = store.lock().unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store[&references[0]].1, 2);
+        assert_eq!(store[&references[1]].1, 0);
+        assert!(context.bulletin_board.bulletins(None, None).is_empty());
+    }
+}
@@ -0,0 +1,319 @@
+use std::{collections::HashMap, fmt, time::Duration};
+
+use regex::Regex;
+
+/// Property values of a processor, by property name.
+pub type Properties = HashMap<String, String>;
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PropertyDescriptor {
+    pub name: String,
+    pub description: String,
+    pub required: bool,
+    pub default_value: Option<String>,
+    /// Values the property is limited to. Any value of the property type is allowed when empty.
+    pub allowed_values: Vec<String>,
+    pub property_type: PropertyType,
+}
+
+/// Type of a property value, which every value is validated against.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PropertyType {
+    String {
+        pattern: Option<String>,
+    },
+    Integer {
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    Boolean,
+    /// Duration like `500 ms`, `5 sec` or `1 min`.
+    Duration,
+    /// Data size like `512 B`, `64 KB` or `1 GB`.
+    DataSize,
+}
+
+/// Invalid value, or missing value, of a property.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PropertyError {
+    pub property: String,
+    pub message: String,
+}
+
+impl fmt::Display for PropertyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.property, self.message)
+    }
+}
+
+impl PropertyDescriptor {
+    pub fn validate(&self, value: &str) -> Result<(), String> {
+        if !self.allowed_values.is_empty() && !self.allowed_values.iter().any(|v| v == value) {
+            return Err(format!(
+                "'{}' is not one of {}",
+                value,
+                self.allowed_values.join(", ")
+            ));
+        }
+        match &self.property_type {
+            PropertyType::String { pattern: None } => Ok(()),
+            PropertyType::String {
+                pattern: Some(pattern),
+            } => {
+                let regex = Regex::new(pattern)
+                    .map_err(|e| format!("Invalid pattern {}: {}", pattern, e))?;
+                if regex.is_match(value) {
+                    Ok(())
+                } else {
+                    Err(format!("'{}' does not match {}", value, pattern))
+                }
+            }
+            PropertyType::Integer { min, max } => {
+                let integer = value
+                    .parse::<i64>()
+                    .map_err(|_| format!("'{}' is not an integer", value))?;
+                if min.is_some_and(|min| integer < min) || max.is_some_and(|max| integer > max) {
+                    return Err(format!(
+                        "{} is not between {} and {}",
+                        integer,
+                        min.map_or("-".to_string(), |min| min.to_string()),
+                        max.map_or("-".to_string(), |max| max.to_string())
+                    ));
+                }
+                Ok(())
+            }
+            PropertyType::Boolean => value
+                .parse::<bool>()
+                .map(|_| ())
+                .map_err(|_| format!("'{}' is not true or false", value)),
+            PropertyType::Duration => parse_duration(value).map(|_| ()),
+            PropertyType::DataSize => parse_data_size(value).map(|_| ()),
+        }
+    }
+}
+
+/// Validates the properties against the descriptors and fills in the defaults of the
+/// properties which are not set. Every invalid property is reported.
+pub fn resolve_properties(
+    descriptors: &[PropertyDescriptor],
+    properties: &Properties,
+) -> Result<Properties, Vec<PropertyError>> {
+    let mut errors = vec![];
+    for name in properties.keys() {
+        if !descriptors
+            .iter()
+            .any(|descriptor| &descriptor.name == name)
+        {
+            errors.push(PropertyError {
+                property: name.clone(),
+                message: "Unknown property".to_string(),
+            });
+        }
+    }
+
+    let mut resolved_properties = Properties::new();
+    for descriptor in descriptors {
+        let value = properties
+            .get(&descriptor.name)
+            .or(descriptor.default_value.as_ref());
+        match value {
+            Some(value) => match descriptor.validate(value) {
+                Ok(()) => {
+                    resolved_properties.insert(descriptor.name.clone(), value.clone());
+                }
+                Err(message) => errors.push(PropertyError {
+                    property: descriptor.name.clone(),
+                    message,
+                }),
+            },
+            None if descriptor.required => errors.push(PropertyError {
+                property: descriptor.name.clone(),
+                message: "Required property is not set".to_string(),
+            }),
+            None => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(resolved_properties)
+    } else {
+        errors.sort_by(|a, b| a.property.cmp(&b.property));
+        Err(errors)
+    }
+}
+
+/// Properties of a processor, kept valid against its property descriptors.
+#[derive(Clone, Debug)]
+pub struct ProcessorProperties {
+    descriptors: Vec<PropertyDescriptor>,
+    values: Properties,
+}
+
+impl ProcessorProperties {
+    pub fn new(
+        descriptors: Vec<PropertyDescriptor>,
+        properties: &Properties,
+    ) -> Result<Self, Vec<PropertyError>> {
+        let values = resolve_properties(&descriptors, properties)?;
+        Ok(ProcessorProperties {
+            descriptors,
+            values,
+        })
+    }
+
+    pub fn values(&self) -> &Properties {
+        &self.values
+    }
+
+    /// Sets the given properties, keeping the others as they are. Nothing is changed when any
+    /// of the resulting properties is invalid.
+    pub fn update(&mut self, properties: &Properties) -> Result<(), Vec<PropertyError>> {
+        let mut values = self.values.clone();
+        values.extend(properties.clone());
+        self.values = resolve_properties(&self.descriptors, &values)?;
+        Ok(())
+    }
+}
+
+/// Splits a value like `5 sec` into its number and unit.
+fn split_unit(value: &str) -> Result<(u64, &str), String> {
+    let value = value.trim();
+    let unit_start = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let number = value[..unit_start]
+        .parse::<u64>()
+        .map_err(|_| format!("'{}' does not start with a number", value))?;
+    Ok((number, value[unit_start..].trim()))
+}
+
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = split_unit(value)?;
+    let millis_per_unit = match unit.to_lowercase().as_str() {
+        "ms" | "millis" => 1,
+        "s" | "sec" | "secs" => 1000,
+        "m" | "min" | "mins" => 60 * 1000,
+        "h" | "hr" | "hrs" => 60 * 60 * 1000,
+        _ => {
+            return Err(format!(
+                "'{}' does not have a unit of ms, sec, min or hr",
+                value
+            ))
+        }
+    };
+    number
+        .checked_mul(millis_per_unit)
+        .map(Duration::from_millis)
+        .ok_or_else(|| format!("'{}' is too long", value))
+}
+
+pub fn parse_data_size(value: &str) -> Result<u64, String> {
+    let (number, unit) = split_unit(value)?;
+    let exponent = match unit.to_uppercase().as_str() {
+        "B" => 0,
+        "KB" => 1,
+        "MB" => 2,
+        "GB" => 3,
+        "TB" => 4,
+        _ => {
+            return Err(format!(
+                "'{}' does not have a unit of B, KB, MB, GB or TB",
+                value
+            ))
+        }
+    };
+    number
+        .checked_mul(1024u64.pow(exponent))
+        .ok_or_else(|| format!("'{}' is too large", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor(name: &str, property_type: PropertyType) -> PropertyDescriptor {
+        PropertyDescriptor {
+            name: name.to_string(),
+            description: String::new(),
+            required: false,
+            default_value: None,
+            allowed_values: vec![],
+            property_type,
+        }
+    }
+
+    #[test]
+    fn test_property_types() {
+        let pattern = descriptor(
+            "pattern",
+            PropertyType::String {
+                pattern: Some("^[a-z]+$".to_string()),
+            },
+        );
+        assert!(pattern.validate("abc").is_ok());
+        assert!(pattern.validate("ABC").is_err());
+
+        let integer = descriptor(
+            "integer",
+            PropertyType::Integer {
+                min: Some(1),
+                max: Some(10),
+            },
+        );
+        assert!(integer.validate("10").is_ok());
+        assert!(integer.validate("11").is_err());
+        assert!(integer.validate("ten").is_err());
+
+        assert_eq!(parse_duration("500 ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2min"), Ok(Duration::from_secs(120)));
+        assert!(parse_duration("2 days").is_err());
+        assert_eq!(parse_data_size("64 KB"), Ok(64 * 1024));
+        assert!(parse_data_size("KB").is_err());
+
+        let allowed = PropertyDescriptor {
+            allowed_values: vec!["fail".to_string(), "wrap".to_string()],
+            ..descriptor("allowed", PropertyType::String { pattern: None })
+        };
+        assert!(allowed.validate("wrap").is_ok());
+        assert!(allowed.validate("ignore").is_err());
+    }
+
+    #[test]
+    fn test_resolve_properties() {
+        let descriptors = vec![
+            PropertyDescriptor {
+                required: true,
+                ..descriptor(
+                    "batch_size",
+                    PropertyType::Integer {
+                        min: Some(1),
+                        max: None,
+                    },
+                )
+            },
+            PropertyDescriptor {
+                default_value: Some("1 sec".to_string()),
+                ..descriptor("interval", PropertyType::Duration)
+            },
+        ];
+
+        let properties = Properties::from([("batch_size".to_string(), "5".to_string())]);
+        assert_eq!(
+            resolve_properties(&descriptors, &properties),
+            Ok(Properties::from([
+                ("batch_size".to_string(), "5".to_string()),
+                ("interval".to_string(), "1 sec".to_string()),
+            ]))
+        );
+
+        let properties = Properties::from([
+            ("interval".to_string(), "soon".to_string()),
+            ("colour".to_string(), "red".to_string()),
+        ]);
+        let errors = resolve_properties(&descriptors, &properties).unwrap_err();
+        let invalid_properties: Vec<&str> =
+            errors.iter().map(|error| error.property.as_str()).collect();
+        assert_eq!(invalid_properties, vec!["batch_size", "colour", "interval"]);
+    }
+}
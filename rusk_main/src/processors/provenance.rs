@@ -0,0 +1,508 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{
+    io::{AsyncWriteExt, BufWriter},
+    sync::mpsc,
+};
+use uuid::Uuid;
+
+use super::{
+    models::{Attributes, InMemoryPacket, Message},
+    processor::ProcessContext,
+};
+
+/// Most events the repository keeps in memory to answer queries. Older events are only in the
+/// log, until it is rotated away.
+pub const MAX_PROVENANCE_EVENTS: usize = 100_000;
+/// Size at which the log is rotated to `{path}.1`, after shifting older logs to `{path}.2` and
+/// so on.
+pub const MAX_LOG_FILE_BYTES: u64 = 64 * 1024 * 1024;
+/// Rotated logs which are kept next to the log. The oldest one is deleted on rotation.
+pub const ROTATED_LOG_FILES: usize = 4;
+/// Events waiting to be written to the log. Events recorded while it is full are not persisted.
+const LOG_CHANNEL_CAPACITY: usize = 10_000;
+
+/// What happened to a packet.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ProvenanceEventType {
+    /// Created by a source processor.
+    Create,
+    /// Taken from a connection by a processor.
+    Receive,
+    /// Transferred to a relationship.
+    Route,
+    /// Content or attributes changed by a processor.
+    Modify,
+    /// Created by a processor from the packets it received, its parents.
+    Fork,
+    /// Dropped without being sent anywhere.
+    Drop,
+    /// Queued on the connections of a relationship.
+    Send,
+}
+
+/// Where the content of an offloaded packet is stored in the content repository.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ContentLocation {
+    pub file_name: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ProvenanceEvent {
+    /// Increases with every event, also across restarts.
+    pub event_id: u64,
+    pub event_type: ProvenanceEventType,
+    pub packet_id: String,
+    pub processor_id: String,
+    /// Time of the event, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Attributes of the packet at the time of the event.
+    pub attributes: Attributes,
+    pub size_bytes: u64,
+    /// Content of the packet in the content repository, when it was offloaded.
+    #[serde(default)]
+    pub content_location: Option<ContentLocation>,
+    #[serde(default)]
+    pub relationship: Option<String>,
+    /// Packets a forked packet was created from.
+    #[serde(default)]
+    pub parent_packet_ids: Vec<String>,
+    #[serde(default)]
+    pub details: Option<String>,
+}
+
+impl ProvenanceEvent {
+    fn new(
+        event_type: ProvenanceEventType,
+        processor_id: Uuid,
+        packet_id: Uuid,
+        attributes: &Attributes,
+        size_bytes: u64,
+    ) -> Self {
+        ProvenanceEvent {
+            event_id: 0,
+            event_type,
+            packet_id: packet_id.to_string(),
+            processor_id: processor_id.to_string(),
+            timestamp: 0,
+            attributes: attributes.clone(),
+            size_bytes,
+            content_location: None,
+            relationship: None,
+            parent_packet_ids: vec![],
+            details: None,
+        }
+    }
+
+    /// Event of the processor about a packet it works on.
+    pub fn of_packet(
+        event_type: ProvenanceEventType,
+        context: &ProcessContext,
+        packet: &InMemoryPacket,
+    ) -> Self {
+        ProvenanceEvent::new(
+            event_type,
+            context.processor_id,
+            packet.id,
+            &packet.attributes,
+            packet.data.len() as u64,
+        )
+    }
+
+    /// Event of the processor about a packet on one of its connections.
+    pub fn of_message(
+        event_type: ProvenanceEventType,
+        processor_id: Uuid,
+        message: &Message,
+    ) -> Self {
+        let mut event = ProvenanceEvent::new(
+            event_type,
+            processor_id,
+            message.id(),
+            message.attributes(),
+            message.size_bytes(),
+        );
+        if let Message::ReferenceMessage(reference_packet) = message {
+            event.content_location = Some(ContentLocation {
+                file_name: reference_packet.file_name.clone(),
+                offset: reference_packet.offset,
+                length: reference_packet.length,
+            });
+        }
+        event
+    }
+
+    pub fn with_relationship(mut self, relationship: &str) -> Self {
+        self.relationship = Some(relationship.to_string());
+        self
+    }
+
+    pub fn with_parents(mut self, parent_packet_ids: &[Uuid]) -> Self {
+        self.parent_packet_ids = parent_packet_ids.iter().map(Uuid::to_string).collect();
+        self
+    }
+
+    pub fn with_details(mut self, details: String) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+/// Event which followed another one in the lineage of a packet.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LineageLink {
+    pub from_event_id: u64,
+    pub to_event_id: u64,
+}
+
+/// Events of a packet, of the packets it was forked from and of the packets forked from it.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Lineage {
+    pub packet_ids: Vec<String>,
+    /// Ordered by event id.
+    pub events: Vec<ProvenanceEvent>,
+    pub links: Vec<LineageLink>,
+    /// Whether some packets of the lineage are only known from events after their creation,
+    /// because older events are no longer held in memory.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Default)]
+struct RepositoryState {
+    events: VecDeque<ProvenanceEvent>,
+    next_event_id: u64,
+}
+
+/// Records provenance events and answers queries about the latest `MAX_PROVENANCE_EVENTS` of
+/// them. With a log file, every event is appended to it as a line of JSON, the log is rotated
+/// once it reaches `MAX_LOG_FILE_BYTES`, and the latest events are read back from the logs on
+/// startup.
+#[derive(Clone, Debug, Default)]
+pub struct ProvenanceRepository {
+    state: Arc<Mutex<RepositoryState>>,
+    log_tx: Option<mpsc::Sender<ProvenanceEvent>>,
+}
+
+impl ProvenanceRepository {
+    /// Opens the log at the path, creating it if it does not exist yet, and starts appending
+    /// events to it.
+    pub fn open(path: &str) -> io::Result<Self> {
+        ProvenanceRepository::open_with_max_log_bytes(path, MAX_LOG_FILE_BYTES)
+    }
+
+    fn open_with_max_log_bytes(path: &str, max_log_bytes: u64) -> io::Result<Self> {
+        let log_path = PathBuf::from(path);
+        if let Some(parent) = log_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut state = RepositoryState::default();
+        for index in (0..=ROTATED_LOG_FILES).rev() {
+            read_log(&rotated_log_path(&log_path, index), &mut state)?;
+        }
+        tracing::info!(
+            "Read {} provenance events from {}",
+            state.events.len(),
+            path
+        );
+
+        let log_file = LogFile::open(log_path, max_log_bytes)?;
+        let (log_tx, log_rx) = mpsc::channel(LOG_CHANNEL_CAPACITY);
+        tokio::spawn(write_log(log_file, log_rx));
+        Ok(ProvenanceRepository {
+            state: Arc::new(Mutex::new(state)),
+            log_tx: Some(log_tx),
+        })
+    }
+
+    pub fn record(&self, mut event: ProvenanceEvent) {
+        event.timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut state = self.state.lock().unwrap();
+        event.event_id = state.next_event_id;
+        state.next_event_id += 1;
+        if let Some(log_tx) = &self.log_tx {
+            if let Err(e) = log_tx.try_send(event.clone()) {
+                tracing::error!(
+                    "Provenance event {} is not persisted, the log is {}",
+                    event.event_id,
+                    match e {
+                        mpsc::error::TrySendError::Full(_) => "falling behind",
+                        mpsc::error::TrySendError::Closed(_) => "closed",
+                    }
+                );
+            }
+        }
+        push_bounded(&mut state.events, event);
+    }
+
+    /// Events of the packet, the oldest first.
+    pub fn events(&self, packet_id: &str) -> Vec<ProvenanceEvent> {
+        self.state
+            .lock()
+            .unwrap()
+            .events
+            .iter()
+            .filter(|event| event.packet_id == packet_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Lineage of the packet, or `None` when there are no events of it.
+    pub fn lineage(&self, packet_id: &str) -> Option<Lineage> {
+        let state = self.state.lock().unwrap();
+        if !state
+            .events
+            .iter()
+            .any(|event| event.packet_id == packet_id)
+        {
+            return None;
+        }
+        // Packets related to the packet through forks, in either direction.
+        let mut packet_ids = vec![packet_id.to_string()];
+        let mut related: HashSet<String> = HashSet::from([packet_id.to_string()]);
+        let mut unvisited = vec![packet_id.to_string()];
+        while let Some(id) = unvisited.pop() {
+            for event in state.events.iter() {
+                let next_ids = if event.packet_id == id {
+                    event.parent_packet_ids.clone()
+                } else if event.parent_packet_ids.contains(&id) {
+                    vec![event.packet_id.clone()]
+                } else {
+                    continue;
+                };
+                for next_id in next_ids {
+                    if related.insert(next_id.clone()) {
+                        packet_ids.push(next_id.clone());
+                        unvisited.push(next_id);
+                    }
+                }
+            }
+        }
+
+        let events: Vec<ProvenanceEvent> = state
+            .events
+            .iter()
+            .filter(|event| related.contains(&event.packet_id))
+            .cloned()
+            .collect();
+        let mut links = vec![];
+        let mut last_events: HashMap<&str, u64> = HashMap::new();
+        for event in events.iter() {
+            if let Some(from_event_id) = last_events.get(event.packet_id.as_str()) {
+                links.push(LineageLink {
+                    from_event_id: *from_event_id,
+                    to_event_id: event.event_id,
+                });
+            }
+            for parent_packet_id in event.parent_packet_ids.iter() {
+                if let Some(from_event_id) = last_events.get(parent_packet_id.as_str()) {
+                    links.push(LineageLink {
+                        from_event_id: *from_event_id,
+                        to_event_id: event.event_id,
+                    });
+                }
+            }
+            last_events.insert(&event.packet_id, event.event_id);
+        }
+        // Every packet starts with a CREATE or FORK event, unless that event was dropped.
+        let oldest_event_id = state.events.front().map_or(0, |event| event.event_id);
+        let truncated = oldest_event_id > 0
+            && packet_ids.iter().any(|packet_id| {
+                events
+                    .iter()
+                    .find(|event| &event.packet_id == packet_id)
+                    .is_none_or(|event| {
+                        !matches!(
+                            event.event_type,
+                            ProvenanceEventType::Create | ProvenanceEventType::Fork
+                        )
+                    })
+            });
+        Some(Lineage {
+            packet_ids,
+            events,
+            links,
+            truncated,
+        })
+    }
+}
+
+fn push_bounded(events: &mut VecDeque<ProvenanceEvent>, event: ProvenanceEvent) {
+    if events.len() == MAX_PROVENANCE_EVENTS {
+        events.pop_front();
+    }
+    events.push_back(event);
+}
+
+/// Path of the log, or of its rotated log with the index.
+fn rotated_log_path(log_path: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        return log_path.to_path_buf();
+    }
+    let mut path = log_path.as_os_str().to_owned();
+    path.push(format!(".{}", index));
+    PathBuf::from(path)
+}
+
+/// Adds the events of the log, if it exists, to the state.
+fn read_log(path: &Path, state: &mut RepositoryState) -> io::Result<()> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for line in io::BufReader::new(file).lines() {
+        match serde_json::from_str::<ProvenanceEvent>(&line?) {
+            Ok(event) => {
+                state.next_event_id = event.event_id + 1;
+                push_bounded(&mut state.events, event);
+            }
+            Err(e) => tracing::warn!("Skipping invalid provenance event: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Log which events are appended to, rotated once it reaches `max_bytes`.
+struct LogFile {
+    path: PathBuf,
+    max_bytes: u64,
+    writer: BufWriter<tokio::fs::File>,
+    length: u64,
+}
+
+impl LogFile {
+    fn open(path: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        let length = file.metadata()?.len();
+        Ok(LogFile {
+            path,
+            max_bytes,
+            writer: BufWriter::new(tokio::fs::File::from_std(file)),
+            length,
+        })
+    }
+
+    async fn append(&mut self, event: &ProvenanceEvent) -> io::Result<()> {
+        let mut line = serde_json::to_vec(event).expect("Provenance events are serializable");
+        line.push(b'\n');
+        self.writer.write_all(&line).await?;
+        self.length += line.len() as u64;
+        if self.length >= self.max_bytes {
+            self.rotate().await?;
+        }
+        Ok(())
+    }
+
+    /// Moves the log to `{path}.1`, shifting the rotated logs, and starts a new log.
+    async fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush().await?;
+        for index in (1..=ROTATED_LOG_FILES).rev() {
+            let from_path = rotated_log_path(&self.path, index - 1);
+            if from_path.exists() {
+                tokio::fs::rename(&from_path, rotated_log_path(&self.path, index)).await?;
+            }
+        }
+        *self = LogFile::open(self.path.clone(), self.max_bytes)?;
+        tracing::info!("Rotated provenance log {:?}", self.path);
+        Ok(())
+    }
+}
+
+/// Appends events to the log, flushing whenever no more events are waiting.
+async fn write_log(mut log_file: LogFile, mut log_rx: mpsc::Receiver<ProvenanceEvent>) {
+    while let Some(event) = log_rx.recv().await {
+        let mut next_event = Some(event);
+        while let Some(event) = next_event {
+            if let Err(e) = log_file.append(&event).await {
+                tracing::error!("Failed to write provenance event {}: {}", event.event_id, e);
+            }
+            next_event = log_rx.try_recv().ok();
+        }
+        if let Err(e) = log_file.writer.flush().await {
+            tracing::error!("Failed to flush provenance log: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn event(event_type: ProvenanceEventType, packet_id: Uuid) -> ProvenanceEvent {
+        ProvenanceEvent::new(event_type, Uuid::new_v4(), packet_id, &Attributes::new(), 1)
+    }
+
+    #[tokio::test]
+    async fn test_provenance_log_is_read_on_open() {
+        let path = std::env::temp_dir().join(format!("provenance-{}.log", Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        let packet_id = Uuid::new_v4();
+
+        let provenance = ProvenanceRepository::open(path).unwrap();
+        provenance.record(event(ProvenanceEventType::Create, packet_id));
+        provenance.record(event(ProvenanceEventType::Send, packet_id));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let reopened = ProvenanceRepository::open(path).unwrap();
+        assert_eq!(
+            reopened.events(&packet_id.to_string()),
+            provenance.events(&packet_id.to_string())
+        );
+        reopened.record(event(ProvenanceEventType::Drop, packet_id));
+        let events = reopened.events(&packet_id.to_string());
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[2].event_id, 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_provenance_log_is_rotated() {
+        let dir = std::env::temp_dir().join(format!("provenance-{}", Uuid::new_v4()));
+        let path = dir.join("provenance.log");
+        let path = path.to_str().unwrap();
+        let packet_id = Uuid::new_v4();
+
+        // Every event fills a log, so that every event is rotated.
+        let provenance = ProvenanceRepository::open_with_max_log_bytes(path, 1).unwrap();
+        for _ in 0..ROTATED_LOG_FILES + 2 {
+            provenance.record(event(ProvenanceEventType::Send, packet_id));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(
+            std::fs::read_dir(&dir).unwrap().count(),
+            ROTATED_LOG_FILES + 1
+        );
+
+        // Events of the oldest log were deleted.
+        let reopened = ProvenanceRepository::open(path).unwrap();
+        let event_ids: Vec<u64> = reopened
+            .events(&packet_id.to_string())
+            .into_iter()
+            .map(|event| event.event_id)
+            .collect();
+        assert_eq!(
+            event_ids,
+            (2..ROTATED_LOG_FILES as u64 + 2).collect::<Vec<_>>()
+        );
+        let lineage = reopened.lineage(&packet_id.to_string()).unwrap();
+        assert!(lineage.truncated);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use super::{
+    base_processor::{SinkProcessor, SourceProcessor},
+    bulletins::BulletinBoard,
+    connection::Inbox,
+    content_offloader::ContentOffloader,
+    in_memory_processor::InMemoryProcessor,
+    in_memory_source_processor::InMemorySourceProcessor,
+    models::{PeersTx, ProcessorCommand, ProcessorType},
+    processor::NewProcessor,
+    properties::{ProcessorProperties, PropertyDescriptor},
+    provenance::ProvenanceRepository,
+    scheduling::Schedule,
+    supervisor::{supervise, RestartPolicy},
+};
+
+/// Describes a kind of processor to the UI.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ProcessorDescriptor {
+    pub name: String,
+    pub description: String,
+    pub properties: Vec<PropertyDescriptor>,
+    /// Relationships the processor routes packets to.
+    pub relationships: Vec<String>,
+}
+
+/// Everything a factory needs to create a processor.
+pub struct ProcessorSetup {
+    pub processor_name: String,
+    pub parent_rx: mpsc::Receiver<ProcessorCommand>,
+    /// Sender of `parent_rx`, which the supervisor uses to restart the processor.
+    pub parent_tx: mpsc::Sender<ProcessorCommand>,
+    /// Queues of the connections from other processors. Not set for source processors.
+    pub inbox: Option<Inbox>,
+    pub properties: ProcessorProperties,
+    pub schedule: Schedule,
+    pub content_offloader: ContentOffloader,
+    pub bulletin_board: BulletinBoard,
+    pub provenance: ProvenanceRepository,
+    pub cancellation_token: CancellationToken,
+    /// Restarts of the processor after it panicked. It stays `Errored` when not set.
+    pub restart_policy: Option<RestartPolicy>,
+}
+
+/// Creates a processor, starts running it and returns its id.
+pub type ProcessorFactory = fn(ProcessorSetup) -> Uuid;
+
+#[derive(Debug)]
+pub struct RegisteredProcessor {
+    pub descriptor: ProcessorDescriptor,
+    pub processor_type: ProcessorType,
+    pub factory: ProcessorFactory,
+}
+
+/// Kind of processor as listed by `GET /processor/types`.
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ProcessorTypeInfo {
+    #[serde(flatten)]
+    pub descriptor: ProcessorDescriptor,
+    pub processor_type: ProcessorType,
+}
+
+/// Kinds of processors which can be created, by name.
+#[derive(Debug, Default)]
+pub struct ProcessorRegistry {
+    processors: BTreeMap<String, RegisteredProcessor>,
+}
+
+impl ProcessorRegistry {
+    pub fn register(
+        &mut self,
+        descriptor: ProcessorDescriptor,
+        processor_type: ProcessorType,
+        factory: ProcessorFactory,
+    ) {
+        let name = descriptor.name.clone();
+        let registered_processor = RegisteredProcessor {
+            descriptor,
+            processor_type,
+            factory,
+        };
+        if self
+            .processors
+            .insert(name.clone(), registered_processor)
+            .is_some()
+        {
+            panic!("Processor {} is registered more than once", name);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RegisteredProcessor> {
+        self.processors.get(name)
+    }
+
+    pub fn processor_types(&self) -> Vec<ProcessorTypeInfo> {
+        self.processors
+            .values()
+            .map(|registered_processor| ProcessorTypeInfo {
+                descriptor: registered_processor.descriptor.clone(),
+                processor_type: registered_processor.processor_type,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl ProcessorRegistry {
+    /// Properties of the kind of processor with all their default values.
+    pub fn default_properties(&self, name: &str) -> ProcessorProperties {
+        let descriptor = &self.get(name).unwrap().descriptor;
+        ProcessorProperties::new(descriptor.properties.clone(), &Default::default()).unwrap()
+    }
+}
+
+/// Runs the processor as an `InMemorySourceProcessor`, which triggers it on its schedule.
+pub fn spawn_source_processor(setup: ProcessorSetup, new_processor: NewProcessor) -> Uuid {
+    let runner = InMemorySourceProcessor::new(
+        setup.processor_name,
+        setup.parent_rx,
+        PeersTx::new(),
+        setup.properties,
+        setup.schedule,
+        setup.content_offloader,
+        setup.bulletin_board,
+        setup.provenance,
+        setup.cancellation_token.clone(),
+    );
+    let processor_id = runner.processor_id;
+    supervise(
+        processor_id,
+        runner,
+        new_processor,
+        setup.parent_tx,
+        setup.restart_policy,
+        setup.cancellation_token,
+    );
+    processor_id
+}
+
+/// Runs the processor as an `InMemoryProcessor`, which triggers it for every received packet.
+pub fn spawn_in_memory_processor(setup: ProcessorSetup, new_processor: NewProcessor) -> Uuid {
+    let runner = InMemoryProcessor::new(
+        setup.processor_name,
+        setup
+            .inbox
+            .expect("In memory processor needs an inbox for packets"),
+        setup.parent_rx,
+        setup.properties,
+        setup.schedule,
+        setup.content_offloader,
+        setup.bulletin_board,
+        setup.provenance,
+        setup.cancellation_token.clone(),
+    );
+    let processor_id = runner.processor_id;
+    supervise(
+        processor_id,
+        runner,
+        new_processor,
+        setup.parent_tx,
+        setup.restart_policy,
+        setup.cancellation_token,
+    );
+    processor_id
+}
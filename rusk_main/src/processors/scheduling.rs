@@ -0,0 +1,276 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use chrono::Utc;
+
+use super::{models::ProcessorType, properties::parse_duration};
+
+/// Most tasks a processor can run at the same time.
+pub const MAX_CONCURRENT_TASKS: u32 = 64;
+/// Longest time a task keeps triggering its processor before routing what it produced.
+pub const MAX_RUN_DURATION: Duration = Duration::from_secs(2);
+/// How long an event driven source processor waits after a trigger which produced nothing.
+const EVENT_DRIVEN_IDLE_DELAY: Duration = Duration::from_millis(10);
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum SchedulingStrategy {
+    /// Triggered every `run_schedule`, a duration like `100 ms`.
+    #[serde(rename = "timer_driven")]
+    Timer,
+    /// Triggered at the times of `run_schedule`, a cron expression with seconds like
+    /// `0 */5 * * * *`, in UTC.
+    #[serde(rename = "cron_driven")]
+    Cron,
+    /// Triggered whenever there is work to do: for every received packet, or for source
+    /// processors as soon as their connections take the packets of the previous trigger.
+    #[serde(rename = "event_driven")]
+    Event,
+}
+
+/// When and how often a processor is triggered, as set through the API.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SchedulingSettings {
+    pub strategy: SchedulingStrategy,
+    /// Interval of timer driven processors or cron expression of cron driven ones. Not set for
+    /// event driven processors.
+    #[serde(default)]
+    pub run_schedule: Option<String>,
+    /// Number of tasks which trigger the processor in parallel, each with its own instance of
+    /// the processor.
+    #[serde(default = "default_concurrent_tasks")]
+    pub concurrent_tasks: u32,
+    /// How long a task keeps triggering the processor, like `25 ms`, unless its connections
+    /// are back pressured. With `0 ms` the processor is triggered once.
+    #[serde(default = "default_run_duration")]
+    pub run_duration: String,
+}
+
+fn default_concurrent_tasks() -> u32 {
+    1
+}
+
+fn default_run_duration() -> String {
+    "0 ms".to_string()
+}
+
+impl SchedulingSettings {
+    /// Settings of new processors of the type: source processors are triggered every 100 ms
+    /// and other processors for every packet they receive.
+    pub fn default_for(processor_type: ProcessorType) -> Self {
+        let (strategy, run_schedule) = match processor_type {
+            ProcessorType::SourceProcessor => {
+                (SchedulingStrategy::Timer, Some("100 ms".to_string()))
+            }
+            ProcessorType::Other => (SchedulingStrategy::Event, None),
+        };
+        SchedulingSettings {
+            strategy,
+            run_schedule,
+            concurrent_tasks: default_concurrent_tasks(),
+            run_duration: default_run_duration(),
+        }
+    }
+}
+
+/// Invalid scheduling settings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchedulingError(pub String);
+
+impl fmt::Display for SchedulingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for SchedulingError {}
+
+#[derive(Clone, Debug)]
+enum Trigger {
+    Timer(Duration),
+    Cron(Box<cron::Schedule>),
+    Event,
+}
+
+/// Scheduling settings of a processor, validated for its processor type.
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    settings: SchedulingSettings,
+    trigger: Trigger,
+    run_duration: Duration,
+}
+
+impl Schedule {
+    /// Only source processors can be timer or cron driven, run concurrent tasks or run for a
+    /// duration. Other processors are triggered for every packet they receive, one at a time.
+    pub fn new(
+        settings: SchedulingSettings,
+        processor_type: ProcessorType,
+    ) -> Result<Self, SchedulingError> {
+        let error = |message: String| Err(SchedulingError(message));
+        let trigger = match (settings.strategy, settings.run_schedule.as_deref()) {
+            (SchedulingStrategy::Timer, Some(run_schedule)) => match parse_duration(run_schedule) {
+                Ok(interval) if interval.is_zero() => {
+                    return error("run_schedule must be longer than 0 ms".to_string())
+                }
+                Ok(interval) => Trigger::Timer(interval),
+                Err(message) => return error(format!("run_schedule: {}", message)),
+            },
+            (SchedulingStrategy::Cron, Some(run_schedule)) => {
+                match cron::Schedule::from_str(run_schedule) {
+                    Ok(schedule) => Trigger::Cron(Box::new(schedule)),
+                    Err(e) => {
+                        return error(format!(
+                            "run_schedule: '{}' is not a cron expression: {}",
+                            run_schedule, e
+                        ))
+                    }
+                }
+            }
+            (SchedulingStrategy::Event, None) => Trigger::Event,
+            (SchedulingStrategy::Event, Some(_)) => {
+                return error("run_schedule is not used by event driven processors".to_string())
+            }
+            (_, None) => {
+                return error(
+                    "run_schedule is required by timer and cron driven processors".to_string(),
+                )
+            }
+        };
+        if !(1..=MAX_CONCURRENT_TASKS).contains(&settings.concurrent_tasks) {
+            return error(format!(
+                "concurrent_tasks must be between 1 and {}",
+                MAX_CONCURRENT_TASKS
+            ));
+        }
+        let run_duration = match parse_duration(&settings.run_duration) {
+            Ok(run_duration) if run_duration > MAX_RUN_DURATION => {
+                return error(format!(
+                    "run_duration must not be longer than {} ms",
+                    MAX_RUN_DURATION.as_millis()
+                ))
+            }
+            Ok(run_duration) => run_duration,
+            Err(message) => return error(format!("run_duration: {}", message)),
+        };
+        if processor_type != ProcessorType::SourceProcessor
+            && (!matches!(trigger, Trigger::Event)
+                || settings.concurrent_tasks != 1
+                || !run_duration.is_zero())
+        {
+            return error(
+                "Only source processors can be timer or cron driven, run concurrent tasks \
+                or set a run duration"
+                    .to_string(),
+            );
+        }
+        Ok(Schedule {
+            settings,
+            trigger,
+            run_duration,
+        })
+    }
+
+    /// Schedule with the default settings of the processor type.
+    pub fn default_for(processor_type: ProcessorType) -> Self {
+        Schedule::new(
+            SchedulingSettings::default_for(processor_type),
+            processor_type,
+        )
+        .expect("Default scheduling settings are valid")
+    }
+
+    pub fn settings(&self) -> &SchedulingSettings {
+        &self.settings
+    }
+
+    pub fn concurrent_tasks(&self) -> usize {
+        self.settings.concurrent_tasks as usize
+    }
+
+    pub fn run_duration(&self) -> Duration {
+        self.run_duration
+    }
+
+    /// Time from now until the processor is triggered next, or `None` when it is never
+    /// triggered again, like for a cron expression of a past year. `produced_packets` tells
+    /// whether the last trigger produced any packets.
+    pub fn next_delay(&self, produced_packets: bool) -> Option<Duration> {
+        match &self.trigger {
+            Trigger::Timer(interval) => Some(*interval),
+            Trigger::Cron(schedule) => schedule
+                .upcoming(Utc)
+                .next()
+                .map(|next| (next - Utc::now()).to_std().unwrap_or_default()),
+            Trigger::Event if produced_packets => Some(Duration::ZERO),
+            Trigger::Event => Some(EVENT_DRIVEN_IDLE_DELAY),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(strategy: SchedulingStrategy, run_schedule: Option<&str>) -> SchedulingSettings {
+        SchedulingSettings {
+            strategy,
+            run_schedule: run_schedule.map(str::to_string),
+            concurrent_tasks: 1,
+            run_duration: "0 ms".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_schedule() {
+        let source = ProcessorType::SourceProcessor;
+        let timer =
+            Schedule::new(settings(SchedulingStrategy::Timer, Some("250 ms")), source).unwrap();
+        assert_eq!(timer.next_delay(true), Some(Duration::from_millis(250)));
+
+        let cron = Schedule::new(
+            settings(SchedulingStrategy::Cron, Some("*/2 * * * * *")),
+            source,
+        )
+        .unwrap();
+        assert!(cron.next_delay(true).unwrap() <= Duration::from_secs(2));
+
+        let event = Schedule::new(settings(SchedulingStrategy::Event, None), source).unwrap();
+        assert_eq!(event.next_delay(true), Some(Duration::ZERO));
+        assert_eq!(event.next_delay(false), Some(EVENT_DRIVEN_IDLE_DELAY));
+
+        for invalid in [
+            settings(SchedulingStrategy::Timer, None),
+            settings(SchedulingStrategy::Timer, Some("0 ms")),
+            settings(SchedulingStrategy::Cron, Some("every minute")),
+            settings(SchedulingStrategy::Event, Some("1 sec")),
+            SchedulingSettings {
+                concurrent_tasks: 0,
+                ..settings(SchedulingStrategy::Event, None)
+            },
+            SchedulingSettings {
+                run_duration: "1 min".to_string(),
+                ..settings(SchedulingStrategy::Event, None)
+            },
+        ] {
+            assert!(
+                Schedule::new(invalid.clone(), source).is_err(),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_only_source_processors_are_scheduled() {
+        let other = ProcessorType::Other;
+        assert!(Schedule::new(SchedulingSettings::default_for(other), other).is_ok());
+        assert!(Schedule::new(settings(SchedulingStrategy::Timer, Some("1 sec")), other).is_err());
+        assert!(Schedule::new(
+            SchedulingSettings {
+                concurrent_tasks: 4,
+                ..SchedulingSettings::default_for(other)
+            },
+            other
+        )
+        .is_err());
+    }
+}
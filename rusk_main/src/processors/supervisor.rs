@@ -0,0 +1,272 @@
+use std::{any::Any, sync::Arc, time::Duration};
+
+use tokio::{
+    sync::{mpsc, oneshot, Mutex},
+    task::JoinError,
+    time::{sleep, Instant},
+};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use super::{
+    models::{ProcessorCommand, ProcessorStatus},
+    processor::NewProcessor,
+};
+
+/// Longest time a panicked processor waits to be restarted. A processor which ran for longer
+/// than this since its last restart starts over with the initial backoff.
+pub const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Runs a processor as a task which the supervisor watches.
+#[async_trait::async_trait]
+pub trait SupervisedProcessor: Send + 'static {
+    /// Handles commands and triggers the processor until it is cancelled.
+    async fn run(&mut self, new_processor: NewProcessor);
+
+    /// Moves the processor to `Errored` after it panicked with the message.
+    fn panicked(&mut self, message: String);
+}
+
+/// Restarts of a panicked processor.
+#[derive(Clone, Copy, Debug)]
+pub struct RestartPolicy {
+    /// Wait before the first restart, which doubles with every further panic.
+    pub initial_backoff: Duration,
+}
+
+/// Runs the processor as a task and keeps its `JoinHandle`. A processor which panics is moved
+/// to `Errored` with the panic message and keeps answering commands. With a restart policy,
+/// it is started again after a backoff, unless it was stopped or deleted in the meantime.
+pub fn supervise<P: SupervisedProcessor>(
+    processor_id: Uuid,
+    runner: P,
+    new_processor: NewProcessor,
+    parent_tx: mpsc::Sender<ProcessorCommand>,
+    restart_policy: Option<RestartPolicy>,
+    cancellation_token: CancellationToken,
+) {
+    let runner = Arc::new(Mutex::new(runner));
+    tokio::spawn(async move {
+        let mut backoff = restart_policy.map(|policy| policy.initial_backoff);
+        loop {
+            let running_runner = runner.clone();
+            let run_started_at = Instant::now();
+            let handle = tokio::spawn(async move {
+                running_runner.lock().await.run(new_processor).await;
+            });
+            let message = match handle.await {
+                Ok(()) => break,
+                Err(e) if e.is_cancelled() => break,
+                Err(e) => panic_message(e),
+            };
+            runner.lock().await.panicked(message);
+
+            let (Some(policy), Some(delay)) = (restart_policy, backoff) else {
+                continue;
+            };
+            let delay = if run_started_at.elapsed() > MAX_RESTART_BACKOFF {
+                policy.initial_backoff
+            } else {
+                delay
+            };
+            backoff = Some((delay * 2).min(MAX_RESTART_BACKOFF));
+            tokio::spawn(restart(
+                processor_id,
+                delay,
+                parent_tx.clone(),
+                cancellation_token.clone(),
+            ));
+        }
+        tracing::info!("Processor {} is no longer supervised", processor_id);
+    });
+}
+
+/// Starts the processor after the delay if it is still `Errored`.
+async fn restart(
+    processor_id: Uuid,
+    delay: Duration,
+    parent_tx: mpsc::Sender<ProcessorCommand>,
+    cancellation_token: CancellationToken,
+) {
+    tokio::select! {
+        _ = sleep(delay) => {}
+        _ = cancellation_token.cancelled() => return,
+    }
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+    if parent_tx
+        .send(ProcessorCommand::GetStatus { resp: oneshot_tx })
+        .await
+        .is_err()
+        || oneshot_rx.await.ok() != Some(ProcessorStatus::Errored)
+    {
+        return;
+    }
+    tracing::info!(
+        "Restarting processor {} after {} ms",
+        processor_id,
+        delay.as_millis()
+    );
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+    if parent_tx
+        .send(ProcessorCommand::Start { resp: oneshot_tx })
+        .await
+        .is_ok()
+    {
+        // The processor may panic again before it answers.
+        let _ = oneshot_rx.await;
+    }
+}
+
+fn panic_message(error: JoinError) -> String {
+    let payload: Box<dyn Any + Send> = error.into_panic();
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Processor panicked".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::handlers::models::ProcessorInfo;
+    use crate::processors::{
+        base_processor::SinkProcessor,
+        bulletins::BulletinBoard,
+        connection::{BackPressure, ConnectionQueue, Inbox},
+        content_offloader::ContentOffloader,
+        in_memory_processor::InMemoryProcessor,
+        models::{relationships, Attributes, InMemoryPacket, Message, ProcessorType},
+        processor::{ProcessContext, ProcessSession, Processor, ProcessorError},
+        provenance::ProvenanceRepository,
+        scheduling::Schedule,
+    };
+
+    /// Passes packets on, but panics on packets starting with 0.
+    struct FragileProcessor;
+
+    #[async_trait::async_trait]
+    impl Processor for FragileProcessor {
+        async fn on_trigger(
+            &mut self,
+            _context: &ProcessContext,
+            session: &mut ProcessSession,
+        ) -> Result<(), ProcessorError> {
+            while let Some(packet) = session.get() {
+                if session.read(&packet).await?[0] == 0 {
+                    panic!("packet of death");
+                }
+                session.transfer(packet, relationships::SUCCESS);
+            }
+            Ok(())
+        }
+    }
+
+    /// Supervised processor with a queue to send it packets and an inbox of its successes.
+    async fn supervised_processor(
+        restart_policy: Option<RestartPolicy>,
+    ) -> (mpsc::Sender<ProcessorCommand>, Arc<ConnectionQueue>, Inbox) {
+        let (parent_tx, parent_rx) = mpsc::channel(10);
+        let inbox = Inbox::default();
+        let queue = ConnectionQueue::new(BackPressure::default(), &inbox).unwrap();
+        let cancellation_token = CancellationToken::new();
+        let runner = InMemoryProcessor::new(
+            "test_fragile_processor".to_string(),
+            inbox,
+            parent_rx,
+            crate::processor_registry().default_properties("doubler"),
+            Schedule::default_for(ProcessorType::Other),
+            ContentOffloader::disabled(),
+            BulletinBoard::default(),
+            ProvenanceRepository::default(),
+            cancellation_token.clone(),
+        );
+        supervise(
+            runner.processor_id,
+            runner,
+            || Box::new(FragileProcessor),
+            parent_tx.clone(),
+            restart_policy,
+            cancellation_token,
+        );
+
+        let sink_inbox = Inbox::default();
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::Connect {
+                relationship: relationships::SUCCESS.to_string(),
+                destination_processor_id: Uuid::new_v4(),
+                queue: ConnectionQueue::new(BackPressure::default(), &sink_inbox).unwrap(),
+                resp: oneshot_tx,
+            })
+            .await
+            .unwrap();
+        oneshot_rx.await.unwrap();
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::Start { resp: oneshot_tx })
+            .await
+            .unwrap();
+        oneshot_rx.await.unwrap();
+        (parent_tx, queue, sink_inbox)
+    }
+
+    fn message(data: u8) -> Message {
+        Message::InMemoryMessage(InMemoryPacket {
+            id: Uuid::new_v4(),
+            attributes: Attributes::new(),
+            data: Bytes::from(vec![data]),
+        })
+    }
+
+    async fn get_info(parent_tx: &mpsc::Sender<ProcessorCommand>) -> ProcessorInfo {
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        parent_tx
+            .send(ProcessorCommand::GetInfo { resp: oneshot_tx })
+            .await
+            .unwrap();
+        oneshot_rx.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_panicked_processor_is_errored() {
+        let (parent_tx, queue, sink_inbox) = supervised_processor(None).await;
+        queue.push(message(0));
+        queue.push(message(1));
+        sleep(Duration::from_millis(100)).await;
+
+        let processor_info = get_info(&parent_tx).await;
+        assert_eq!(processor_info.status, ProcessorStatus::Errored);
+        assert!(processor_info
+            .last_error
+            .unwrap()
+            .contains("packet of death"));
+        assert_eq!(queue.len(), 1);
+        assert!(sink_inbox.pop().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_panicked_processor_is_restarted() {
+        let restart_policy = RestartPolicy {
+            initial_backoff: Duration::from_millis(50),
+        };
+        let (parent_tx, queue, sink_inbox) = supervised_processor(Some(restart_policy)).await;
+        queue.push(message(0));
+        queue.push(message(1));
+
+        let Message::InMemoryMessage(packet) = sink_inbox.recv().await else {
+            panic!("Expected InMemoryMessage");
+        };
+        assert_eq!(packet.data, vec![1]);
+        let processor_info = get_info(&parent_tx).await;
+        assert_eq!(processor_info.status, ProcessorStatus::Running);
+        assert!(processor_info
+            .last_error
+            .unwrap()
+            .contains("packet of death"));
+    }
+}